@@ -1,12 +1,13 @@
 use std::{
     any::Any,
-    collections::VecDeque,
-    fmt::{format, Debug, Display},
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
 };
 
 use crate::tokens::{Divide, Minus, Multiply, Number, Plus, Power};
 
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 struct AST {
     tree: Box<Node>,
     depth: usize,
@@ -17,7 +18,7 @@ impl Display for AST {
         write!(f, "{}", self.tree)
     }
 }
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 struct Node {
     token: Token,
     depth: usize,
@@ -30,13 +31,9 @@ impl Display for Node {
         let mut output = "".to_string();
 
         output.push_str(
-            format!(
-                "{}",
-                self.token
-                    .get_operation()
-                    .unwrap_or(self.token.to_string().as_str())
-            )
-            .as_str(),
+            self.token
+                .get_operation()
+                .unwrap_or(self.token.to_string().as_str()),
         );
 
         if let Some(node) = &self.left {
@@ -81,6 +78,17 @@ impl PartialEq for Token {
     }
 }
 
+impl Eq for Token {}
+
+/// Tokens of the same type that are equal display the same, so the type and
+/// the text are enough.
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_any().type_id().hash(state);
+        self.0.to_string().hash(state);
+    }
+}
+
 pub(crate) trait AstToken: Any + Debug + Display {
     fn as_any(&self) -> &dyn Any;
     fn eq_dyn(&self, other: &dyn AstToken) -> bool;
@@ -95,7 +103,7 @@ impl AstToken for Number {
         other
             .as_any()
             .downcast_ref::<Self>()
-            .map_or(false, |a| self == a)
+            .is_some_and(|a| self == a)
     }
 }
 
@@ -108,7 +116,7 @@ impl AstToken for Plus {
         other
             .as_any()
             .downcast_ref::<Self>()
-            .map_or(false, |a| self == a)
+            .is_some_and(|a| self == a)
     }
 }
 
@@ -121,7 +129,7 @@ impl AstToken for Minus {
         other
             .as_any()
             .downcast_ref::<Self>()
-            .map_or(false, |a| self == a)
+            .is_some_and(|a| self == a)
     }
 }
 
@@ -134,7 +142,7 @@ impl AstToken for Multiply {
         other
             .as_any()
             .downcast_ref::<Self>()
-            .map_or(false, |a| self == a)
+            .is_some_and(|a| self == a)
     }
 }
 
@@ -147,7 +155,7 @@ impl AstToken for Divide {
         other
             .as_any()
             .downcast_ref::<Self>()
-            .map_or(false, |a| self == a)
+            .is_some_and(|a| self == a)
     }
 }
 
@@ -160,7 +168,7 @@ impl AstToken for Power {
         other
             .as_any()
             .downcast_ref::<Self>()
-            .map_or(false, |a| self == a)
+            .is_some_and(|a| self == a)
     }
 }
 
@@ -208,7 +216,7 @@ fn power(base: Node, expoent: Node) -> Node {
 
 fn number(n: f64) -> Node {
     Node {
-        token: Token(Box::new(Number(n))),
+        token: Token(Box::new(Number::from(n))),
         depth: 1,
         left: None,
         right: None,
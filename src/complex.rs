@@ -0,0 +1,413 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+use crate::{
+    evaluator::{arity_mismatch, unknown_function, EvalError, EvalResult, Evaluator},
+    numeric::Numeric,
+    tokens::Number,
+};
+
+/// A complex number `re + im·i` over `f64`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub const fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn from_polar(r: f64, theta: f64) -> Self {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    /// The modulus `|z|`.
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// The argument in `(-π, π]`.
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.re == 0.0 && self.im == 0.0
+    }
+
+    pub fn exp(self) -> Self {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    /// The principal natural logarithm.
+    pub fn ln(self) -> Self {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    /// The principal square root, so `sqrt(-1)` is `i`.
+    pub fn sqrt(self) -> Self {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// Integer exponents multiply exactly; anything else uses the principal
+    /// value `exp(w·ln z)`. Zero only has powers whose exponent is zero or
+    /// has a positive real part; any other would be infinite or undefined.
+    pub fn pow(self, exponent: Complex) -> EvalResult<Self> {
+        if self.is_zero() {
+            return if exponent.is_zero() {
+                Ok(Complex::new(1.0, 0.0))
+            } else if exponent.re > 0.0 {
+                Ok(Complex::default())
+            } else {
+                Err(EvalError::new(format!(
+                    "0 to the power of {} is undefined",
+                    exponent
+                )))
+            };
+        }
+        if exponent.im == 0.0 && exponent.re.fract() == 0.0 && exponent.re.abs() <= 1024.0 {
+            let power = self.powi(exponent.re.abs() as u32);
+            return Ok(if exponent.re < 0.0 {
+                Complex::new(1.0, 0.0) / power
+            } else {
+                power
+            });
+        }
+        Ok((exponent * self.ln()).exp())
+    }
+
+    fn powi(self, mut exponent: u32) -> Self {
+        let mut base = self;
+        let mut result = Complex::new(1.0, 0.0);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        result
+    }
+
+    pub fn sin(self) -> Self {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    pub fn cos(self) -> Self {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    pub fn tan(self) -> Self {
+        self.sin() / self.cos()
+    }
+
+    pub fn format(&self, format: ComplexFormat) -> String {
+        match format {
+            ComplexFormat::Rectangular => self.to_string(),
+            ComplexFormat::Polar => format!("{} ∠ {}", self.abs(), self.arg()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ComplexFormat {
+    /// `3 + 4i`
+    #[default]
+    Rectangular,
+    /// `5 ∠ 0.9272952180016122`, with the angle in radians.
+    Polar,
+}
+
+impl Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Complex { re, im } = *self;
+        if im == 0.0 {
+            write!(f, "{}", re)
+        } else if re == 0.0 {
+            write!(f, "{}i", im)
+        } else if im < 0.0 {
+            write!(f, "{} - {}i", re, -im)
+        } else {
+            write!(f, "{} + {}i", re, im)
+        }
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.re * other.re + other.im * other.im;
+        let numerator = self * other.conj();
+        Complex::new(numerator.re / denominator, numerator.im / denominator)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl Numeric for Complex {
+    type Context = ();
+
+    fn from_literal(literal: &Number) -> EvalResult<Self> {
+        Ok(Complex::from(literal.value()))
+    }
+
+    fn from_imaginary(literal: &Number) -> EvalResult<Self> {
+        Ok(Complex::new(0.0, literal.value()))
+    }
+
+    fn add(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(*self + *other)
+    }
+
+    fn sub(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(*self - *other)
+    }
+
+    fn mul(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(*self * *other)
+    }
+
+    fn div(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        if other.is_zero() {
+            return Err(EvalError::new("Division by zero"));
+        }
+        Ok(*self / *other)
+    }
+
+    fn pow(&self, exponent: &Self, _: &()) -> EvalResult<Self> {
+        Complex::pow(*self, *exponent)
+    }
+
+    // Subtracting from zero keeps `-1` on the positive side of the branch
+    // cut instead of giving it an imaginary part of `-0`.
+    fn neg(&self) -> EvalResult<Self> {
+        Ok(Complex::default() - *self)
+    }
+
+    fn compare(&self, _other: &Self) -> EvalResult<Option<Ordering>> {
+        Err(EvalError::new("Complex numbers are not ordered"))
+    }
+
+    fn to_index(&self) -> EvalResult<usize> {
+        if self.im != 0.0 || self.re < 0.0 || self.re.fract() != 0.0 {
+            return Err(EvalError::new(format!("Invalid index: {}", self)));
+        }
+        Ok(self.re as usize)
+    }
+
+    fn call(name: &str, args: &[Self]) -> EvalResult<Self> {
+        if name == "polar" {
+            return match args {
+                [r, theta] if r.im == 0.0 && theta.im == 0.0 => {
+                    Ok(Complex::from_polar(r.re, theta.re))
+                }
+                [_, _] => Err(EvalError::new("polar expects real arguments")),
+                _ => Err(arity_mismatch(name, 2, args.len())),
+            };
+        }
+
+        let function: fn(Complex) -> Complex = match name {
+            "sqrt" => Complex::sqrt,
+            "abs" => |z| Complex::from(z.abs()),
+            "arg" => |z| Complex::from(z.arg()),
+            "conj" => Complex::conj,
+            "re" => |z| Complex::from(z.re),
+            "im" => |z| Complex::from(z.im),
+            "exp" => Complex::exp,
+            "ln" => Complex::ln,
+            "sin" => Complex::sin,
+            "cos" => Complex::cos,
+            "tan" => Complex::tan,
+            _ => return Err(unknown_function(name)),
+        };
+        match args {
+            [z] => Ok(function(*z)),
+            _ => Err(arity_mismatch(name, 1, args.len())),
+        }
+    }
+
+    fn constant(name: &str) -> Option<Self> {
+        match name {
+            "i" => Some(Complex::I),
+            "pi" => Some(Complex::from(std::f64::consts::PI)),
+            "e" => Some(Complex::from(std::f64::consts::E)),
+            _ => None,
+        }
+    }
+}
+
+/// Evaluates an [`ASTNode`] over [`Complex`] numbers.
+///
+/// Literals such as `4i`, which the lexer reads with
+/// [`Lexer::imaginary`](crate::lexer::Lexer::imaginary), are imaginary and
+/// `i` on its own is the imaginary unit unless a variable shadows it.
+/// Functions use principal branches, so `sqrt(-1)` is `i` and `ln(-1)` is
+/// `πi`. The built-in functions are `sqrt`, `abs`, `arg`, `conj`, `re`,
+/// `im`, `exp`, `ln`, `sin`, `cos`, `tan` and `polar(r, θ)`. `<`, `//`
+/// and the other operators that need an order are rejected since complex
+/// numbers are not ordered; everything else works as with [`Evaluator`].
+///
+/// [`ASTNode`]: crate::parser::ASTNode
+pub type ComplexEvaluator = Evaluator<Complex>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluator::Value, lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn run(input: &str) -> AppResult<Value<Complex>> {
+        let (tokens, _) = Lexer::new(input).imaginary(true).tokenize()?;
+        let program = Parser::new(tokens).parse_program()?;
+        Ok(ComplexEvaluator::default().run(&program)?)
+    }
+
+    fn eval(input: &str) -> AppResult<Complex> {
+        match run(input)? {
+            Value::Number(z) => Ok(z),
+            value => panic!("Expected a number, got {}", value),
+        }
+    }
+
+    fn assert_close(actual: Complex, expected: Complex) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            eval("(3 + 4i) * (1 - 2i)").unwrap(),
+            Complex::new(11.0, -2.0)
+        );
+        assert_eq!(
+            eval("(3 + 4i) / (1 - 2i)").unwrap(),
+            Complex::new(-1.0, 2.0)
+        );
+        assert_eq!(eval("i * i").unwrap(), Complex::new(-1.0, 0.0));
+        assert_eq!(eval("(1 + i)^2").unwrap(), Complex::new(0.0, 2.0));
+        assert!(eval("1 / (0i)").is_err());
+        assert!(eval("1i < 2i").is_err());
+    }
+
+    #[test]
+    fn powers_of_zero() {
+        assert_eq!(eval("0^2").unwrap(), Complex::default());
+        assert_eq!(eval("0^(0.5 + i)").unwrap(), Complex::default());
+        assert_eq!(eval("0^0").unwrap(), Complex::from(1.0));
+        assert_eq!(
+            eval("0^(-0.5)").unwrap_err().to_string(),
+            "Error(Eval): 0 to the power of -0.5 is undefined"
+        );
+        assert!(eval("0^-1").is_err());
+        assert!(eval("0^i").is_err());
+    }
+
+    #[test]
+    fn functions_use_principal_branches() {
+        assert_eq!(eval("sqrt(-1)").unwrap(), Complex::I);
+        assert_eq!(eval("sqrt(-4)").unwrap(), Complex::new(0.0, 2.0));
+        assert!(eval("sqrt(-4 - 1i)").unwrap().im < 0.0);
+        assert_eq!(eval("abs(3 + 4i)").unwrap(), Complex::from(5.0));
+        assert_eq!(eval("conj(3 + 4i)").unwrap(), Complex::new(3.0, -4.0));
+        assert_eq!(eval("im(3 + 4i)").unwrap(), Complex::from(4.0));
+        assert_close(
+            eval("ln(-1)").unwrap(),
+            Complex::new(0.0, std::f64::consts::PI),
+        );
+        assert_close(eval("e^(i * pi)").unwrap(), Complex::from(-1.0));
+        assert_close(eval("polar(2, pi / 2)").unwrap(), Complex::new(0.0, 2.0));
+        assert!(eval("sqrt(1, 2)").is_err());
+    }
+
+    #[test]
+    fn programs_and_lists() {
+        assert_eq!(
+            eval("rotate(z) = z * i\nrotate(rotate(1 + 2i))").unwrap(),
+            Complex::new(-1.0, -2.0)
+        );
+        assert_eq!(run("map([1, i], z => z^2)").unwrap().to_string(), "[1, -1]");
+        assert_eq!(
+            eval("sum([1 + i, 2 - 3i])").unwrap(),
+            Complex::new(3.0, -2.0)
+        );
+        assert_eq!(run("i^2 == -1").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("i = 2\n3 * i").unwrap(), Complex::from(6.0));
+        assert!(eval("max([1, i])").is_err());
+    }
+
+    #[test]
+    fn output_formats() {
+        let z = eval("3 - 4i").unwrap();
+        assert_eq!(z.to_string(), "3 - 4i");
+        assert_eq!(Complex::new(0.0, 2.0).to_string(), "2i");
+        assert_eq!(Complex::from(-1.5).to_string(), "-1.5");
+        assert_eq!(
+            Complex::new(0.0, 1.0).format(ComplexFormat::Polar),
+            format!("1 ∠ {}", std::f64::consts::FRAC_PI_2)
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    ops::{Add, Mul, Neg, Sub},
+};
+
+use crate::{
+    bigint::{BigInt, MAX_POWER_BITS},
+    evaluator::{EvalError, EvalResult, Evaluator},
+    numeric::Numeric,
+    tokens::Number,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round to nearest, ties to the even neighbour ("banker's rounding").
+    #[default]
+    HalfEven,
+    /// Round to nearest, ties away from zero.
+    HalfUp,
+    /// Drop the excess digits, rounding towards zero.
+    Truncate,
+}
+
+/// A decimal number `coefficient × 10^-scale`.
+///
+/// The scale is kept as written, so `1.50` has scale 2 and prints as `1.50`;
+/// comparisons are by value, so `1.50 == 1.5`.
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    coefficient: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn from_integer(n: BigInt) -> Self {
+        Decimal {
+            coefficient: n,
+            scale: 0,
+        }
+    }
+
+    /// Parses a decimal literal such as `12`, `-0.125` or `3.10` exactly.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let mut coefficient = BigInt::parse(&format!("{}{}", integer, fraction))?;
+        if negative {
+            coefficient = -coefficient;
+        }
+        Some(Decimal {
+            coefficient,
+            scale: u32::try_from(fraction.len()).ok()?,
+        })
+    }
+
+    pub fn coefficient(&self) -> &BigInt {
+        &self.coefficient
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficient.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.coefficient.is_negative()
+    }
+
+    /// Changes the number of fractional digits, rounding if digits are lost.
+    pub fn rescale(&self, scale: u32, mode: RoundingMode) -> Decimal {
+        let coefficient = match scale.cmp(&self.scale) {
+            Ordering::Equal => self.coefficient.clone(),
+            Ordering::Greater => &self.coefficient * &power_of_ten(scale - self.scale),
+            Ordering::Less => {
+                divide_rounded(&self.coefficient, &power_of_ten(self.scale - scale), mode)
+            }
+        };
+        Decimal { coefficient, scale }
+    }
+
+    /// Rounds to at most `precision` significant digits. Digits left of the
+    /// decimal point are never dropped.
+    pub fn round_significant(&self, precision: u32, mode: RoundingMode) -> Decimal {
+        let digits = self.coefficient.abs().to_string().len() as u32;
+        if digits <= precision {
+            return self.clone();
+        }
+        let excess = (digits - precision).min(self.scale);
+        self.rescale(self.scale - excess, mode)
+    }
+
+    /// `self / divisor` rounded to `scale` fractional digits. Returns `None`
+    /// when dividing by zero.
+    pub fn div(&self, divisor: &Decimal, scale: u32, mode: RoundingMode) -> Option<Decimal> {
+        if divisor.is_zero() {
+            return None;
+        }
+        // self / divisor = (a / b) × 10^(divisor.scale - self.scale); shift a
+        // so the quotient comes out with the requested scale.
+        let shift = scale as i64 + divisor.scale as i64 - self.scale as i64;
+        let (numerator, denominator) = if shift >= 0 {
+            (
+                &self.coefficient * &power_of_ten(shift as u32),
+                divisor.coefficient.clone(),
+            )
+        } else {
+            (
+                self.coefficient.clone(),
+                &divisor.coefficient * &power_of_ten(shift.unsigned_abs() as u32),
+            )
+        };
+        Some(Decimal {
+            coefficient: divide_rounded(&numerator, &denominator, mode),
+            scale,
+        })
+    }
+
+    /// Raises to a power, or `None` when the coefficient or the `10^scale`
+    /// needed to align the result would exceed [`MAX_POWER_BITS`].
+    pub fn pow(&self, exponent: u32) -> Option<Decimal> {
+        // 10^scale needs a little under 4 bits per digit.
+        let scale = self
+            .scale
+            .checked_mul(exponent)
+            .filter(|scale| u64::from(*scale) * 4 <= MAX_POWER_BITS)?;
+        Some(Decimal {
+            coefficient: self.coefficient.checked_pow(exponent)?,
+            scale,
+        })
+    }
+
+    fn aligned(&self, other: &Decimal) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        (
+            self.rescale(scale, RoundingMode::Truncate).coefficient,
+            other.rescale(scale, RoundingMode::Truncate).coefficient,
+            scale,
+        )
+    }
+}
+
+fn power_of_ten(exponent: u32) -> BigInt {
+    BigInt::from(10u64).pow(exponent)
+}
+
+/// `numerator / denominator` rounded to an integer.
+fn divide_rounded(numerator: &BigInt, denominator: &BigInt, mode: RoundingMode) -> BigInt {
+    let (quotient, remainder) = numerator.div_rem(denominator);
+    if remainder.is_zero() || mode == RoundingMode::Truncate {
+        return quotient;
+    }
+
+    let away_from_zero = if numerator.is_negative() != denominator.is_negative() {
+        &quotient - &BigInt::one()
+    } else {
+        &quotient + &BigInt::one()
+    };
+    let twice_remainder = &remainder.abs() * &BigInt::from(2u64);
+    match twice_remainder.cmp(&denominator.abs()) {
+        Ordering::Less => quotient,
+        Ordering::Greater => away_from_zero,
+        Ordering::Equal => match mode {
+            RoundingMode::HalfUp => away_from_zero,
+            _ => {
+                let (_, parity) = quotient.div_rem(&BigInt::from(2u64));
+                if parity.is_zero() {
+                    quotient
+                } else {
+                    away_from_zero
+                }
+            }
+        },
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.coefficient.abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+
+        if self.is_negative() {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", integer)?;
+        if !fraction.is_empty() {
+            write!(f, ".{}", fraction)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b, _) = self.aligned(other);
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal {
+            coefficient: &a + &b,
+            scale,
+        }
+    }
+}
+
+impl Sub for &Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: &Decimal) -> Decimal {
+        self + &-other
+    }
+}
+
+impl Mul for &Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: &Decimal) -> Decimal {
+        Decimal {
+            coefficient: &self.coefficient * &other.coefficient,
+            scale: self.scale + other.scale,
+        }
+    }
+}
+
+impl Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal {
+            coefficient: -&self.coefficient,
+            scale: self.scale,
+        }
+    }
+}
+
+/// How [`Decimal`] arithmetic rounds, given to the evaluator with
+/// [`Evaluator::context`].
+///
+/// Literals and variables are read exactly, however many digits they have.
+/// `+`, `-` and `*` are exact until a result exceeds the precision (28
+/// significant digits by default), at which point it is rounded with the
+/// [`RoundingMode`]; negation, percentages, `//` and `%` never round. `/` rounds to [`DecimalContext::division_scale`] fractional
+/// digits when one is set and to the precision otherwise.
+#[derive(Debug, Clone)]
+pub struct DecimalContext {
+    precision: u32,
+    rounding: RoundingMode,
+    division_scale: Option<u32>,
+}
+
+impl Default for DecimalContext {
+    fn default() -> Self {
+        DecimalContext {
+            precision: 28,
+            rounding: RoundingMode::default(),
+            division_scale: None,
+        }
+    }
+}
+
+impl DecimalContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of significant digits kept in any result.
+    pub fn precision(mut self, digits: u32) -> Self {
+        self.precision = digits.max(1);
+        self
+    }
+
+    pub fn rounding(mut self, mode: RoundingMode) -> Self {
+        self.rounding = mode;
+        self
+    }
+
+    /// Number of fractional digits every `/` result is rounded to, e.g. `2`
+    /// for cents.
+    pub fn division_scale(mut self, scale: u32) -> Self {
+        self.division_scale = Some(scale);
+        self
+    }
+
+    fn round(&self, value: Decimal) -> Decimal {
+        value.round_significant(self.precision, self.rounding)
+    }
+}
+
+impl Numeric for Decimal {
+    type Context = DecimalContext;
+
+    fn from_literal(literal: &Number) -> EvalResult<Self> {
+        Decimal::parse(literal.text())
+            .ok_or_else(|| EvalError::new(format!("Invalid number: {}", literal)))
+    }
+
+    fn add(&self, other: &Self, context: &DecimalContext) -> EvalResult<Self> {
+        Ok(context.round(self + other))
+    }
+
+    fn sub(&self, other: &Self, context: &DecimalContext) -> EvalResult<Self> {
+        Ok(context.round(self - other))
+    }
+
+    fn mul(&self, other: &Self, context: &DecimalContext) -> EvalResult<Self> {
+        Ok(context.round(self * other))
+    }
+
+    fn div(&self, divisor: &Self, context: &DecimalContext) -> EvalResult<Self> {
+        let divide = |scale| {
+            Decimal::div(self, divisor, scale, context.rounding)
+                .ok_or_else(|| EvalError::new("Division by zero"))
+        };
+        if let Some(scale) = context.division_scale {
+            return divide(scale);
+        }
+
+        // The quotient has `magnitude` or `magnitude + 1` integer digits; try
+        // the larger scale first so the result is only rounded once.
+        let integer_digits =
+            |d: &Decimal| d.coefficient().abs().to_string().len() as i64 - d.scale() as i64;
+        let magnitude = integer_digits(self) - integer_digits(divisor);
+        let scale = (context.precision as i64 - magnitude).max(0) as u32;
+        let mut quotient = divide(scale)?;
+        if quotient.coefficient().abs().to_string().len() as u32 > context.precision && scale > 0 {
+            quotient = divide(scale - 1)?;
+        }
+
+        // Exact quotients drop trailing zeros down to the scale the operands
+        // imply, so `1 / 4` is `0.25` rather than `0.2500…`.
+        if &quotient * divisor == *self {
+            let ideal = self.scale().saturating_sub(divisor.scale());
+            while quotient.scale() > ideal {
+                let trimmed = quotient.rescale(quotient.scale() - 1, RoundingMode::Truncate);
+                if trimmed != quotient {
+                    break;
+                }
+                quotient = trimmed;
+            }
+        }
+        Ok(quotient)
+    }
+
+    fn pow(&self, exponent: &Self, context: &DecimalContext) -> EvalResult<Self> {
+        let integral = exponent.rescale(0, RoundingMode::Truncate);
+        let exponent = (integral == *exponent)
+            .then(|| integral.coefficient().to_i128())
+            .flatten()
+            .and_then(|n| i32::try_from(n).ok())
+            .ok_or_else(|| EvalError::new(format!("Exponent {} must be an integer", exponent)))?;
+        let power = Decimal::pow(self, exponent.unsigned_abs()).ok_or_else(|| {
+            EvalError::new(format!(
+                "Power of {} too large: exponent {}",
+                self, exponent
+            ))
+        })?;
+        if exponent < 0 {
+            Numeric::div(&Decimal::from_integer(BigInt::one()), &power, context)
+        } else {
+            Ok(context.round(power))
+        }
+    }
+
+    /// Exact, so `//` and `%` do not depend on how `/` rounds.
+    fn div_floor(&self, divisor: &Self, _: &DecimalContext) -> EvalResult<Self> {
+        if divisor.is_zero() {
+            return Err(EvalError::new("Division by zero"));
+        }
+        let (a, b, _) = self.aligned(divisor);
+        Ok(Decimal::from_integer(a.div_floor(&b)))
+    }
+
+    /// Moves the decimal point, so the result is exact.
+    fn percent(&self, _: &DecimalContext) -> EvalResult<Self> {
+        Ok(Decimal {
+            coefficient: self.coefficient.clone(),
+            scale: self.scale + 2,
+        })
+    }
+
+    fn neg(&self) -> EvalResult<Self> {
+        Ok(-self)
+    }
+
+    fn compare(&self, other: &Self) -> EvalResult<Option<Ordering>> {
+        Ok(Some(self.cmp(other)))
+    }
+
+    fn floor(&self) -> EvalResult<Self> {
+        Ok(Decimal::from_integer(
+            self.coefficient.div_floor(&power_of_ten(self.scale)),
+        ))
+    }
+
+    fn to_index(&self) -> EvalResult<usize> {
+        let integral = self.rescale(0, RoundingMode::Truncate);
+        (integral == *self)
+            .then(|| integral.coefficient().to_i128())
+            .flatten()
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or_else(|| EvalError::new(format!("Invalid index: {}", self)))
+    }
+}
+
+/// Evaluates an [`ASTNode`] over [`Decimal`]s, rounding as its
+/// [`DecimalContext`] says. `^` only takes integer exponents, and there are
+/// no built-in functions or `pi`/`e` constants; everything else works as
+/// with [`Evaluator`].
+///
+/// [`ASTNode`]: crate::parser::ASTNode
+pub type DecimalEvaluator = Evaluator<Decimal>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval_with(context: &DecimalContext, input: &str) -> AppResult<String> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let program = Parser::new(tokens).parse_program()?;
+        let mut evaluator = DecimalEvaluator::default().context(context.clone());
+        Ok(evaluator.run(&program)?.to_string())
+    }
+
+    fn eval(input: &str) -> AppResult<String> {
+        eval_with(&DecimalContext::new(), input)
+    }
+
+    #[test]
+    fn literals_keep_their_scale() {
+        assert_eq!(eval("0.1 + 0.2").unwrap(), "0.3");
+        assert_eq!(eval("1.50 + 2.25").unwrap(), "3.75");
+        assert_eq!(eval("19.99 * 3").unwrap(), "59.97");
+        assert_eq!(eval("1.10 - 0.10").unwrap(), "1.00");
+        assert_eq!(eval("12.5%").unwrap(), "0.125");
+    }
+
+    #[test]
+    fn division_with_explicit_scale() {
+        let cents = DecimalContext::new().division_scale(2);
+        assert_eq!(eval_with(&cents, "10 / 3").unwrap(), "3.33");
+        assert_eq!(eval_with(&cents, "-10 / 3").unwrap(), "-3.33");
+        assert_eq!(eval_with(&cents, "2 / 3").unwrap(), "0.67");
+        assert!(eval_with(&cents, "1 / 0").is_err());
+        assert_eq!(eval("1 / 3").unwrap(), "0.3333333333333333333333333333");
+        assert_eq!(eval("1 / 4").unwrap(), "0.25");
+        assert_eq!(eval("1.00 / 4").unwrap(), "0.25");
+        assert_eq!(eval("9 / 3").unwrap(), "3");
+    }
+
+    #[test]
+    fn programs_lists_and_comparisons() {
+        let cents = DecimalContext::new().division_scale(2);
+        assert_eq!(
+            eval_with(&cents, "price = 19.99\nshare(n) = price / n\nshare(3)").unwrap(),
+            "6.66"
+        );
+        assert_eq!(eval_with(&cents, "12.5%").unwrap(), "0.125");
+        assert_eq!(eval_with(&cents, "7 // 2").unwrap(), "3");
+        assert_eq!(eval("sum([0.10, 0.20, 0.30])").unwrap(), "0.60");
+        assert_eq!(eval("map([1.5, 2.25], x => x * 2)").unwrap(), "[3.0, 4.50]");
+        assert_eq!(eval("0.1 + 0.2 == 0.3").unwrap(), "true");
+        assert_eq!(eval("max([1.5, 1.25]) < 2").unwrap(), "true");
+        assert!(eval("sqrt(2)").is_err());
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let scale = |mode| DecimalContext::new().division_scale(0).rounding(mode);
+        let half_even = scale(RoundingMode::HalfEven);
+        let half_up = scale(RoundingMode::HalfUp);
+        let truncate = scale(RoundingMode::Truncate);
+
+        assert_eq!(eval_with(&half_even, "5 / 2").unwrap(), "2");
+        assert_eq!(eval_with(&half_even, "7 / 2").unwrap(), "4");
+        assert_eq!(eval_with(&half_up, "5 / 2").unwrap(), "3");
+        assert_eq!(eval_with(&half_up, "-5 / 2").unwrap(), "-3");
+        assert_eq!(eval_with(&truncate, "-7 / 2").unwrap(), "-3");
+        assert_eq!(eval_with(&truncate, "9.99 / 1").unwrap(), "9");
+    }
+
+    #[test]
+    fn precision_limits_significant_digits() {
+        let evaluator = DecimalContext::new().precision(4);
+        assert_eq!(eval_with(&evaluator, "1.23456 * 1").unwrap(), "1.235");
+        assert_eq!(eval_with(&evaluator, "1.23456").unwrap(), "1.23456");
+        assert_eq!(eval_with(&evaluator, "-1.23456").unwrap(), "-1.23456");
+        assert_eq!(eval_with(&evaluator, "1.00005 - 1").unwrap(), "0.00005");
+        assert_eq!(eval_with(&evaluator, "123456 * 1").unwrap(), "123456");
+        assert_eq!(eval_with(&evaluator, "2 / 3").unwrap(), "0.6667");
+    }
+
+    #[test]
+    fn integer_division_and_powers() {
+        assert_eq!(eval("7.5 // 2").unwrap(), "3");
+        assert_eq!(eval("-7.5 // 2").unwrap(), "-4");
+        assert_eq!(eval("-7 // 2").unwrap(), "-4");
+        assert_eq!(eval("7.5 % 2").unwrap(), "1.5");
+        assert_eq!(eval("1.1^2").unwrap(), "1.21");
+        assert_eq!(eval("2^-2").unwrap(), "0.25");
+        assert!(eval("2^0.5").is_err());
+        assert_eq!(
+            eval("2^2000000000").unwrap_err().to_string(),
+            "Error(Eval): Power of 2 too large: exponent 2000000000"
+        );
+        assert!(eval("0.5^100000").is_err());
+    }
+}
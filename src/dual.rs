@@ -0,0 +1,349 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+use crate::{
+    evaluator::{arity_mismatch, unknown_function, EvalError, EvalResult, Evaluator, Value},
+    numeric::Numeric,
+    tokens::Number,
+};
+
+/// A value together with its partial derivatives, keyed by variable name.
+///
+/// Variables that do not appear in [`Dual::partials`] have a derivative of
+/// zero.
+#[derive(Debug, Clone)]
+pub struct Dual {
+    value: f64,
+    partials: BTreeMap<String, f64>,
+}
+
+impl Dual {
+    pub fn constant(value: f64) -> Self {
+        Dual {
+            value,
+            partials: BTreeMap::new(),
+        }
+    }
+
+    /// An independent variable, whose derivative with respect to itself is
+    /// one.
+    pub fn variable(name: impl Into<String>, value: f64) -> Self {
+        Dual {
+            value,
+            partials: BTreeMap::from([(name.into(), 1.0)]),
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// `∂self/∂name`.
+    pub fn partial(&self, name: &str) -> f64 {
+        self.partials.get(name).copied().unwrap_or(0.0)
+    }
+
+    pub fn partials(&self) -> &BTreeMap<String, f64> {
+        &self.partials
+    }
+
+    fn is_constant(&self) -> bool {
+        self.partials.is_empty()
+    }
+
+    /// `f(self) = value` with derivative `derivative`.
+    fn chain(&self, value: f64, derivative: f64) -> Dual {
+        self.combine(&Dual::constant(0.0), value, derivative, 0.0)
+    }
+
+    /// `f(self, other) = value` with partial derivatives `d_self` and
+    /// `d_other`.
+    fn combine(&self, other: &Dual, value: f64, d_self: f64, d_other: f64) -> Dual {
+        let mut partials = BTreeMap::new();
+        for (name, d) in &self.partials {
+            *partials.entry(name.clone()).or_insert(0.0) += d_self * d;
+        }
+        for (name, d) in &other.partials {
+            *partials.entry(name.clone()).or_insert(0.0) += d_other * d;
+        }
+        Dual { value, partials }
+    }
+}
+
+impl Display for Dual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)?;
+        for (i, (name, d)) in self.partials.iter().enumerate() {
+            let separator = if i == 0 { " (" } else { ", " };
+            write!(f, "{}d/d{} = {}", separator, name, d)?;
+        }
+        if !self.partials.is_empty() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Dual {
+    /// Compares values only, so `x == 2` holds at `x = 2` whatever the
+    /// partial derivatives.
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Numeric for Dual {
+    type Context = ();
+
+    fn from_literal(literal: &Number) -> EvalResult<Self> {
+        Ok(Dual::constant(literal.value()))
+    }
+
+    fn add(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(self.combine(other, self.value + other.value, 1.0, 1.0))
+    }
+
+    fn sub(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(self.combine(other, self.value - other.value, 1.0, -1.0))
+    }
+
+    fn mul(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(self.combine(other, self.value * other.value, other.value, self.value))
+    }
+
+    fn div(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        let (a, b) = (self.value, other.value);
+        if b == 0.0 {
+            return Err(EvalError::new("Division by zero"));
+        }
+        Ok(self.combine(other, a / b, 1.0 / b, -a / (b * b)))
+    }
+
+    fn pow(&self, exponent: &Self, _: &()) -> EvalResult<Self> {
+        power(self, exponent)
+    }
+
+    fn neg(&self) -> EvalResult<Self> {
+        Ok(self.chain(-self.value, -1.0))
+    }
+
+    /// Orders by value, so conditions are decided at the point.
+    fn compare(&self, other: &Self) -> EvalResult<Option<Ordering>> {
+        Ok(self.value.partial_cmp(&other.value))
+    }
+
+    /// Constant between the integers, so the derivative is zero.
+    fn floor(&self) -> EvalResult<Self> {
+        Ok(Dual::constant(self.value.floor()))
+    }
+
+    fn to_index(&self) -> EvalResult<usize> {
+        self.value.to_index()
+    }
+
+    fn call(name: &str, args: &[Self]) -> EvalResult<Self> {
+        let [arg] = args else {
+            return match derivative(name, 0.0) {
+                Some(_) => Err(arity_mismatch(name, 1, args.len())),
+                None => Err(unknown_function(name)),
+            };
+        };
+        let (value, d) = derivative(name, arg.value).ok_or_else(|| unknown_function(name))?;
+        Ok(arg.chain(value, d))
+    }
+
+    fn constant(name: &str) -> Option<Self> {
+        f64::constant(name).map(Dual::constant)
+    }
+}
+
+/// Evaluates an [`ASTNode`] over [`Dual`] numbers, giving the value and the
+/// exact partial derivatives with respect to every variable set with
+/// [`Evaluator::set_independent`] in a single pass.
+///
+/// Other variables are held constant. `x^y` is differentiated in both its
+/// base and its exponent, so `2^x` and `x^x` work; the exponent derivative
+/// needs a positive base, except that a zero base with a positive exponent
+/// contributes nothing. Functions whose derivative does not exist at the
+/// point, such as `sqrt` at zero, give an infinite or NaN partial rather
+/// than an error.
+///
+/// Comparisons, `cond ? a : b` and `piecewise(...)` compare values at the
+/// point and differentiate only the chosen branch, which gives the
+/// derivative everywhere except at the boundaries between pieces.
+///
+/// [`ASTNode`]: crate::parser::ASTNode
+pub type DualEvaluator = Evaluator<Dual>;
+
+impl Evaluator<Dual> {
+    /// Binds `name` to `value` and differentiates with respect to it.
+    pub fn set_independent(&mut self, name: impl Into<String>, value: f64) {
+        let name = name.into();
+        self.set_variable(name.clone(), Value::Number(Dual::variable(name, value)));
+    }
+}
+
+/// `d/da a^b = b·a^(b-1)` and `d/db a^b = a^b·ln a`.
+fn power(base: &Dual, exponent: &Dual) -> EvalResult<Dual> {
+    let (a, b) = (base.value, exponent.value);
+    let value = a.powf(b);
+    if value.is_nan() {
+        return Err(EvalError::new(format!(
+            "Non-integer power of a negative number: {}^{}",
+            a, b
+        )));
+    }
+
+    let d_base = if b == 0.0 { 0.0 } else { b * a.powf(b - 1.0) };
+    let d_exponent = if exponent.is_constant() || (a == 0.0 && b > 0.0) {
+        0.0
+    } else if a > 0.0 {
+        value * a.ln()
+    } else {
+        return Err(EvalError::new(format!(
+            "Cannot differentiate {}^{} with respect to its exponent",
+            a, b
+        )));
+    };
+    Ok(base.combine(exponent, value, d_base, d_exponent))
+}
+
+/// The value and derivative of a built-in function at `x`.
+fn derivative(name: &str, x: f64) -> Option<(f64, f64)> {
+    Some(match name {
+        "sqrt" => (x.sqrt(), 0.5 / x.sqrt()),
+        "abs" => (x.abs(), if x == 0.0 { 0.0 } else { x.signum() }),
+        "exp" => (x.exp(), x.exp()),
+        "ln" => (x.ln(), 1.0 / x),
+        "log10" => (x.log10(), 1.0 / (x * std::f64::consts::LN_10)),
+        "sin" => (x.sin(), x.cos()),
+        "cos" => (x.cos(), -x.sin()),
+        "tan" => (x.tan(), 1.0 / (x.cos() * x.cos())),
+        "asin" => (x.asin(), 1.0 / (1.0 - x * x).sqrt()),
+        "acos" => (x.acos(), -1.0 / (1.0 - x * x).sqrt()),
+        "atan" => (x.atan(), 1.0 / (1.0 + x * x)),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval_with(evaluator: &DualEvaluator, input: &str) -> AppResult<Dual> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        match evaluator.evaluate(&ast)? {
+            Value::Number(n) => Ok(n),
+            value => panic!("Expected a number, got {}", value),
+        }
+    }
+
+    fn at(x: f64, y: f64) -> DualEvaluator {
+        let mut evaluator = DualEvaluator::default();
+        evaluator.set_independent("x", x);
+        evaluator.set_independent("y", y);
+        evaluator
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn gradient_of_a_polynomial() {
+        let result = eval_with(&at(2.0, 3.0), "x^2 * y + 3 * x - y / 2").unwrap();
+        assert_eq!(result.value(), 16.5);
+        assert_eq!(result.partial("x"), 15.0);
+        assert_eq!(result.partial("y"), 3.5);
+        assert_eq!(result.partial("z"), 0.0);
+        assert_eq!(result.to_string(), "16.5 (d/dx = 15, d/dy = 3.5)");
+    }
+
+    #[test]
+    fn only_selected_variables_are_differentiated() {
+        let mut evaluator = DualEvaluator::default();
+        evaluator.set_independent("x", 2.0);
+        evaluator.set_variable("k", Value::Number(Dual::constant(5.0)));
+        let result = eval_with(&evaluator, "k * x^3").unwrap();
+        assert_eq!(result.value(), 40.0);
+        assert_eq!(
+            result.partials(),
+            &BTreeMap::from([("x".to_string(), 60.0)])
+        );
+    }
+
+    #[test]
+    fn powers_with_real_and_variable_exponents() {
+        let sqrt = eval_with(&at(4.0, 0.0), "x^0.5").unwrap();
+        assert_close(sqrt.partial("x"), 0.25);
+
+        let exponential = eval_with(&at(3.0, 0.0), "2^x").unwrap();
+        assert_close(exponential.partial("x"), 8.0 * 2f64.ln());
+
+        // d/dx x^x = x^x (ln x + 1)
+        let tower = eval_with(&at(2.0, 0.0), "x^x").unwrap();
+        assert_close(tower.partial("x"), 4.0 * (2f64.ln() + 1.0));
+
+        let both = eval_with(&at(2.0, 3.0), "x^y").unwrap();
+        assert_close(both.partial("x"), 12.0);
+        assert_close(both.partial("y"), 8.0 * 2f64.ln());
+
+        assert_eq!(eval_with(&at(-2.0, 0.0), "x^3").unwrap().partial("x"), 12.0);
+        assert_eq!(eval_with(&at(0.0, 2.0), "x^y").unwrap().partial("y"), 0.0);
+        assert!(eval_with(&at(-2.0, 0.0), "x^0.5").is_err());
+        assert!(eval_with(&at(-2.0, 2.0), "x^y").is_err());
+    }
+
+    #[test]
+    fn piecewise_differentiates_the_chosen_branch() {
+        let formula = "piecewise(x < 0, -x, x < 2 && y > 0, x^2 * y, 4 * y)";
+        let left = eval_with(&at(-3.0, 1.0), formula).unwrap();
+        assert_eq!((left.value(), left.partial("x")), (3.0, -1.0));
+        let middle = eval_with(&at(1.5, 2.0), formula).unwrap();
+        assert_eq!(middle.value(), 4.5);
+        assert_eq!((middle.partial("x"), middle.partial("y")), (6.0, 2.25));
+        let right = eval_with(&at(5.0, 2.0), formula).unwrap();
+        assert_eq!((right.partial("x"), right.partial("y")), (0.0, 4.0));
+
+        let conditional = eval_with(&at(2.0, 0.0), "x > 1 ? x^3 : x").unwrap();
+        assert_eq!(conditional.partial("x"), 12.0);
+        assert!(eval_with(&at(1.0, 0.0), "piecewise(x, 1, 2)").is_err());
+    }
+
+    #[test]
+    fn user_functions_and_lists() {
+        let input = "f(t) = t^2 * y\nf(x) + sum(map([x, y], t => 2 * t))";
+        let (tokens, _) = Lexer::new(input).tokenize().unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        let Value::Number(result) = at(3.0, 2.0).run(&program).unwrap() else {
+            panic!("Expected a number");
+        };
+        assert_eq!(result.value(), 28.0);
+        assert_eq!((result.partial("x"), result.partial("y")), (14.0, 11.0));
+
+        let series = eval_with(&at(2.0, 0.0), "sum(k, 1, 3, x^k)").unwrap();
+        assert_eq!((series.value(), series.partial("x")), (14.0, 17.0));
+        assert_eq!(
+            eval_with(&at(2.0, 0.0), "x == 2 ? 1 : 0").unwrap().value(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn chain_rule_through_functions() {
+        let result = eval_with(&at(0.5, 0.0), "sin(x^2) + ln(x)").unwrap();
+        assert_close(result.partial("x"), 2.0 * 0.5 * 0.25f64.cos() + 2.0);
+        assert!(eval_with(&at(1.0, 0.0), "sin(x, 2)").is_err());
+        assert!(eval_with(&at(1.0, 0.0), "nope(x)").is_err());
+    }
+}
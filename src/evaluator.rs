@@ -0,0 +1,2100 @@
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    location::Location,
+    matrix::Matrix,
+    numeric::Numeric,
+    parser::{ASTNode, Function, Program, Statement},
+    rewrite::substitute,
+    tokens::{self, Number, Range},
+};
+
+pub type EvalResult<T> = Result<T, EvalError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<T = f64> {
+    Number(T),
+    Boolean(bool),
+    List(Vec<Value<T>>),
+    Matrix(Matrix<T>),
+    Function(Rc<Closure<T>>),
+}
+
+impl<T> Value<T> {
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::List(_) => "list",
+            Value::Matrix(_) => "matrix",
+            Value::Function(_) => "function",
+        }
+    }
+}
+
+impl<T: Display> Display for Value<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Matrix(matrix) => write!(f, "{}", matrix),
+            Value::Function(closure) => write!(f, "{}", closure),
+        }
+    }
+}
+
+/// The value of a lambda such as `x => x * k`.
+///
+/// Scoping is lexical: the body sees the lambda's parameters, then the
+/// parameters of the user function or lambda calls it was created in, which
+/// it keeps after those calls return, and then the evaluator's variables as
+/// they are when it is called. Two closures are only equal if they are the
+/// same value.
+#[derive(Debug)]
+pub struct Closure<T = f64> {
+    params: Vec<String>,
+    body: ASTNode,
+    captured: HashMap<String, Value<T>>,
+}
+
+impl<T> Closure<T> {
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &ASTNode {
+        &self.body
+    }
+}
+
+impl<T> PartialEq for Closure<T> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl<T> Display for Closure<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.params.as_slice() {
+            [param] => write!(f, "{} => {}", param, self.body),
+            params => write!(f, "({}) => {}", params.join(", "), self.body),
+        }
+    }
+}
+
+/// Why an expression could not be evaluated, and where, once a
+/// [`ASTNode::Located`] node or a located statement has said so.
+#[derive(Debug)]
+pub struct EvalError {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(location) = self.location {
+            write!(
+                f,
+                " in line {} at column {}",
+                location.line(),
+                location.col()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for EvalError {}
+
+impl EvalError {
+    pub fn new(message: impl Into<String>) -> Self {
+        EvalError {
+            message: message.into(),
+            location: None,
+        }
+    }
+
+    /// Records where the error happened, shown like a [`LexerError`]'s
+    /// location. An error that already has a location keeps that innermost
+    /// one.
+    ///
+    /// [`LexerError`]: crate::lexer::LexerError
+    pub fn at(mut self, location: Location) -> Self {
+        self.location.get_or_insert(location);
+        self
+    }
+}
+
+/// What a postfix `%` means.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PercentMode {
+    /// `x%` is always `x / 100`, so `200 + 10%` is `200.1`.
+    #[default]
+    Fraction,
+    /// Calculator style: on the right of `+` or `-` a percentage is taken of
+    /// the left operand, so `200 + 10%` is `220`. Anywhere else `x%` is
+    /// `x / 100`.
+    Relative,
+}
+
+/// The largest [`Evaluator::recursion_limit`]. Each nested call takes up to
+/// a few kilobytes of stack in a debug build, so this leaves a wide margin on
+/// an 8 MB stack.
+pub const MAX_RECURSION_LIMIT: usize = 256;
+
+/// Walks an [`ASTNode`] and computes its [`Value`].
+///
+/// Numbers and booleans are distinct types: `1 + (2 < 3)` and `1 && 2` are
+/// type errors. Enable [`Evaluator::numeric_booleans`] to get C-like
+/// behaviour instead, where booleans count as `1`/`0` in arithmetic and any
+/// non-zero number is true in a logical context. The literals `true` and
+/// `false` are booleans, and `if(c, a, b)` is another way to write
+/// `c ? a : b`.
+///
+/// Variables are looked up in the bindings set with
+/// [`Evaluator::set_variable`] first and then among the constants `pi` and
+/// `e`, which can therefore be shadowed.
+///
+/// Lists are written `[1, 2, 3]` and indexed from zero with `xs[0]`.
+/// Arithmetic on a list applies item by item, pairing up the items of two
+/// lists of the same length or combining each item with a scalar.
+///
+/// A list literal whose items are rows of numbers of the same length, such
+/// as `[[1, 2], [3, 4]]`, is a [`Matrix`]. For matrices `*` is the matrix
+/// product, `^` with an integer exponent is repeated multiplication, `A \ b`
+/// solves `A * x = b`, and `det`, `inv` and `transpose` are built in; other
+/// operators apply item by item. A matrix is otherwise the list of its rows:
+/// it can be indexed, mapped, filtered and counted, and equals the same rows
+/// as a list. Likewise a list of rows built by `map` works as a matrix with
+/// those operators.
+///
+/// `sum(i, from, to, body)` and `prod(i, from, to, body)` add up or multiply
+/// `body` for every integer `i` in the range, with `i` bound only inside
+/// `body`. The number of terms is capped by [`Evaluator::iteration_limit`].
+///
+/// `piecewise(c1, v1, c2, v2, ..., default)` gives the value after the first
+/// condition that holds, or `default`, evaluating nothing else past that
+/// condition, so `piecewise(x == 0, 0, 1 / x)` never divides by zero.
+///
+/// A cell reference such as `B2` or `$B$2` reads the variable named `B2`, and
+/// is `0` when there is none. A range such as `A1:C3` is the list of the
+/// values of its cells, row by row, leaving out those without a value, so
+/// `avg(A1:A9)` averages just the filled cells.
+///
+/// Numbers are `f64` by default. Any [`Numeric`] type can be used instead
+/// with `Evaluator::<T>::default()`, such as `f32`, the crate's
+/// [`Rational`](crate::rational::Rational),
+/// [`Decimal`](crate::decimal::Decimal),
+/// [`Complex`](crate::complex::Complex) and [`Dual`](crate::dual::Dual), or
+/// a downstream fixed-point type; operators the type does not support are
+/// errors.
+///
+/// The integer, programmer, interval, uncertainty and units modes have
+/// evaluators of their own, because they change what the operators mean
+/// rather than just the numbers: integers pick their width and overflow
+/// policy per evaluation, programmer mode adds bitwise operators on fixed
+/// word sizes, dividing by an interval around zero splits the result into
+/// a union of intervals, `±` builds an uncertain value, and units check
+/// dimensions across whole expressions.
+#[derive(Debug)]
+pub struct Evaluator<T: Numeric = f64> {
+    context: T::Context,
+    numeric_booleans: bool,
+    percent_mode: PercentMode,
+    variables: HashMap<String, Value<T>>,
+    functions: BTreeMap<String, Function>,
+    recursion_limit: usize,
+    iteration_limit: usize,
+}
+
+impl<T: Numeric> Default for Evaluator<T> {
+    fn default() -> Self {
+        Evaluator {
+            context: T::Context::default(),
+            numeric_booleans: false,
+            percent_mode: PercentMode::default(),
+            variables: HashMap::new(),
+            functions: BTreeMap::new(),
+            recursion_limit: 128,
+            iteration_limit: 100_000,
+        }
+    }
+}
+
+/// The parameters of the user function call being evaluated, how many
+/// calls deep it is, and how many series terms the whole evaluation has
+/// taken so far, a count every scope of it shares.
+#[derive(Debug)]
+struct Scope<T> {
+    locals: HashMap<String, Value<T>>,
+    depth: usize,
+    terms: Rc<Cell<usize>>,
+}
+
+impl<T> Scope<T> {
+    fn global() -> Self {
+        Scope {
+            locals: HashMap::new(),
+            depth: 0,
+            terms: Rc::default(),
+        }
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T: Numeric> Evaluator<T> {
+    /// The settings the number type's arithmetic uses, such as the
+    /// precision of a [`Decimal`](crate::decimal::Decimal).
+    pub fn context(mut self, context: T::Context) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn numeric_booleans(mut self, enabled: bool) -> Self {
+        self.numeric_booleans = enabled;
+        self
+    }
+
+    pub fn percent_mode(mut self, mode: PercentMode) -> Self {
+        self.percent_mode = mode;
+        self
+    }
+
+    /// How many user function calls may be nested, counting recursive
+    /// ones. Deeper calls fail with a recursion limit error. Defaults to 128,
+    /// so a function that recurses once per step, such as
+    /// `f(n) = n <= 0 ? 0 : 1 + f(n - 1)`, can take up to 127 steps.
+    ///
+    /// Larger limits are lowered to [`MAX_RECURSION_LIMIT`]; the recursion
+    /// limit error then reports that limit rather than the one given.
+    ///
+    /// The limit only keeps evaluation off the end of the stack on a thread
+    /// with the usual 8 MB main thread stack, and for function bodies of
+    /// ordinary depth. Every nested call and subexpression uses stack, so a
+    /// thread with a smaller stack, or very deeply nested bodies, needs a
+    /// lower limit.
+    pub fn recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = limit.min(MAX_RECURSION_LIMIT);
+        self
+    }
+
+    /// How many terms the `sum(i, from, to, body)` and
+    /// `prod(i, from, to, body)` series of one evaluation may have in all,
+    /// counting every run of a nested or repeated series. A series that
+    /// would go over fails before any of its terms is evaluated. Defaults to
+    /// 100 000.
+    pub fn iteration_limit(mut self, limit: usize) -> Self {
+        self.iteration_limit = limit;
+        self
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: Value<T>) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn remove_variable(&mut self, name: &str) -> Option<Value<T>> {
+        self.variables.remove(name)
+    }
+
+    /// Defines or replaces a function, as `f(x, y) = body` does in a
+    /// [`Program`]. User functions take precedence over built-in ones of
+    /// the same name. The body sees its parameters and the evaluator's
+    /// variables, and may call any defined function including itself.
+    pub fn define_function(&mut self, name: impl Into<String>, function: Function) {
+        self.functions.insert(name.into(), function);
+    }
+
+    pub fn remove_function(&mut self, name: &str) -> Option<Function> {
+        self.functions.remove(name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// The defined functions, sorted by name.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, &Function)> {
+        self.functions
+            .iter()
+            .map(|(name, function)| (name.as_str(), function))
+    }
+
+    /// Runs the statements of `program` in order and returns the value of the
+    /// last one that has a value, where an assignment gives the value it
+    /// assigns. Assigned variables and defined functions stay afterwards, so
+    /// later programs can use them. Errors in a [`Statement::Located`] say
+    /// which line they happened on.
+    pub fn run(&mut self, program: &Program) -> EvalResult<Value<T>> {
+        let mut result = None;
+        for statement in &program.statements {
+            if let Some(value) = self.execute(statement)? {
+                result = Some(value);
+            }
+        }
+        result.ok_or_else(|| EvalError::new("Program has no value"))
+    }
+
+    /// The statement's value; definitions have none.
+    fn execute(&mut self, statement: &Statement) -> EvalResult<Option<Value<T>>> {
+        match statement {
+            Statement::Located(location, statement) => {
+                self.execute(statement).map_err(|error| error.at(*location))
+            }
+            Statement::Assign(name, node) => {
+                let value = self.evaluate(node)?;
+                self.set_variable(name.clone(), value.clone());
+                Ok(Some(value))
+            }
+            Statement::Define(name, function) => {
+                self.define_function(name.clone(), function.clone());
+                Ok(None)
+            }
+            Statement::Expression(node) => self.evaluate(node).map(Some),
+        }
+    }
+
+    pub fn evaluate(&self, node: &ASTNode) -> EvalResult<Value<T>> {
+        self.evaluate_in(node, &Scope::global())
+    }
+
+    /// The residual of `node` once the variables this evaluator knows, its
+    /// bindings and constants such as `pi`, are replaced by their values:
+    /// every part that no longer reads a variable is folded into a literal,
+    /// and `?:` and `piecewise` keep only the branches whose conditions
+    /// are not yet known. Evaluating the residual with the remaining
+    /// variables bound gives the same result as evaluating `node`.
+    ///
+    /// Values that cannot be written as literals, such as functions,
+    /// lists of lists and numbers whose text is not a plain decimal, are left
+    /// unfolded. So are calls that may not reach a built-in, and parts that
+    /// fail to evaluate, so the error still happens when the residual is
+    /// evaluated.
+    pub fn partially_evaluate(&self, node: &ASTNode) -> ASTNode {
+        let mut residual = node.clone();
+        for name in node.references().variables.keys() {
+            let value = self.variable(name, &Scope::global()).ok();
+            if let Some(literal) = value.as_ref().and_then(to_node) {
+                residual = substitute(&residual, name, &literal);
+            }
+        }
+        self.fold(&residual, &mut Vec::new())
+    }
+
+    /// Folds `node`, where `bound` holds the names bound around it by
+    /// lambdas and series, whose calls may not reach a built-in.
+    fn fold(&self, node: &ASTNode, bound: &mut Vec<String>) -> ASTNode {
+        let node = match node {
+            ASTNode::Located(location, inner) => {
+                let inner = self.fold(inner, bound);
+                return if is_literal(&inner) {
+                    inner
+                } else {
+                    ASTNode::Located(*location, Box::new(inner))
+                };
+            }
+            ASTNode::Conditional(condition, then_branch, else_branch) => {
+                let condition = self.fold(condition, bound);
+                return match self.known_truth(&condition, bound) {
+                    Some(true) => self.fold(then_branch, bound),
+                    Some(false) => self.fold(else_branch, bound),
+                    None => ASTNode::Conditional(
+                        Box::new(condition),
+                        Box::new(self.fold(then_branch, bound)),
+                        Box::new(self.fold(else_branch, bound)),
+                    ),
+                };
+            }
+            ASTNode::Piecewise(cases, default) => {
+                let mut kept = Vec::new();
+                for (condition, value) in cases {
+                    let condition = self.fold(condition, bound);
+                    match self.known_truth(&condition, bound) {
+                        Some(false) => {}
+                        // Later cases can no longer be reached.
+                        Some(true) if kept.is_empty() => return self.fold(value, bound),
+                        Some(true) => {
+                            return ASTNode::Piecewise(kept, Box::new(self.fold(value, bound)))
+                        }
+                        None => kept.push((condition, self.fold(value, bound))),
+                    }
+                }
+                let default = self.fold(default, bound);
+                return if kept.is_empty() {
+                    default
+                } else {
+                    ASTNode::Piecewise(kept, Box::new(default))
+                };
+            }
+            ASTNode::Lambda(params, body) => {
+                let outer = bound.len();
+                bound.extend(params.iter().cloned());
+                let body = self.fold(body, bound);
+                bound.truncate(outer);
+                ASTNode::Lambda(params.clone(), Box::new(body))
+            }
+            ASTNode::Sum(index, from, to, body) | ASTNode::Product(index, from, to, body) => {
+                let from = Box::new(self.fold(from, bound));
+                let to = Box::new(self.fold(to, bound));
+                bound.push(index.clone());
+                let body = Box::new(self.fold(body, bound));
+                bound.pop();
+                match node {
+                    ASTNode::Sum(..) => ASTNode::Sum(index.clone(), from, to, body),
+                    _ => ASTNode::Product(index.clone(), from, to, body),
+                }
+            }
+            node => node.map_children(&mut |child| self.fold(child, bound)),
+        };
+
+        if is_literal(&node) || !self.is_foldable(&node, bound) {
+            return node;
+        }
+        match self.evaluate(&node).ok().as_ref().and_then(to_node) {
+            Some(literal) => literal,
+            None => node,
+        }
+    }
+
+    /// Whether `node` reads no variables and only calls built-ins that no
+    /// variable or user function of this evaluator, and none of the names
+    /// in `bound`, shadows.
+    fn is_foldable(&self, node: &ASTNode, bound: &[String]) -> bool {
+        node.is_constant_calling(|name| {
+            !self.variables.contains_key(name)
+                && !self.functions.contains_key(name)
+                && !bound.iter().any(|bound| bound == name)
+        })
+    }
+
+    /// The value of a condition that reads no variables.
+    fn known_truth(&self, condition: &ASTNode, bound: &[String]) -> Option<bool> {
+        if !self.is_foldable(condition, bound) {
+            return None;
+        }
+        self.truth(self.evaluate(condition).ok()?).ok()
+    }
+
+    fn evaluate_in(&self, node: &ASTNode, scope: &Scope<T>) -> EvalResult<Value<T>> {
+        match node {
+            ASTNode::Located(location, node) => self
+                .evaluate_in(node, scope)
+                .map_err(|error| error.at(*location)),
+            ASTNode::Number(n) => Ok(Value::Number(T::from_literal(n)?)),
+            ASTNode::Variable(name) => self.variable(name, scope),
+            ASTNode::Add(l, r) => self.additive(l, r, scope, Numeric::add),
+            ASTNode::Subtract(l, r) => self.additive(l, r, scope, Numeric::sub),
+            ASTNode::Divide(l, r) => self.arithmetic(l, r, scope, Numeric::div),
+            ASTNode::Less(l, r) => self.compare(l, r, scope, Ordering::is_lt),
+            ASTNode::LessEqual(l, r) => self.compare(l, r, scope, Ordering::is_le),
+            ASTNode::Greater(l, r) => self.compare(l, r, scope, Ordering::is_gt),
+            ASTNode::GreaterEqual(l, r) => self.compare(l, r, scope, Ordering::is_ge),
+            ASTNode::Conditional(condition, then_branch, else_branch) => {
+                if self.boolean(condition, scope)? {
+                    self.evaluate_in(then_branch, scope)
+                } else {
+                    self.evaluate_in(else_branch, scope)
+                }
+            }
+            ASTNode::Call(name, args) => self.call(name, args, scope),
+            node => self.evaluate_rest(node, scope),
+        }
+    }
+
+    /// The nodes [`Evaluator::evaluate_in`] does not handle itself. Keeping
+    /// them apart keeps its stack frame, which every nested call and
+    /// subexpression pays for, small.
+    fn evaluate_rest(&self, node: &ASTNode, scope: &Scope<T>) -> EvalResult<Value<T>> {
+        match node {
+            ASTNode::Multiply(l, r) => {
+                let left = self.evaluate_in(l, scope)?;
+                self.product(left, self.evaluate_in(r, scope)?)
+            }
+            ASTNode::IntDivide(l, r) => self.arithmetic(l, r, scope, Numeric::div_floor),
+            // Floored like `//`, so `a == (a // b) * b + a % b` always holds
+            // and the result takes the sign of the divisor.
+            ASTNode::Modulo(l, r) => self.arithmetic(l, r, scope, |dividend, divisor, context| {
+                let quotient = dividend.div_floor(divisor, context)?;
+                dividend.sub(&divisor.mul(&quotient, context)?, context)
+            }),
+            ASTNode::Factorial(n) => Ok(Value::Number(self.number(n, scope)?.factorial()?)),
+            ASTNode::Percent(n) => Ok(Value::Number(
+                self.number(n, scope)?.percent(&self.context)?,
+            )),
+            ASTNode::Power(l, r) => {
+                let base = self.evaluate_in(l, scope)?;
+                match as_matrix(&base) {
+                    Ok(matrix) => {
+                        let exponent = self.number(r, scope)?;
+                        let exponent = integer_exponent(&exponent)?;
+                        Ok(Value::Matrix(matrix.pow(exponent, &self.context)?))
+                    }
+                    Err(_) => self.broadcast(base, self.evaluate_in(r, scope)?, Numeric::pow),
+                }
+            }
+            ASTNode::Solve(l, r) => {
+                let left = self.evaluate_in(l, scope)?;
+                let matrix = as_matrix(&left)?;
+                match self.evaluate_in(r, scope)? {
+                    Value::List(items) if as_rows(&items).is_none() => {
+                        let solution = matrix.solve(&self.column(&items)?, &self.context)?;
+                        Ok(column_items(&solution))
+                    }
+                    rhs => Ok(Value::Matrix(
+                        matrix.solve(&*as_matrix(&rhs)?, &self.context)?,
+                    )),
+                }
+            }
+            ASTNode::Negate(n) => {
+                // Broadcast against an ignored scalar so lists negate item by
+                // item.
+                let operand = self.evaluate_in(n, scope)?;
+                let zero = Value::Number(literal("0")?);
+                self.broadcast(zero, operand, |_, x, _| x.neg())
+            }
+            ASTNode::Not(n) => Ok(Value::Boolean(!self.boolean(n, scope)?)),
+            ASTNode::Equal(l, r) => Ok(Value::Boolean(self.equals(l, r, scope)?)),
+            ASTNode::NotEqual(l, r) => Ok(Value::Boolean(!self.equals(l, r, scope)?)),
+            // The right-hand side is only evaluated when it can change the result.
+            ASTNode::And(l, r) => Ok(Value::Boolean(
+                self.boolean(l, scope)? && self.boolean(r, scope)?,
+            )),
+            ASTNode::Or(l, r) => Ok(Value::Boolean(
+                self.boolean(l, scope)? || self.boolean(r, scope)?,
+            )),
+            // Conditions are tried in order and only the chosen value is
+            // evaluated.
+            ASTNode::Piecewise(cases, default) => {
+                for (condition, value) in cases {
+                    if self.boolean(condition, scope)? {
+                        return self.evaluate_in(value, scope);
+                    }
+                }
+                self.evaluate_in(default, scope)
+            }
+            ASTNode::Cell(cell) => match self.variables.get(&cell.position().to_string()) {
+                Some(value) => Ok(value.clone()),
+                None => Ok(Value::Number(literal("0")?)),
+            },
+            ASTNode::Range(range) => Ok(Value::List(self.range(range))),
+            ASTNode::BitAnd(..)
+            | ASTNode::BitOr(..)
+            | ASTNode::BitXor(..)
+            | ASTNode::ShiftLeft(..)
+            | ASTNode::ShiftRight(..)
+            | ASTNode::BitNot(..) => Err(EvalError::new(format!(
+                "Bitwise operators need programmer mode: {}",
+                node
+            ))),
+            ASTNode::Imaginary(n) => Ok(Value::Number(T::from_imaginary(n)?)),
+            ASTNode::List(items) => {
+                let items = self.values(items, scope)?;
+                Ok(match as_rows(&items) {
+                    Some(matrix) => Value::Matrix(matrix),
+                    None => Value::List(items),
+                })
+            }
+            ASTNode::Index(list, index) => {
+                let list = self.evaluate_in(list, scope)?;
+                let index = self.number(index, scope)?.to_index()?;
+                let items = as_list(&list)?;
+                items.get(index).cloned().ok_or_else(|| {
+                    EvalError::new(format!(
+                        "Index {} out of range for a list of {} items",
+                        index,
+                        items.len()
+                    ))
+                })
+            }
+            ASTNode::Lambda(params, body) => Ok(Value::Function(Rc::new(Closure {
+                params: params.clone(),
+                body: (**body).clone(),
+                captured: scope.locals.clone(),
+            }))),
+            ASTNode::Sum(index, from, to, body) => self.series("sum", index, from, to, body, scope),
+            ASTNode::Product(index, from, to, body) => {
+                self.series("prod", index, from, to, body, scope)
+            }
+            ASTNode::Boolean(b) => Ok(Value::Boolean(*b)),
+            ASTNode::PlusMinus(..) => Err(EvalError::new(format!(
+                "Uncertainties need uncertainty mode: {}",
+                node
+            ))),
+            ASTNode::Quantity(..) | ASTNode::Convert(..) => {
+                Err(EvalError::new(format!("Units need units mode: {}", node)))
+            }
+            ASTNode::Located(..)
+            | ASTNode::Number(_)
+            | ASTNode::Variable(_)
+            | ASTNode::Add(..)
+            | ASTNode::Subtract(..)
+            | ASTNode::Divide(..)
+            | ASTNode::Less(..)
+            | ASTNode::LessEqual(..)
+            | ASTNode::Greater(..)
+            | ASTNode::GreaterEqual(..)
+            | ASTNode::Conditional(..)
+            | ASTNode::Call(..) => self.evaluate_in(node, scope),
+        }
+    }
+
+    /// The values of the variables named after the cells of `range`, row by
+    /// row. A large range is matched against the variables instead of
+    /// looking up each of its cells.
+    fn range(&self, range: &Range) -> Vec<Value<T>> {
+        if range.area() <= self.variables.len() as u64 {
+            return range
+                .cells()
+                .filter_map(|cell| self.variables.get(&cell.to_string()).cloned())
+                .collect();
+        }
+        let mut cells: Vec<_> = self
+            .variables
+            .iter()
+            .filter_map(|(name, value)| {
+                let cell = tokens::Cell::parse(name)?;
+                (cell == cell.position() && range.contains(cell)).then_some((cell, value))
+            })
+            .collect();
+        cells.sort_unstable_by_key(|(cell, _)| *cell);
+        cells.into_iter().map(|(_, value)| value.clone()).collect()
+    }
+
+    /// Calls, in order of precedence, a function value bound to `name`, a
+    /// user function, a higher-order built-in or a numeric built-in.
+    fn call(&self, name: &str, args: &[ASTNode], scope: &Scope<T>) -> EvalResult<Value<T>> {
+        let bound = scope.locals.get(name).or_else(|| self.variables.get(name));
+        if let Some(Value::Function(closure)) = bound {
+            let args = self.values(args, scope)?;
+            return self.apply(name, closure, args, scope);
+        }
+        if let Some(function) = self.functions.get(name) {
+            let args = self.values(args, scope)?;
+            return self.enter(
+                name,
+                &function.params,
+                &function.body,
+                HashMap::new(),
+                args,
+                scope,
+            );
+        }
+        if let "map" | "filter" | "reduce" = name {
+            return self.higher_order(name, args, scope);
+        }
+        self.builtin(name, self.values(args, scope)?)
+    }
+
+    /// A numeric built-in, or one that takes a list or matrix.
+    fn builtin(&self, name: &str, args: Vec<Value<T>>) -> EvalResult<Value<T>> {
+        match (name, args.as_slice()) {
+            ("det", [value]) => Ok(Value::Number(as_matrix(value)?.det(&self.context)?)),
+            ("inv", [value]) => Ok(Value::Matrix(as_matrix(value)?.inv(&self.context)?)),
+            ("transpose", [value]) => Ok(Value::Matrix(as_matrix(value)?.transpose())),
+            (
+                "sum" | "avg" | "min" | "max" | "count" | "median" | "stddev",
+                [items @ (Value::List(_) | Value::Matrix(_))],
+            ) => self.aggregate(name, &as_list(items)?),
+            _ => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.to_number(arg))
+                    .collect::<EvalResult<Vec<_>>>()?;
+                Ok(Value::Number(T::call(name, &args)?))
+            }
+        }
+    }
+
+    /// `sum`, `avg`, `min`, `max`, `count`, `median` and `stddev`, the
+    /// sample standard deviation, of a list of numbers.
+    fn aggregate(&self, name: &str, items: &[Value<T>]) -> EvalResult<Value<T>> {
+        let context = &self.context;
+        let count = literal::<T>(&items.len().to_string())?;
+        if name == "count" {
+            return Ok(Value::Number(count));
+        }
+        let numbers = items
+            .iter()
+            .map(|item| self.to_number(item.clone()))
+            .collect::<EvalResult<Vec<_>>>()?;
+        let sum = numbers
+            .iter()
+            .try_fold(literal::<T>("0")?, |sum, x| sum.add(x, context))?;
+        if numbers.is_empty() && name != "sum" {
+            return Err(EvalError::new(format!(
+                "Cannot take {} of an empty list",
+                name
+            )));
+        }
+
+        let result = match name {
+            "sum" => sum,
+            "avg" => sum.div(&count, context)?,
+            "min" | "max" => {
+                let wanted = if name == "min" {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                let mut best = numbers[0].clone();
+                for x in &numbers[1..] {
+                    match x.compare(&best)? {
+                        Some(ordering) if ordering == wanted => best = x.clone(),
+                        Some(_) => {}
+                        None => return Err(unordered(name, x)),
+                    }
+                }
+                best
+            }
+            "median" => {
+                let mut sorted = numbers;
+                let mut failure = None;
+                sorted.sort_by(|a, b| match a.compare(b) {
+                    Ok(Some(ordering)) => ordering,
+                    Ok(None) => {
+                        failure.get_or_insert_with(|| unordered(name, a));
+                        Ordering::Equal
+                    }
+                    Err(error) => {
+                        failure.get_or_insert(error);
+                        Ordering::Equal
+                    }
+                });
+                if let Some(error) = failure {
+                    return Err(error);
+                }
+                let middle = sorted.len() / 2;
+                if sorted.len() % 2 == 1 {
+                    sorted[middle].clone()
+                } else {
+                    sorted[middle - 1]
+                        .add(&sorted[middle], context)?
+                        .div(&literal("2")?, context)?
+                }
+            }
+            _ => {
+                if numbers.len() < 2 {
+                    return Err(EvalError::new(format!(
+                        "Function stddev needs at least 2 items, got {}",
+                        numbers.len()
+                    )));
+                }
+                let mean = sum.div(&count, context)?;
+                let mut squares = literal::<T>("0")?;
+                for x in &numbers {
+                    let deviation = x.sub(&mean, context)?;
+                    squares = squares.add(&deviation.mul(&deviation, context)?, context)?;
+                }
+                let variance = squares.div(&count.sub(&literal("1")?, context)?, context)?;
+                T::call("sqrt", &[variance])?
+            }
+        };
+        Ok(Value::Number(result))
+    }
+
+    /// `map(xs, f)`, `filter(xs, f)` and `reduce(xs, f)` or
+    /// `reduce(xs, f, initial)`, which folds from the left starting with
+    /// `initial` or else the first item.
+    fn higher_order(&self, name: &str, args: &[ASTNode], scope: &Scope<T>) -> EvalResult<Value<T>> {
+        let args = self.values(args, scope)?;
+        let (items, function, initial) = match args.as_slice() {
+            [items, function] => (as_list(items)?, as_function(function)?, None),
+            [items, function, initial] if name == "reduce" => (
+                as_list(items)?,
+                as_function(function)?,
+                Some(initial.clone()),
+            ),
+            _ => return Err(arity_mismatch(name, 2, args.len())),
+        };
+
+        match name {
+            "map" => items
+                .iter()
+                .map(|item| self.apply(name, function, vec![item.clone()], scope))
+                .collect::<EvalResult<_>>()
+                .map(Value::List),
+            "filter" => {
+                let mut kept = Vec::new();
+                for item in items.iter() {
+                    let keep = self.apply(name, function, vec![item.clone()], scope)?;
+                    if self.truth(keep)? {
+                        kept.push(item.clone());
+                    }
+                }
+                Ok(Value::List(kept))
+            }
+            _ => {
+                let mut items = items.iter().cloned();
+                let mut result = initial.or_else(|| items.next()).ok_or_else(|| {
+                    EvalError::new("Cannot reduce an empty list without an initial value")
+                })?;
+                for item in items {
+                    result = self.apply(name, function, vec![result, item], scope)?;
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn apply(
+        &self,
+        name: &str,
+        closure: &Closure<T>,
+        args: Vec<Value<T>>,
+        scope: &Scope<T>,
+    ) -> EvalResult<Value<T>> {
+        let captured = closure.captured.clone();
+        self.enter(name, &closure.params, &closure.body, captured, args, scope)
+    }
+
+    /// Evaluates a function body with `params` bound to `args` on top of
+    /// `locals`, one call deeper than `scope`.
+    fn enter(
+        &self,
+        name: &str,
+        params: &[String],
+        body: &ASTNode,
+        mut locals: HashMap<String, Value<T>>,
+        args: Vec<Value<T>>,
+        scope: &Scope<T>,
+    ) -> EvalResult<Value<T>> {
+        if args.len() != params.len() {
+            return Err(arity_mismatch(name, params.len(), args.len()));
+        }
+        if scope.depth >= self.recursion_limit {
+            return Err(EvalError::new(format!(
+                "Recursion limit exceeded: more than {} nested calls in {}",
+                self.recursion_limit, name
+            )));
+        }
+
+        locals.extend(params.iter().cloned().zip(args));
+        let scope = Scope {
+            locals,
+            depth: scope.depth + 1,
+            terms: Rc::clone(&scope.terms),
+        };
+        self.evaluate_in(body, &scope)
+    }
+
+    /// `sum` or `prod` of `body` with `index` bound to each integer from
+    /// `from` to `to`. Terms combine like `+` and `*` do, so lists and
+    /// matrices work too, and an empty range gives `0` or `1`.
+    fn series(
+        &self,
+        name: &str,
+        index: &str,
+        from: &ASTNode,
+        to: &ASTNode,
+        body: &ASTNode,
+        scope: &Scope<T>,
+    ) -> EvalResult<Value<T>> {
+        let bound = |node| {
+            let bound = self.number(node, scope)?;
+            integer(&bound).ok_or_else(|| {
+                EvalError::new(format!(
+                    "Bounds of {} must be integers, got {}",
+                    name, bound
+                ))
+            })
+        };
+        let (from, to) = (bound(from)?, bound(to)?);
+        let terms = (i128::from(to) - i128::from(from) + 1).max(0);
+        let taken = scope.terms.get();
+        if terms > self.iteration_limit as i128 {
+            return Err(EvalError::new(format!(
+                "Iteration limit exceeded: {} of {} terms, more than {}",
+                name, terms, self.iteration_limit
+            )));
+        }
+        if taken as i128 + terms > self.iteration_limit as i128 {
+            return Err(EvalError::new(format!(
+                "Iteration limit exceeded: {} of {} terms after {} others, more than {} in all",
+                name, terms, taken, self.iteration_limit
+            )));
+        }
+        // Counted up front, so series nested in `body` see these terms too.
+        scope.terms.set(taken + terms as usize);
+
+        // The index shadows any binding of the same name, but only in `body`.
+        let mut inner = Scope {
+            locals: scope.locals.clone(),
+            depth: scope.depth,
+            terms: Rc::clone(&scope.terms),
+        };
+        let mut result = Value::Number(literal(if name == "sum" { "0" } else { "1" })?);
+        for i in from..=to {
+            inner
+                .locals
+                .insert(index.to_string(), Value::Number(integer_literal(i)?));
+            let term = self.evaluate_in(body, &inner)?;
+            result = match name {
+                "sum" => self.broadcast(result, term, Numeric::add)?,
+                _ => self.product(result, term)?,
+            };
+        }
+        Ok(result)
+    }
+
+    fn values(&self, nodes: &[ASTNode], scope: &Scope<T>) -> EvalResult<Vec<Value<T>>> {
+        nodes
+            .iter()
+            .map(|node| self.evaluate_in(node, scope))
+            .collect()
+    }
+
+    fn variable(&self, name: &str, scope: &Scope<T>) -> EvalResult<Value<T>> {
+        if let Some(value) = scope.locals.get(name).or_else(|| self.variables.get(name)) {
+            return Ok(value.clone());
+        }
+        T::constant(name)
+            .map(Value::Number)
+            .ok_or_else(|| EvalError::new(format!("Unknown variable: {}", name)))
+    }
+
+    /// `+` or `-`, applying [`PercentMode::Relative`] to a percentage on
+    /// the right.
+    fn additive(
+        &self,
+        left: &ASTNode,
+        right: &ASTNode,
+        scope: &Scope<T>,
+        op: Operation<T>,
+    ) -> EvalResult<Value<T>> {
+        match right {
+            ASTNode::Percent(n) if self.percent_mode == PercentMode::Relative => {
+                let left = self.number(left, scope)?;
+                let part = left
+                    .mul(&self.number(n, scope)?, &self.context)?
+                    .percent(&self.context)?;
+                Ok(Value::Number(op(&left, &part, &self.context)?))
+            }
+            _ => self.arithmetic(left, right, scope, op),
+        }
+    }
+
+    fn arithmetic(
+        &self,
+        left: &ASTNode,
+        right: &ASTNode,
+        scope: &Scope<T>,
+        op: Operation<T>,
+    ) -> EvalResult<Value<T>> {
+        let left = self.evaluate_in(left, scope)?;
+        let right = self.evaluate_in(right, scope)?;
+        self.broadcast(left, right, op)
+    }
+
+    /// Applies `op` item by item when either operand is a list: a scalar
+    /// combines with every item, and two lists must have the same length.
+    fn broadcast(&self, left: Value<T>, right: Value<T>, op: Operation<T>) -> EvalResult<Value<T>> {
+        let apply = |l: &T, r: &T| op(l, r, &self.context);
+        match (left, right) {
+            (Value::Matrix(l), Value::Matrix(r)) => Ok(Value::Matrix(l.zip_with(&r, apply)?)),
+            (Value::Matrix(l), r @ Value::List(_)) => {
+                Ok(Value::Matrix(l.zip_with(&rows_like(&l, &r)?, apply)?))
+            }
+            (l @ Value::List(_), Value::Matrix(r)) => {
+                Ok(Value::Matrix(rows_like(&r, &l)?.zip_with(&r, apply)?))
+            }
+            (Value::Matrix(l), r) => {
+                let r = self.to_number(r)?;
+                Ok(Value::Matrix(l.map(|l| apply(l, &r))?))
+            }
+            (l, Value::Matrix(r)) => {
+                let l = self.to_number(l)?;
+                Ok(Value::Matrix(r.map(|r| apply(&l, r))?))
+            }
+            (Value::List(l), Value::List(r)) => {
+                if l.len() != r.len() {
+                    return Err(EvalError::new(format!(
+                        "Length mismatch: lists of {} and {} items",
+                        l.len(),
+                        r.len()
+                    )));
+                }
+                l.into_iter()
+                    .zip(r)
+                    .map(|(l, r)| self.broadcast(l, r, op))
+                    .collect::<EvalResult<_>>()
+                    .map(Value::List)
+            }
+            (Value::List(l), r) => l
+                .into_iter()
+                .map(|l| self.broadcast(l, r.clone(), op))
+                .collect::<EvalResult<_>>()
+                .map(Value::List),
+            (l, Value::List(r)) => r
+                .into_iter()
+                .map(|r| self.broadcast(l.clone(), r, op))
+                .collect::<EvalResult<_>>()
+                .map(Value::List),
+            (l, r) => Ok(Value::Number(apply(
+                &self.to_number(l)?,
+                &self.to_number(r)?,
+            )?)),
+        }
+    }
+
+    fn compare(
+        &self,
+        left: &ASTNode,
+        right: &ASTNode,
+        scope: &Scope<T>,
+        holds: fn(Ordering) -> bool,
+    ) -> EvalResult<Value<T>> {
+        let ordering = self
+            .number(left, scope)?
+            .compare(&self.number(right, scope)?)?;
+        Ok(Value::Boolean(ordering.is_some_and(holds)))
+    }
+
+    /// `*`: the matrix product when either side is a matrix, treating a
+    /// list as a column vector on the right and a row vector on the left,
+    /// and otherwise item by item.
+    fn product(&self, left: Value<T>, right: Value<T>) -> EvalResult<Value<T>> {
+        let left = matrix_shaped(left, &right);
+        let right = matrix_shaped(right, &left);
+        match (left, right) {
+            (Value::Matrix(l), Value::Matrix(r)) => Ok(Value::Matrix(l.mul(&r, &self.context)?)),
+            (Value::Matrix(l), Value::List(r)) => {
+                Ok(column_items(&l.mul(&self.column(&r)?, &self.context)?))
+            }
+            (Value::List(l), Value::Matrix(r)) => {
+                let row = self.column(&l)?.transpose();
+                let product = row.mul(&r, &self.context)?;
+                Ok(Value::List(
+                    product.row(0).iter().cloned().map(Value::Number).collect(),
+                ))
+            }
+            (l, r) => self.broadcast(l, r, Numeric::mul),
+        }
+    }
+
+    /// A list of numbers as a one-column matrix.
+    fn column(&self, items: &[Value<T>]) -> EvalResult<Matrix<T>> {
+        let rows = items
+            .iter()
+            .map(|item| Ok(vec![self.to_number(item.clone())?]))
+            .collect::<EvalResult<Vec<_>>>()?;
+        Matrix::from_rows(rows).ok_or_else(|| EvalError::new("Expected a non-empty vector"))
+    }
+
+    fn number(&self, node: &ASTNode, scope: &Scope<T>) -> EvalResult<T> {
+        self.to_number(self.evaluate_in(node, scope)?)
+    }
+
+    fn to_number(&self, value: Value<T>) -> EvalResult<T> {
+        match value {
+            Value::Number(n) => Ok(n),
+            Value::Boolean(b) if self.numeric_booleans => from_bool(b),
+            value => Err(type_mismatch("number", &value)),
+        }
+    }
+
+    fn boolean(&self, node: &ASTNode, scope: &Scope<T>) -> EvalResult<bool> {
+        self.truth(self.evaluate_in(node, scope)?)
+    }
+
+    fn truth(&self, value: Value<T>) -> EvalResult<bool> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            Value::Number(n) if self.numeric_booleans => Ok(n != literal("0")?),
+            value => Err(type_mismatch("boolean", &value)),
+        }
+    }
+
+    fn equals(&self, left: &ASTNode, right: &ASTNode, scope: &Scope<T>) -> EvalResult<bool> {
+        // A matrix equals the list of its rows.
+        let rows = |value: Value<T>| match value {
+            Value::Matrix(_) => as_list(&value).map(|rows| Value::List(rows.into_owned())),
+            value => Ok(value),
+        };
+        match (
+            rows(self.evaluate_in(left, scope)?)?,
+            rows(self.evaluate_in(right, scope)?)?,
+        ) {
+            (Value::Number(l), Value::Number(r)) => Ok(l == r),
+            (Value::Boolean(l), Value::Boolean(r)) => Ok(l == r),
+            (Value::List(l), Value::List(r)) => Ok(l == r),
+            (l, r) if self.numeric_booleans => Ok(as_number(l)? == as_number(r)?),
+            (l, r) => Err(EvalError::new(format!(
+                "Cannot compare {} with {}",
+                l.type_name(),
+                r.type_name()
+            ))),
+        }
+    }
+}
+
+/// An arithmetic operation of the number type, such as [`Numeric::add`].
+type Operation<T> = fn(&T, &T, &<T as Numeric>::Context) -> EvalResult<T>;
+
+fn literal<T: Numeric>(text: &str) -> EvalResult<T> {
+    let number = Number::parse(text).expect("valid literal");
+    T::from_literal(&number)
+}
+
+/// A literal for `value`: a boolean, a number, a negated number or a list
+/// of them.
+fn to_node<T: Numeric>(value: &Value<T>) -> Option<ASTNode> {
+    match value {
+        Value::Number(n) => {
+            let text = n.to_string();
+            match text.strip_prefix('-') {
+                Some(magnitude) => Some(ASTNode::Negate(Box::new(ASTNode::Number(Number::parse(
+                    magnitude,
+                )?)))),
+                None => Some(ASTNode::Number(Number::parse(&text)?)),
+            }
+        }
+        // A literal of lists would evaluate to a matrix, not a list.
+        Value::List(items)
+            if items
+                .iter()
+                .any(|item| matches!(item, Value::List(_) | Value::Matrix(_))) =>
+        {
+            None
+        }
+        Value::List(items) => items
+            .iter()
+            .map(to_node)
+            .collect::<Option<_>>()
+            .map(ASTNode::List),
+        Value::Matrix(matrix) => (0..matrix.rows())
+            .map(|row| {
+                let items = matrix.row(row).iter().cloned().map(Value::Number);
+                items
+                    .map(|item| to_node(&item))
+                    .collect::<Option<_>>()
+                    .map(ASTNode::List)
+            })
+            .collect::<Option<_>>()
+            .map(ASTNode::List),
+        Value::Boolean(b) => Some(ASTNode::Boolean(*b)),
+        Value::Function(_) => None,
+    }
+}
+
+fn is_literal(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Number(_) | ASTNode::Boolean(_) => true,
+        ASTNode::Negate(n) => matches!(**n, ASTNode::Number(_)),
+        ASTNode::List(items) => items.iter().all(is_literal),
+        _ => false,
+    }
+}
+
+fn integer_literal<T: Numeric>(n: i64) -> EvalResult<T> {
+    let magnitude = literal::<T>(&n.unsigned_abs().to_string())?;
+    if n < 0 {
+        magnitude.neg()
+    } else {
+        Ok(magnitude)
+    }
+}
+
+fn from_bool<T: Numeric>(b: bool) -> EvalResult<T> {
+    literal(if b { "1" } else { "0" })
+}
+
+fn as_number<T: Numeric>(value: Value<T>) -> EvalResult<T> {
+    match value {
+        Value::Number(n) => Ok(n),
+        Value::Boolean(b) => from_bool(b),
+        value => Err(type_mismatch("number", &value)),
+    }
+}
+
+fn unordered<T: Display>(name: &str, value: &T) -> EvalError {
+    EvalError::new(format!("Cannot take {} of unordered value {}", name, value))
+}
+
+/// The items of a list, or the rows of a matrix as lists.
+fn as_list<T: Numeric>(value: &Value<T>) -> EvalResult<Cow<'_, [Value<T>]>> {
+    match value {
+        Value::List(items) => Ok(Cow::Borrowed(items)),
+        Value::Matrix(matrix) => Ok(Cow::Owned(
+            (0..matrix.rows())
+                .map(|row| {
+                    Value::List(matrix.row(row).iter().cloned().map(Value::Number).collect())
+                })
+                .collect(),
+        )),
+        value => Err(type_mismatch("list", value)),
+    }
+}
+
+/// A matrix, or the matrix a list of rows spells out.
+fn as_matrix<T: Numeric>(value: &Value<T>) -> EvalResult<Cow<'_, Matrix<T>>> {
+    match value {
+        Value::Matrix(matrix) => Ok(Cow::Borrowed(matrix)),
+        Value::List(items) => as_rows(items)
+            .map(Cow::Owned)
+            .ok_or_else(|| type_mismatch("matrix", value)),
+        value => Err(type_mismatch("matrix", value)),
+    }
+}
+
+/// `list` as a matrix to combine item by item with `matrix`.
+fn rows_like<T: Numeric>(matrix: &Matrix<T>, list: &Value<T>) -> EvalResult<Matrix<T>> {
+    as_matrix(list).map(Cow::into_owned).map_err(|_| {
+        EvalError::new(format!(
+            "Shape mismatch: cannot combine a {} matrix with a list item by item",
+            matrix.shape()
+        ))
+    })
+}
+
+/// The matrix a list literal spells out, if its items are rows of numbers
+/// of the same length.
+fn as_rows<T: Numeric>(items: &[Value<T>]) -> Option<Matrix<T>> {
+    let rows = items
+        .iter()
+        .map(|item| match item {
+            Value::List(row) => row
+                .iter()
+                .map(|x| match x {
+                    Value::Number(n) => Some(n.clone()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Matrix::from_rows(rows)
+}
+
+/// `value` as a matrix if it is a list of rows and `other` is a list or a
+/// matrix, so a list of rows multiplies the same whether it was written as
+/// a literal or built by `map`.
+fn matrix_shaped<T: Numeric>(value: Value<T>, other: &Value<T>) -> Value<T> {
+    if let (Value::List(items), Value::List(_) | Value::Matrix(_)) = (&value, other) {
+        if let Some(matrix) = as_rows(items) {
+            return Value::Matrix(matrix);
+        }
+    }
+    value
+}
+
+/// The single column of `matrix` as a list.
+fn column_items<T: Numeric>(matrix: &Matrix<T>) -> Value<T> {
+    Value::List(
+        (0..matrix.rows())
+            .map(|row| Value::Number(matrix.get(row, 0).clone()))
+            .collect(),
+    )
+}
+
+fn integer_exponent<T: Numeric>(exponent: &T) -> EvalResult<i64> {
+    integer(exponent).ok_or_else(|| {
+        EvalError::new(format!(
+            "Matrix powers need an integer exponent, got {}",
+            exponent
+        ))
+    })
+}
+
+/// `value` as an `i64`, if it is a whole number in range.
+fn integer<T: Numeric>(value: &T) -> Option<i64> {
+    let (magnitude, sign) = match value.to_index() {
+        Ok(n) => (n, 1),
+        Err(_) => (value.neg().ok()?.to_index().ok()?, -1),
+    };
+    i64::try_from(magnitude).ok().map(|n| sign * n)
+}
+
+fn as_function<T>(value: &Value<T>) -> EvalResult<&Closure<T>> {
+    match value {
+        Value::Function(closure) => Ok(closure),
+        value => Err(type_mismatch("function", value)),
+    }
+}
+
+pub(crate) fn unknown_function(name: &str) -> EvalError {
+    EvalError::new(format!("Unknown function: {}", name))
+}
+
+pub(crate) fn arity_mismatch(name: &str, expected: usize, found: usize) -> EvalError {
+    EvalError::new(format!(
+        "Function {} expects {} argument{}, got {}",
+        name,
+        expected,
+        if expected == 1 { "" } else { "s" },
+        found
+    ))
+}
+
+fn type_mismatch<T>(expected: &str, found: &Value<T>) -> EvalError {
+    EvalError::new(format!(
+        "Type mismatch: expected {}, found {}",
+        expected,
+        found.type_name()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval_with(evaluator: &Evaluator, input: &str) -> AppResult<Value> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        Ok(evaluator.evaluate(&ast)?)
+    }
+
+    fn eval(input: &str) -> AppResult<Value> {
+        eval_with(&Evaluator::new(), input)
+    }
+
+    fn eval_implicit(evaluator: &Evaluator, input: &str) -> AppResult<Value> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).implicit_multiplication(true).parse()?;
+        Ok(evaluator.evaluate(&ast)?)
+    }
+
+    #[test]
+    fn arithmetic_precedence() {
+        assert_eq!(eval("3 + 4 * (2 + 1)^2").unwrap(), Value::Number(39.0));
+        assert_eq!(eval("-2^2").unwrap(), Value::Number(-4.0));
+        assert_eq!(eval("2^-1").unwrap(), Value::Number(0.5));
+    }
+
+    #[test]
+    fn comparisons_produce_booleans() {
+        assert_eq!(eval("1 < 2").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("2 <= 1").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("1 + 1 == 2").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("3 != 3").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("!(1 > 2) && 2 >= 2").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("true && !false").unwrap(), Value::Boolean(true));
+        assert_eq!(
+            eval("[true, 1 > 2]").unwrap(),
+            Value::List(vec![Value::Boolean(true), Value::Boolean(false)])
+        );
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        // The right-hand side would fail with a division by zero.
+        assert_eq!(eval("1 > 2 && 1 / 0 > 0").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("1 < 2 || 1 / 0 > 0").unwrap(), Value::Boolean(true));
+        assert!(eval("1 < 2 && 1 / 0 > 0").is_err());
+    }
+
+    #[test]
+    fn conditional_only_evaluates_selected_branch() {
+        assert_eq!(eval("1 < 2 ? 10 : 1 / 0").unwrap(), Value::Number(10.0));
+        assert_eq!(eval("1 > 2 ? 1 / 0 : 20").unwrap(), Value::Number(20.0));
+        assert_eq!(
+            eval("1 > 2 ? 1 : 2 > 3 ? 2 : 3").unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(eval("if(1 < 2, 10, 1 / 0)").unwrap(), Value::Number(10.0));
+        assert_eq!(eval("if(false, 1 / 0, 20)").unwrap(), Value::Number(20.0));
+        assert!(eval("if(true, 1)").is_err());
+    }
+
+    #[test]
+    fn booleans_and_numbers_do_not_mix_by_default() {
+        assert!(eval("1 + (2 < 3)").is_err());
+        assert!(eval("1 && 2").is_err());
+        assert!(eval("1 ? 2 : 3").is_err());
+        assert!(eval("true + 1").is_err());
+        assert!(eval("(1 < 2) == 1").is_err());
+    }
+
+    #[test]
+    fn numeric_booleans_mode() {
+        let evaluator = Evaluator::new().numeric_booleans(true);
+        assert_eq!(
+            eval_with(&evaluator, "1 + (2 < 3)").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "2 && 0").unwrap(),
+            Value::Boolean(false)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "(1 < 2) == 1").unwrap(),
+            Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn modulo_and_integer_division() {
+        assert_eq!(eval("7 % 3").unwrap(), Value::Number(1.0));
+        assert_eq!(eval("7 // 2").unwrap(), Value::Number(3.0));
+        assert_eq!(eval("-7 // 2").unwrap(), Value::Number(-4.0));
+        assert_eq!(eval("-7 % 2").unwrap(), Value::Number(1.0));
+        assert_eq!(eval("7 % (0 - 2)").unwrap(), Value::Number(-1.0));
+        assert_eq!(eval("7 % -3").unwrap(), Value::Number(-2.0));
+        assert_eq!(eval("7 % (-3)").unwrap(), Value::Number(-2.0));
+        assert_eq!(eval("(20%) - 3").unwrap(), Value::Number(-2.8));
+        assert_eq!(eval("7 % [3, 5]").unwrap(), numbers(&[1.0, 2.0]));
+        let evaluator = Evaluator::new().numeric_booleans(true);
+        assert_eq!(eval_with(&evaluator, "7 % !0").unwrap(), Value::Number(0.0));
+        assert!(eval("7 % 0").is_err());
+        assert!(eval("7 // 0").is_err());
+    }
+
+    #[test]
+    fn factorial() {
+        assert_eq!(eval("5!").unwrap(), Value::Number(120.0));
+        assert_eq!(eval("0!").unwrap(), Value::Number(1.0));
+        assert_eq!(eval("2^3!").unwrap(), Value::Number(64.0));
+        assert_eq!(eval("3!! == 720").unwrap(), Value::Boolean(true));
+        assert!(eval("(-3)!").is_err());
+        assert!(eval("2.5!").is_err());
+    }
+
+    #[test]
+    fn percent() {
+        assert_eq!(eval("20%").unwrap(), Value::Number(0.2));
+        assert_eq!(eval("50% * 8").unwrap(), Value::Number(4.0));
+        assert_eq!(eval("200 + 10%").unwrap(), Value::Number(200.1));
+
+        let evaluator = Evaluator::new().percent_mode(PercentMode::Relative);
+        assert_eq!(
+            eval_with(&evaluator, "200 + 10%").unwrap(),
+            Value::Number(220.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "200 - 10%").unwrap(),
+            Value::Number(180.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "200 * 10%").unwrap(),
+            Value::Number(20.0)
+        );
+    }
+
+    #[test]
+    fn variables_and_constants() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_variable("x", Value::Number(3.0));
+        assert_eq!(eval_with(&evaluator, "x * 2").unwrap(), Value::Number(6.0));
+        assert_eq!(
+            eval_with(&evaluator, "pi").unwrap(),
+            Value::Number(std::f64::consts::PI)
+        );
+        assert!(eval_with(&evaluator, "y + 1").is_err());
+
+        evaluator.set_variable("e", Value::Number(1.0));
+        assert_eq!(eval_with(&evaluator, "e").unwrap(), Value::Number(1.0));
+    }
+
+    #[test]
+    fn builtin_functions() {
+        assert_eq!(eval("sqrt(16) + 1").unwrap(), Value::Number(5.0));
+        assert_eq!(eval("max(1, 7, 3)").unwrap(), Value::Number(7.0));
+        assert_eq!(eval("min(2)").unwrap(), Value::Number(2.0));
+        assert_eq!(eval("floor(-2.5)").unwrap(), Value::Number(-3.0));
+        assert_eq!(
+            eval("sqrt(1, 2)").unwrap_err().to_string(),
+            "Error(Eval): Function sqrt expects 1 argument, got 2"
+        );
+        assert!(eval("nope(1)").is_err());
+        assert!(eval("2i").is_err());
+    }
+
+    #[test]
+    fn implicit_multiplication() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_variable("a", Value::Number(5.0));
+        evaluator.set_variable("b", Value::Number(3.0));
+        evaluator.set_variable("x", Value::Number(4.0));
+        assert_eq!(
+            eval_implicit(&evaluator, "2(3+4)").unwrap(),
+            Value::Number(14.0)
+        );
+        assert_eq!(
+            eval_implicit(&evaluator, "(a+b)(a-b)").unwrap(),
+            Value::Number(16.0)
+        );
+        assert_eq!(
+            eval_implicit(&evaluator, "3x").unwrap(),
+            Value::Number(12.0)
+        );
+        assert_eq!(
+            eval_implicit(&evaluator, "2pi").unwrap(),
+            Value::Number(2.0 * std::f64::consts::PI)
+        );
+        assert_eq!(
+            eval_implicit(&evaluator, "1/2x").unwrap(),
+            Value::Number(0.125)
+        );
+
+        // Without complex mode `3i` is a number times the variable `i`.
+        evaluator.set_variable("i", Value::Number(2.0));
+        assert_eq!(eval_implicit(&evaluator, "3i").unwrap(), Value::Number(6.0));
+        assert_eq!(
+            eval_implicit(&evaluator, "sum(i, 1, 10, 2i)").unwrap(),
+            Value::Number(110.0)
+        );
+    }
+
+    fn run(evaluator: &mut Evaluator, input: &str) -> AppResult<Value> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        let program = Parser::new(tokens).locations(locations).parse_program()?;
+        Ok(evaluator.run(&program)?)
+    }
+
+    #[test]
+    fn programs_bind_variables_in_order() {
+        let mut evaluator = Evaluator::new();
+        let program = "let width = 3\nheight = width + 1; area = width * height\n\narea / 2";
+        assert_eq!(run(&mut evaluator, program).unwrap(), Value::Number(6.0));
+        assert_eq!(eval_with(&evaluator, "area").unwrap(), Value::Number(12.0));
+
+        assert_eq!(
+            run(&mut evaluator, "x = 2; x = x * 5").unwrap(),
+            Value::Number(10.0)
+        );
+        assert_eq!(
+            run(&mut evaluator, "total = (1 +\n 2)\ntotal").unwrap(),
+            Value::Number(3.0)
+        );
+        assert!(run(&mut evaluator, "\n;\n").is_err());
+    }
+
+    #[test]
+    fn program_errors_name_the_line() {
+        let mut evaluator = Evaluator::new();
+        let err = run(&mut evaluator, "a = 1\nb = a / 0\nb").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error(Eval): Division by zero in line 2 at column 7"
+        );
+
+        let err = run(&mut evaluator, "a = 1\nb = missing + a").unwrap_err();
+        assert!(err.to_string().ends_with("in line 2 at column 5"));
+        let err = run(&mut evaluator, "a = 1\nb = (-1)!").unwrap_err();
+        assert!(err.to_string().ends_with("in line 2 at column 1"));
+
+        let err = run(&mut evaluator, "a = 1\n\nb = a +").unwrap_err();
+        assert!(err.to_string().ends_with("in line 3 at column 8"));
+        let err = run(&mut evaluator, "z = 1 + ").unwrap_err();
+        assert!(err.to_string().ends_with("in line 1 at column 8"));
+
+        let err = run(&mut evaluator, "let a 1").unwrap_err();
+        assert!(err.to_string().contains("Expected '=' after 'let a'"));
+    }
+
+    #[test]
+    fn user_functions_call_each_other() {
+        let mut evaluator = Evaluator::new();
+        let program =
+            "f(x, y) = x^2 + y\nsquare(x) = f(x, 0)\nk = 10\ng() = k + square(2)\nf(3, 4) + g()";
+        assert_eq!(run(&mut evaluator, program).unwrap(), Value::Number(27.0));
+
+        // Parameters shadow globals and do not leak out of the call.
+        assert_eq!(
+            run(&mut evaluator, "x = 100; f(1, x)").unwrap(),
+            Value::Number(101.0)
+        );
+        assert_eq!(
+            run(&mut evaluator, "abs(x) = 42; abs(-1)").unwrap(),
+            Value::Number(42.0)
+        );
+        assert!(run(&mut evaluator, "f(1)").is_err());
+        assert!(run(&mut evaluator, "f(x, x) = x").is_err());
+        assert!(run(&mut evaluator, "h(x) = 1").is_err());
+    }
+
+    #[test]
+    fn recursion_is_bounded() {
+        let mut evaluator = Evaluator::new();
+        let program = "fact(n) = n <= 1 ? 1 : n * fact(n - 1)\nfact(10)";
+        assert_eq!(
+            run(&mut evaluator, program).unwrap(),
+            Value::Number(3628800.0)
+        );
+
+        // The error points at the call that went too deep.
+        let err = run(&mut evaluator, "forever(n) = forever(n + 1)\nforever(0)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error(Eval): Recursion limit exceeded: more than 128 nested calls in forever in line 1 at column 14"
+        );
+        let depth = "f(n) = n <= 0 ? 0 : 1 + f(n - 1)\nf(100)";
+        assert_eq!(run(&mut evaluator, depth).unwrap(), Value::Number(100.0));
+
+        let mut evaluator = Evaluator::new().recursion_limit(5);
+        assert_eq!(
+            run(
+                &mut evaluator,
+                "fact(n) = n <= 1 ? 1 : n * fact(n - 1); fact(5)"
+            )
+            .unwrap(),
+            Value::Number(120.0)
+        );
+        assert!(run(&mut evaluator, "fact(6)").is_err());
+    }
+
+    #[test]
+    fn recursion_limit_is_capped() {
+        // The cap is meant for a main thread stack, larger than a test's.
+        let deep = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| {
+                let mut evaluator = Evaluator::new().recursion_limit(100_000);
+                let program = "f(n) = n <= 0 ? 0 : 1 + f(n - 1)\nf(100000)";
+                run(&mut evaluator, program).unwrap_err().to_string()
+            })
+            .unwrap();
+        assert_eq!(
+            deep.join().unwrap(),
+            "Error(Eval): Recursion limit exceeded: more than 256 nested calls in f in line 1 at column 25"
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_innermost_location() {
+        let ast = parse_located("1 + (2 * missing)").unwrap();
+        let error = Evaluator::new().evaluate(&ast).unwrap_err();
+        assert_eq!(error.message, "Unknown variable: missing");
+        let location = error.location.unwrap();
+        assert_eq!((location.line(), location.col()), (1, 10));
+        assert_eq!(
+            error.to_string(),
+            "Unknown variable: missing in line 1 at column 10"
+        );
+        assert!(EvalError::new("Division by zero").location.is_none());
+    }
+
+    #[test]
+    fn definitions_can_be_listed_and_removed() {
+        let mut evaluator = Evaluator::new();
+        run(&mut evaluator, "g(x) = x + 1\nf(a, b) = a * b\n1").unwrap();
+        let listed: Vec<String> = evaluator
+            .functions()
+            .map(|(name, function)| format!("{}{}", name, function))
+            .collect();
+        assert_eq!(listed, ["f(a, b) = (a * b)", "g(x) = (x + 1)"]);
+
+        assert!(evaluator.remove_function("g").is_some());
+        assert!(evaluator.function("g").is_none());
+        assert!(eval_with(&evaluator, "g(1)").is_err());
+
+        let function = evaluator.function("f").unwrap().clone();
+        evaluator.define_function("times", function);
+        assert_eq!(
+            eval_with(&evaluator, "times(6, 7)").unwrap(),
+            Value::Number(42.0)
+        );
+    }
+
+    fn numbers(values: &[f64]) -> Value {
+        Value::List(values.iter().copied().map(Value::Number).collect())
+    }
+
+    #[test]
+    fn lambdas_are_values() {
+        let mut evaluator = Evaluator::new();
+        assert_eq!(
+            run(&mut evaluator, "double = x => x * 2\ndouble(21)").unwrap(),
+            Value::Number(42.0)
+        );
+        assert_eq!(
+            run(&mut evaluator, "hyp = (a, b) => sqrt(a^2 + b^2); hyp(3, 4)").unwrap(),
+            Value::Number(5.0)
+        );
+        assert_eq!(
+            run(&mut evaluator, "answer = () => 42; answer()").unwrap(),
+            Value::Number(42.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "double").unwrap().to_string(),
+            "x => (x * 2)"
+        );
+        assert!(eval_with(&evaluator, "double + 1").is_err());
+        assert!(eval_with(&evaluator, "double(1, 2)").is_err());
+    }
+
+    #[test]
+    fn closures_capture_enclosing_parameters() {
+        let mut evaluator = Evaluator::new();
+        let program = "adder(k) = x => x + k\nadd5 = adder(5)\nk = 100\nadd5(1)";
+        assert_eq!(run(&mut evaluator, program).unwrap(), Value::Number(6.0));
+
+        // Globals are read when the closure is called.
+        let program = "rate = 2\nscale = x => x * rate\nrate = 3\nscale(5)";
+        assert_eq!(run(&mut evaluator, program).unwrap(), Value::Number(15.0));
+
+        // A parameter shadows a captured binding of the same name, and a
+        // closure's own bindings do not leak into functions it calls.
+        let program = "outer(x) = x => x * 10\nf = outer(1)\nf(2)";
+        assert_eq!(run(&mut evaluator, program).unwrap(), Value::Number(20.0));
+        let program = "show() = y\nwrap(y) = () => show()\ng = wrap(1)\ng()";
+        assert!(run(&mut evaluator, program).is_err());
+
+        // Recursion through a global lambda is bounded like user functions.
+        let program = "fact = n => n <= 1 ? 1 : n * fact(n - 1)\nfact(5)";
+        assert_eq!(run(&mut evaluator, program).unwrap(), Value::Number(120.0));
+        assert!(run(&mut evaluator, "loop = n => loop(n)\nloop(1)").is_err());
+    }
+
+    #[test]
+    fn higher_order_builtins() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_variable("xs", numbers(&[1.0, 2.0, 3.0, 4.0]));
+        evaluator.set_variable("empty", numbers(&[]));
+
+        assert_eq!(
+            eval_with(&evaluator, "map(xs, x => x * 2)").unwrap(),
+            numbers(&[2.0, 4.0, 6.0, 8.0])
+        );
+        assert_eq!(
+            eval_with(&evaluator, "filter(xs, x => x % 2 == 0)").unwrap(),
+            numbers(&[2.0, 4.0])
+        );
+        assert_eq!(
+            eval_with(&evaluator, "reduce(xs, (acc, x) => acc + x)").unwrap(),
+            Value::Number(10.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "reduce(xs, (acc, x) => acc * x, 10)").unwrap(),
+            Value::Number(240.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "reduce(empty, (acc, x) => acc + x, 0)").unwrap(),
+            Value::Number(0.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "map(xs, x => x)")
+                .unwrap()
+                .to_string(),
+            "[1, 2, 3, 4]"
+        );
+
+        let program = "threshold = 2\nabove(t) = filter(xs, x => x > t)\nabove(threshold)";
+        assert_eq!(run(&mut evaluator, program).unwrap(), numbers(&[3.0, 4.0]));
+
+        assert!(eval_with(&evaluator, "reduce(empty, (acc, x) => acc + x)").is_err());
+        assert!(eval_with(&evaluator, "map(xs, 2)").is_err());
+        assert!(eval_with(&evaluator, "map(1, x => x)").is_err());
+        assert!(eval_with(&evaluator, "map(xs, (a, b) => a)").is_err());
+        assert!(eval_with(&evaluator, "filter(xs, x => x)").is_err());
+    }
+
+    #[test]
+    fn list_literals_and_indexing() {
+        assert_eq!(eval("[1, 2 + 3, 4]").unwrap(), numbers(&[1.0, 5.0, 4.0]));
+        assert_eq!(eval("[]").unwrap(), numbers(&[]));
+        assert_eq!(eval("[10, 20, 30][1]").unwrap(), Value::Number(20.0));
+        assert_eq!(eval("[[1, 2], [3, 4]][1][0]").unwrap(), Value::Number(3.0));
+        assert_eq!(eval("[1, 2] == [1, 2]").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("[1, [2, 3]]").unwrap().to_string(), "[1, [2, 3]]");
+
+        let mut evaluator = Evaluator::new();
+        assert_eq!(
+            run(&mut evaluator, "xs = [\n  3,\n  1,\n  2\n]\nxs[2]").unwrap(),
+            Value::Number(2.0)
+        );
+        assert!(eval("[1, 2][2]").is_err());
+        assert!(eval("[1, 2][-1]").is_err());
+        assert!(eval("[1, 2][0.5]").is_err());
+        assert!(eval("3[0]").is_err());
+    }
+
+    #[test]
+    fn aggregates_over_lists() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_variable("xs", numbers(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]));
+        let aggregate = |expression| eval_with(&evaluator, expression).unwrap();
+        assert_eq!(aggregate("sum(xs)"), Value::Number(40.0));
+        assert_eq!(aggregate("avg(xs)"), Value::Number(5.0));
+        assert_eq!(aggregate("min(xs)"), Value::Number(2.0));
+        assert_eq!(aggregate("max(xs)"), Value::Number(9.0));
+        assert_eq!(aggregate("count(xs)"), Value::Number(8.0));
+        assert_eq!(aggregate("median(xs)"), Value::Number(4.5));
+        assert_eq!(aggregate("median([3, 1, 2])"), Value::Number(2.0));
+        assert_eq!(
+            aggregate("stddev(xs)"),
+            Value::Number((32.0f64 / 7.0).sqrt())
+        );
+        assert_eq!(aggregate("max(1, 3, 2)"), Value::Number(3.0));
+        assert_eq!(aggregate("sum([])"), Value::Number(0.0));
+        assert_eq!(aggregate("count([])"), Value::Number(0.0));
+
+        assert!(eval("avg([])").is_err());
+        assert!(eval("median([])").is_err());
+        assert!(eval("stddev([1])").is_err());
+        assert!(eval("sum([1, [2]])").is_err());
+        assert!(eval("sqrt([4])").is_err());
+    }
+
+    #[test]
+    fn sums_and_products_bind_their_index() {
+        assert_eq!(eval("sum(i, 1, 10, i^2)").unwrap(), Value::Number(385.0));
+        assert_eq!(eval("prod(k, 1, 5, k)").unwrap(), Value::Number(120.0));
+        assert_eq!(eval("sum(i, -2, 2, i)").unwrap(), Value::Number(0.0));
+        assert_eq!(eval("sum(i, 3, 1, i)").unwrap(), Value::Number(0.0));
+        assert_eq!(eval("prod(i, 3, 1, i)").unwrap(), Value::Number(1.0));
+        assert_eq!(
+            eval("sum(i, 1, 3, sum(j, 1, i, j))").unwrap(),
+            Value::Number(10.0)
+        );
+        assert_eq!(eval("sum(i, 1, 3, [i, 1])").unwrap(), numbers(&[6.0, 3.0]));
+
+        let mut evaluator = Evaluator::new();
+        evaluator.set_variable("i", Value::Number(100.0));
+        assert_eq!(
+            eval_with(&evaluator, "sum(i, 1, 3, i) + i").unwrap(),
+            Value::Number(106.0)
+        );
+        assert_eq!(
+            eval("sum(j, 1, 3, j) + j").unwrap_err().to_string(),
+            "Error(Eval): Unknown variable: j"
+        );
+        assert_eq!(
+            run(
+                &mut evaluator,
+                "fact(n) = prod(k, 1, n, k)
+fact(6)"
+            )
+            .unwrap(),
+            Value::Number(720.0)
+        );
+        assert_eq!(
+            run(&mut evaluator, "map([1, 2, 3], n => sum(i, 1, n, i))").unwrap(),
+            numbers(&[1.0, 3.0, 6.0])
+        );
+    }
+
+    #[test]
+    fn series_are_bounded() {
+        let evaluator = Evaluator::new().iteration_limit(10);
+        assert_eq!(
+            eval_with(&evaluator, "sum(i, 1, 10, i)").unwrap(),
+            Value::Number(55.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "sum(i, 1, 11, i)")
+                .unwrap_err()
+                .to_string(),
+            "Error(Eval): Iteration limit exceeded: sum of 11 terms, more than 10"
+        );
+        // Nested series share the limit, so the work stays bounded.
+        assert_eq!(
+            eval_with(&evaluator, "sum(i, 1, 2, sum(j, 1, 4, 1))").unwrap(),
+            Value::Number(8.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "sum(i, 1, 3, sum(j, 1, 3, 1))")
+                .unwrap_err()
+                .to_string(),
+            "Error(Eval): Iteration limit exceeded: sum of 3 terms after 9 others, more than 10 in all"
+        );
+        let evaluator = Evaluator::new().iteration_limit(1000);
+        assert!(eval_with(
+            &evaluator,
+            "sum(i, 1, 1000, sum(j, 1, 1000, sum(k, 1, 100, 1)))"
+        )
+        .is_err());
+        assert_eq!(
+            eval("prod(i, 1, 2.5, i)").unwrap_err().to_string(),
+            "Error(Eval): Bounds of prod must be integers, got 2.5"
+        );
+    }
+
+    #[test]
+    fn piecewise_evaluates_only_the_chosen_branch() {
+        let tariff =
+            "piecewise(x <= 100, 0.1 * x, x <= 500, 10 + 0.2 * (x - 100), 90 + 0.3 * (x - 500))";
+        let mut evaluator = Evaluator::new();
+        for (x, expected) in [(50.0, 5.0), (100.0, 10.0), (300.0, 50.0), (600.0, 120.0)] {
+            evaluator.set_variable("x", Value::Number(x));
+            assert_eq!(
+                eval_with(&evaluator, tariff).unwrap(),
+                Value::Number(expected)
+            );
+        }
+
+        evaluator.set_variable("x", Value::Number(0.0));
+        assert_eq!(
+            eval_with(&evaluator, "piecewise(x == 0, 0, 1 / x)").unwrap(),
+            Value::Number(0.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "piecewise(x > 0, missing, x < 0, missing, 7)").unwrap(),
+            Value::Number(7.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "piecewise(x < 0, 1, 1 / x)")
+                .unwrap_err()
+                .to_string(),
+            "Error(Eval): Division by zero"
+        );
+        assert!(eval_with(&evaluator, "piecewise(x, 1, 2)").is_err());
+    }
+
+    fn parse_located(input: &str) -> AppResult<ASTNode> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        Ok(Parser::new(tokens).locations(locations).parse()?)
+    }
+
+    fn partially_evaluated(evaluator: &Evaluator, input: &str) -> String {
+        let ast = parse_located(input).unwrap();
+        evaluator.partially_evaluate(&ast).to_string()
+    }
+
+    #[test]
+    fn partial_evaluation_folds_what_becomes_constant() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_variable("discount", Value::Number(0.1));
+        evaluator.set_variable("shipping", Value::Number(5.0));
+        assert_eq!(
+            partially_evaluated(
+                &evaluator,
+                "base * qty * (1 - discount) + (qty > 100 ? 0 : shipping * 2)"
+            ),
+            "(((base * qty) * 0.9) + ((qty > 100) ? 0 : 10))"
+        );
+        assert_eq!(
+            partially_evaluated(&evaluator, "shipping * (1 + discount) - 10"),
+            "(-4.5)"
+        );
+        assert_eq!(
+            partially_evaluated(&evaluator, "2 * pi * r"),
+            "(6.283185307179586 * r)"
+        );
+        assert_eq!(
+            partially_evaluated(&evaluator, "map(xs, x => x * (shipping + 1))"),
+            "map(xs, (x => (x * 6)))"
+        );
+        assert_eq!(
+            partially_evaluated(&evaluator, "x + 1 / 0"),
+            "(x + (1 / 0))"
+        );
+    }
+
+    #[test]
+    fn partial_evaluation_keeps_only_reachable_branches() {
+        let mut evaluator = Evaluator::new();
+        let tiers = "piecewise(tier == 1, price * 0.9, tier == 2, price * 0.8, qty > 10, price * 0.95, price)";
+        evaluator.set_variable("tier", Value::Number(2.0));
+        assert_eq!(partially_evaluated(&evaluator, tiers), "(price * 0.8)");
+
+        evaluator.set_variable("tier", Value::Number(3.0));
+        assert_eq!(
+            partially_evaluated(&evaluator, tiers),
+            "piecewise((qty > 10), (price * 0.95), price)"
+        );
+        evaluator.set_variable("qty", Value::Number(5.0));
+        evaluator.set_variable("price", Value::Number(40.0));
+        assert_eq!(partially_evaluated(&evaluator, tiers), "40");
+        assert_eq!(
+            partially_evaluated(&evaluator, "piecewise(x > 0, 1, qty < 10, 2, 3)"),
+            "piecewise((x > 0), 1, 2)"
+        );
+        evaluator.set_variable("member", Value::Boolean(true));
+        assert_eq!(
+            partially_evaluated(&evaluator, "member && x > 0"),
+            "(true && (x > 0))"
+        );
+    }
+
+    #[test]
+    fn residuals_evaluate_like_the_original() {
+        let formula =
+            "base * qty * (1 - discount) + sum(i, 1, n, i * qty) - (discount > 0.2 ? 1 : 0)";
+        let ast = parse_located(formula).unwrap();
+        let mut specialised = Evaluator::new();
+        specialised.set_variable("discount", Value::Number(0.25));
+        specialised.set_variable("n", Value::Number(3.0));
+        let residual = specialised.partially_evaluate(&ast);
+        assert_eq!(
+            residual.to_string(),
+            "((((base * qty) * 0.75) + (Σ[i = 1..3] (i * qty))) - 1)"
+        );
+
+        let mut full = Evaluator::new();
+        for (name, value) in [("base", 12.0), ("qty", 7.0), ("discount", 0.25), ("n", 3.0)] {
+            full.set_variable(name, Value::Number(value));
+        }
+        let mut rest = Evaluator::new();
+        rest.set_variable("base", Value::Number(12.0));
+        rest.set_variable("qty", Value::Number(7.0));
+        assert_eq!(
+            rest.evaluate(&residual).unwrap(),
+            full.evaluate(&ast).unwrap()
+        );
+    }
+
+    #[test]
+    fn residuals_keep_what_may_not_be_built_in() {
+        let mut evaluator = Evaluator::new();
+        run(
+            &mut evaluator,
+            "g(x) = x * 10; fs = [x => x + 1]; xs = map([1, 3], x => [x, x + 1])",
+        )
+        .unwrap();
+        for (input, residual) in [
+            ("map(fs, g => g(2))", "map(fs, (g => g(2)))"),
+            ("map([x => x + 1], g => g(2))", "[3]"),
+            ("map(fs, sqrt => sqrt(16))", "map(fs, (sqrt => sqrt(16)))"),
+            ("g(2) + sqrt(16)", "(g(2) + 4)"),
+            ("xs * xs", "(xs * xs)"),
+        ] {
+            let ast = parse_located(input).unwrap();
+            let folded = evaluator.partially_evaluate(&ast);
+            assert_eq!(folded.to_string(), residual);
+            assert_eq!(
+                evaluator.evaluate(&folded).unwrap(),
+                evaluator.evaluate(&ast).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_broadcasts_over_lists() {
+        assert_eq!(eval("[1, 2, 3] * 2").unwrap(), numbers(&[2.0, 4.0, 6.0]));
+        assert_eq!(eval("10 - [1, 2]").unwrap(), numbers(&[9.0, 8.0]));
+        assert_eq!(eval("[1, 2] + [10, 20]").unwrap(), numbers(&[11.0, 22.0]));
+        assert_eq!(eval("[2, 3]^2").unwrap(), numbers(&[4.0, 9.0]));
+        assert_eq!(eval("[7, -7] % 3").unwrap(), numbers(&[1.0, 2.0]));
+        assert_eq!(eval("-[1, -2]").unwrap(), numbers(&[-1.0, 2.0]));
+        assert_eq!(
+            eval("[[1, 2], [3, 4]] / 2").unwrap().to_string(),
+            "[[0.5, 1], [1.5, 2]]"
+        );
+        assert_eq!(
+            eval("sum([1, 2, 3] * [4, 5, 6])").unwrap(),
+            Value::Number(32.0)
+        );
+
+        let err = eval("[1, 2] + [1, 2, 3]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error(Eval): Length mismatch: lists of 2 and 3 items"
+        );
+        assert!(eval("[1, 2] / [1, 0]").is_err());
+        assert!(eval("[1] < 2").is_err());
+    }
+}
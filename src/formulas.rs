@@ -0,0 +1,382 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    evaluator::{EvalError, EvalResult, Evaluator, Value},
+    graph,
+    lexer::Lexer,
+    parser::{ASTNode, Parser, ParserError, Program, Statement},
+    result::{AppError, AppResult},
+};
+
+/// A named formula, the names it reads and its last computed value.
+#[derive(Debug)]
+struct Formula {
+    node: ASTNode,
+    variables: BTreeSet<String>,
+    calls: BTreeSet<String>,
+    value: EvalResult<Value>,
+}
+
+/// A set of named formulas such as `net = gross - tax` and
+/// `tax = gross * rate`, kept up to date as their inputs change.
+///
+/// A formula depends on the free variables of its expression and on any
+/// formula it calls, as in `y = double(3)` after `double = x => x * 2`.
+/// Names that no formula defines are inputs, set with
+/// [`FormulaSet::set_input`]. Formulas are evaluated in topological order,
+/// and a definition that would make a formula depend on itself is rejected
+/// with the path of the cycle, such as `net -> tax -> net`. A lambda calling
+/// itself, as in `f = n => n <= 1 ? 1 : n * f(n - 1)`, is not a cycle.
+///
+/// Changing an input or a definition recomputes only the formulas that
+/// depend on it, directly or through other formulas, and returns their
+/// names in the order they were recomputed. A formula that fails keeps the
+/// error as its value, and so do the formulas that depend on it.
+#[derive(Debug, Default)]
+pub struct FormulaSet {
+    formulas: BTreeMap<String, Formula>,
+    evaluator: Evaluator,
+}
+
+impl FormulaSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the definitions in `source`, one per line or
+    /// separated by `;`, in order. If one fails, the ones before it are
+    /// kept.
+    pub fn define(&mut self, source: &str) -> AppResult<Vec<String>> {
+        let program = parse_program(source)?;
+        let mut changed = Vec::new();
+        let mut failure = None;
+        for statement in &program.statements {
+            match self.insert(statement) {
+                Ok(name) => changed.push(name),
+                Err(error) => {
+                    failure = Some(error);
+                    break;
+                }
+            }
+        }
+
+        let recomputed = self.recompute(changed);
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(recomputed),
+        }
+    }
+
+    /// Sets an input that formulas may read and recomputes those that
+    /// depend on it.
+    pub fn set_input(&mut self, name: &str, value: Value) -> EvalResult<Vec<String>> {
+        if self.formulas.contains_key(name) {
+            return Err(EvalError::new(format!("{} is defined by a formula", name)));
+        }
+        self.evaluator.set_variable(name, value);
+        Ok(self.recompute(vec![name.to_string()]))
+    }
+
+    /// Removes a formula or an input and recomputes the formulas that
+    /// depended on it.
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        self.formulas.remove(name);
+        self.evaluator.remove_variable(name);
+        self.recompute(vec![name.to_string()])
+    }
+
+    /// The value of the formula `name`, or `None` if there is none.
+    pub fn value(&self, name: &str) -> Option<&EvalResult<Value>> {
+        self.formulas.get(name).map(|formula| &formula.value)
+    }
+
+    pub fn formula(&self, name: &str) -> Option<&ASTNode> {
+        self.formulas.get(name).map(|formula| &formula.node)
+    }
+
+    /// The free variables of the formula `name` and the formulas it calls.
+    pub fn dependencies(&self, name: &str) -> Vec<&str> {
+        let Some(formula) = self.formulas.get(name) else {
+            return Vec::new();
+        };
+        let calls = formula
+            .calls
+            .iter()
+            .filter(|call| self.formulas.contains_key(*call));
+        let names: BTreeSet<_> = formula.variables.iter().chain(calls).collect();
+        names.into_iter().map(String::as_str).collect()
+    }
+
+    /// The formulas that read `name` directly.
+    pub fn dependents(&self, name: &str) -> Vec<&str> {
+        self.formulas
+            .iter()
+            .filter(|(_, formula)| formula.variables.contains(name) || formula.calls.contains(name))
+            .map(|(dependent, _)| dependent.as_str())
+            .collect()
+    }
+
+    /// Every formula, each after the formulas it depends on.
+    pub fn order(&self) -> Vec<&str> {
+        let order = graph::topological_order(self.formulas.keys().cloned(), |name| {
+            self.formulas.contains_key(name).then(|| self.edges(name))
+        });
+        order
+            .iter()
+            .filter_map(|name| self.formulas.get_key_value(name))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Adds the definition in `statement`, unless it closes a cycle.
+    fn insert(&mut self, statement: &Statement) -> AppResult<String> {
+        let (name, node) = definition(statement)?;
+        let references = node.references();
+        let mut calls: BTreeSet<_> = references.functions.into_keys().collect();
+        // A lambda's body only runs once it is called, after the definition,
+        // so a lambda may call itself.
+        if matches!(node.unlocated(), ASTNode::Lambda(..)) {
+            calls.remove(name);
+        }
+        let formula = Formula {
+            node: node.clone(),
+            variables: references.variables.into_keys().collect(),
+            calls,
+            value: Ok(Value::Number(0.0)),
+        };
+        let previous = self.formulas.insert(name.to_string(), formula);
+        if let Some(cycle) = graph::cycle_through(&name.to_string(), |name| self.edges(name)) {
+            match previous {
+                Some(previous) => self.formulas.insert(name.to_string(), previous),
+                None => self.formulas.remove(name),
+            };
+            return Err(
+                EvalError::new(format!("Circular definition: {}", cycle.join(" -> "))).into(),
+            );
+        }
+        Ok(name.to_string())
+    }
+
+    /// The formulas the formula `name` reads.
+    fn edges(&self, name: &String) -> Vec<String> {
+        let Some(formula) = self.formulas.get(name) else {
+            return Vec::new();
+        };
+        formula
+            .variables
+            .union(&formula.calls)
+            .filter(|dependency| self.formulas.contains_key(*dependency))
+            .cloned()
+            .collect()
+    }
+
+    /// Recomputes the formulas among `changed` and every formula that
+    /// depends on one of `changed`, in topological order.
+    fn recompute(&mut self, changed: Vec<String>) -> Vec<String> {
+        let mut affected = BTreeSet::new();
+        let mut pending = changed;
+        while let Some(name) = pending.pop() {
+            if self.formulas.contains_key(&name) {
+                affected.insert(name.clone());
+            }
+            pending.extend(
+                self.dependents(&name)
+                    .into_iter()
+                    .filter(|dependent| !affected.contains(*dependent))
+                    .map(str::to_string),
+            );
+        }
+
+        let order = graph::topological_order(affected.iter().cloned(), |name| {
+            affected.contains(name).then(|| self.edges(name))
+        });
+        for name in &order {
+            let Some(formula) = self.formulas.get(name) else {
+                continue;
+            };
+            let failed = self.edges(name).into_iter().find(|dependency| {
+                matches!(
+                    self.formulas.get(dependency),
+                    Some(Formula { value: Err(_), .. })
+                )
+            });
+            let value = match failed {
+                Some(dependency) => Err(EvalError::new(format!(
+                    "Formula {} depends on {}, which has an error",
+                    name, dependency
+                ))),
+                None => self.evaluator.evaluate(&formula.node),
+            };
+
+            match &value {
+                Ok(value) => self.evaluator.set_variable(name.clone(), value.clone()),
+                Err(_) => {
+                    self.evaluator.remove_variable(name);
+                }
+            }
+            if let Some(formula) = self.formulas.get_mut(name) {
+                formula.value = value;
+            }
+        }
+        order
+    }
+}
+
+fn parse_program(source: &str) -> AppResult<Program> {
+    let (tokens, locations) = Lexer::new(source).tokenize()?;
+    Ok(Parser::new(tokens).locations(locations).parse_program()?)
+}
+
+fn definition(statement: &Statement) -> AppResult<(&str, &ASTNode)> {
+    match statement {
+        Statement::Located(_, statement) => definition(statement),
+        Statement::Assign(name, node) => Ok((name, node)),
+        statement => Err(AppError::Parser(ParserError(format!(
+            "Expected a definition such as `net = gross - tax`, got {}",
+            statement
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(formulas: &FormulaSet, name: &str) -> f64 {
+        match formulas.value(name) {
+            Some(Ok(Value::Number(n))) => *n,
+            value => panic!("{} is {:?}", name, value),
+        }
+    }
+
+    fn payroll() -> FormulaSet {
+        let mut formulas = FormulaSet::new();
+        formulas
+            .define("net = gross - tax\ntax = gross * rate; fee = 5\ntotal = fee * 2")
+            .unwrap();
+        formulas.set_input("gross", Value::Number(1000.0)).unwrap();
+        formulas.set_input("rate", Value::Number(0.2)).unwrap();
+        formulas
+    }
+
+    #[test]
+    fn empty_sources_define_nothing() {
+        let mut formulas = FormulaSet::new();
+        for source in ["", "  ", "\n;"] {
+            assert_eq!(formulas.define(source).unwrap(), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn formulas_are_evaluated_in_dependency_order() {
+        let formulas = payroll();
+        assert_eq!(number(&formulas, "tax"), 200.0);
+        assert_eq!(number(&formulas, "net"), 800.0);
+        assert_eq!(formulas.dependencies("net"), ["gross", "tax"]);
+        assert_eq!(formulas.dependents("gross"), ["net", "tax"]);
+        assert_eq!(formulas.order(), ["fee", "tax", "net", "total"]);
+    }
+
+    #[test]
+    fn changes_recompute_only_their_dependents() {
+        let mut formulas = payroll();
+        assert_eq!(
+            formulas.set_input("rate", Value::Number(0.5)).unwrap(),
+            ["tax", "net"]
+        );
+        assert_eq!(number(&formulas, "net"), 500.0);
+        assert_eq!(formulas.define("fee = 6").unwrap(), ["fee", "total"]);
+        assert_eq!(number(&formulas, "total"), 12.0);
+        assert_eq!(
+            formulas.set_input("unused", Value::Number(1.0)).unwrap(),
+            Vec::<String>::new()
+        );
+        assert!(formulas.set_input("tax", Value::Number(1.0)).is_err());
+
+        assert_eq!(formulas.remove("tax"), ["net"]);
+        assert_eq!(
+            formulas
+                .value("net")
+                .unwrap()
+                .as_ref()
+                .unwrap_err()
+                .to_string(),
+            "Unknown variable: tax in line 1 at column 15"
+        );
+    }
+
+    #[test]
+    fn bound_names_are_not_dependencies() {
+        let mut formulas = FormulaSet::new();
+        formulas
+            .define("scale = x => x * k\ny = scale(3) + sum(i, 1, n, i)")
+            .unwrap();
+        assert_eq!(formulas.dependencies("scale"), ["k"]);
+        assert_eq!(formulas.dependencies("y"), ["n", "scale"]);
+
+        formulas.set_input("n", Value::Number(3.0)).unwrap();
+        assert_eq!(
+            formulas.set_input("k", Value::Number(2.0)).unwrap(),
+            ["scale", "y"]
+        );
+        assert_eq!(number(&formulas, "y"), 12.0);
+    }
+
+    #[test]
+    fn cycles_are_rejected_with_their_path() {
+        let mut formulas = payroll();
+        assert_eq!(
+            formulas
+                .define("gross = net * 1.25")
+                .unwrap_err()
+                .to_string(),
+            "Error(Eval): Circular definition: gross -> net -> gross"
+        );
+        assert!(formulas.formula("gross").is_none());
+        assert_eq!(
+            formulas.define("a = 1; b = b + a").unwrap_err().to_string(),
+            "Error(Eval): Circular definition: b -> b"
+        );
+        assert_eq!(number(&formulas, "a"), 1.0);
+        assert!(formulas.define("2 + 2").is_err());
+        assert_eq!(
+            formulas.define("c = c(1)").unwrap_err().to_string(),
+            "Error(Eval): Circular definition: c -> c"
+        );
+    }
+
+    #[test]
+    fn lambdas_may_call_themselves() {
+        let mut formulas = FormulaSet::new();
+        formulas
+            .define(
+                "f = n => n <= 1 ? 1 : n * f(n - 1)
+y = f(5)",
+            )
+            .unwrap();
+        assert_eq!(formulas.dependencies("f"), Vec::<&str>::new());
+        assert_eq!(formulas.dependencies("y"), ["f"]);
+        assert_eq!(number(&formulas, "y"), 120.0);
+        assert_eq!(formulas.order(), ["f", "y"]);
+    }
+
+    #[test]
+    fn errors_reach_dependent_formulas() {
+        let mut formulas = FormulaSet::new();
+        formulas
+            .define("ratio = a / b\npercent = ratio * 100")
+            .unwrap();
+        assert_eq!(
+            formulas
+                .value("percent")
+                .unwrap()
+                .as_ref()
+                .unwrap_err()
+                .to_string(),
+            "Formula percent depends on ratio, which has an error"
+        );
+        formulas.set_input("a", Value::Number(1.0)).unwrap();
+        formulas.set_input("b", Value::Number(4.0)).unwrap();
+        assert_eq!(number(&formulas, "percent"), 25.0);
+    }
+}
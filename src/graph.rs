@@ -0,0 +1,71 @@
+use std::collections::BTreeSet;
+
+/// A path of dependencies from `start` back to itself, such as `[a, b, a]`,
+/// if there is one.
+///
+/// Like [`topological_order`] it keeps its own stack, since a long chain of
+/// dependencies would overflow the call stack.
+pub(crate) fn cycle_through<K: Ord + Clone>(
+    start: &K,
+    dependencies: impl Fn(&K) -> Vec<K>,
+) -> Option<Vec<K>> {
+    let reversed = |node: &K| -> Vec<K> { dependencies(node).into_iter().rev().collect() };
+
+    let mut path = vec![start.clone()];
+    let mut pending = vec![reversed(start)];
+    let mut seen = BTreeSet::from([start.clone()]);
+    while let Some(candidates) = pending.last_mut() {
+        match candidates.pop() {
+            Some(next) if next == *start => {
+                path.push(next);
+                return Some(path);
+            }
+            Some(next) => {
+                if seen.insert(next.clone()) {
+                    pending.push(reversed(&next));
+                    path.push(next);
+                }
+            }
+            None => {
+                pending.pop();
+                path.pop();
+            }
+        }
+    }
+    None
+}
+
+/// `nodes` each after the ones it depends on. Dependencies for which
+/// `dependencies` gives `None` are not part of the graph and are left out.
+/// The graph must have no cycles.
+pub(crate) fn topological_order<K: Ord + Clone>(
+    nodes: impl IntoIterator<Item = K>,
+    dependencies: impl Fn(&K) -> Option<Vec<K>>,
+) -> Vec<K> {
+    let mut order = Vec::new();
+    let mut seen = BTreeSet::new();
+    for root in nodes {
+        // A node is pushed again as `finished` to come off after its
+        // dependencies.
+        let mut stack = vec![(root, false)];
+        while let Some((node, finished)) = stack.pop() {
+            if finished {
+                order.push(node);
+                continue;
+            }
+            let Some(next) = dependencies(&node) else {
+                continue;
+            };
+            if !seen.insert(node.clone()) {
+                continue;
+            }
+            stack.push((node, true));
+            stack.extend(
+                next.into_iter()
+                    .filter(|dependency| !seen.contains(dependency))
+                    .map(|dependency| (dependency, false)),
+            );
+        }
+    }
+    order
+}
@@ -0,0 +1,421 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{
+    evaluator::{EvalError, EvalResult},
+    parser::ASTNode,
+    tokens::Number,
+};
+
+/// What happens when a result does not fit the [`IntegerWidth`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    #[default]
+    Error,
+    /// Two's complement wrap-around, like `i64::wrapping_add`.
+    Wrapping,
+    /// Clamp to the smallest or largest representable value.
+    Saturating,
+}
+
+/// How `/` treats a non-zero remainder. `//` always floors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerDivision {
+    /// `7 / 2` is an error; only exact quotients are allowed.
+    #[default]
+    Exact,
+    /// `7 / 2` is `3` and `-7 / 2` is `-3`.
+    Truncate,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerWidth {
+    #[default]
+    I64,
+    I128,
+}
+
+/// The primitive operations [`IntegerEvaluator`] needs from `i64` and `i128`.
+trait Int: Copy + Ord + Display + FromStr + TryFrom<i128> {
+    const ZERO: Self;
+    const ONE: Self;
+    const MIN: Self;
+    const MAX: Self;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn checked_pow(self, exponent: u32) -> Option<Self>;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn wrapping_div(self, other: Self) -> Self;
+    fn wrapping_pow(self, exponent: u32) -> Self;
+    fn rem(self, other: Self) -> Self;
+    fn from_u32(n: u32) -> Self;
+    fn to_u32(self) -> Option<u32>;
+}
+
+macro_rules! impl_int {
+    ($t:ty) => {
+        impl Int for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn checked_add(self, other: Self) -> Option<Self> {
+                <$t>::checked_add(self, other)
+            }
+            fn checked_sub(self, other: Self) -> Option<Self> {
+                <$t>::checked_sub(self, other)
+            }
+            fn checked_mul(self, other: Self) -> Option<Self> {
+                <$t>::checked_mul(self, other)
+            }
+            fn checked_div(self, other: Self) -> Option<Self> {
+                <$t>::checked_div(self, other)
+            }
+            fn checked_pow(self, exponent: u32) -> Option<Self> {
+                <$t>::checked_pow(self, exponent)
+            }
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+            fn wrapping_sub(self, other: Self) -> Self {
+                <$t>::wrapping_sub(self, other)
+            }
+            fn wrapping_mul(self, other: Self) -> Self {
+                <$t>::wrapping_mul(self, other)
+            }
+            fn wrapping_div(self, other: Self) -> Self {
+                <$t>::wrapping_div(self, other)
+            }
+            fn wrapping_pow(self, exponent: u32) -> Self {
+                <$t>::wrapping_pow(self, exponent)
+            }
+            fn rem(self, other: Self) -> Self {
+                <$t>::wrapping_rem(self, other)
+            }
+            fn from_u32(n: u32) -> Self {
+                n as $t
+            }
+            fn to_u32(self) -> Option<u32> {
+                u32::try_from(self).ok()
+            }
+        }
+    };
+}
+
+impl_int!(i64);
+impl_int!(i128);
+
+/// Evaluates an [`ASTNode`] over checked `i64` or `i128` integers.
+///
+/// Literals must be written without a decimal point. Every operation is
+/// checked and an overflow is handled according to the [`OverflowPolicy`];
+/// division by zero is always an error. Only arithmetic is supported.
+#[derive(Debug, Default)]
+pub struct IntegerEvaluator {
+    width: IntegerWidth,
+    overflow: OverflowPolicy,
+    division: IntegerDivision,
+    variables: HashMap<String, i128>,
+}
+
+impl IntegerEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn width(mut self, width: IntegerWidth) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    pub fn division(mut self, division: IntegerDivision) -> Self {
+        self.division = division;
+        self
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: i128) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn evaluate(&self, node: &ASTNode) -> EvalResult<i128> {
+        match self.width {
+            IntegerWidth::I64 => self.eval::<i64>(node).map(i128::from),
+            IntegerWidth::I128 => self.eval::<i128>(node),
+        }
+    }
+
+    fn eval<T: Int>(&self, node: &ASTNode) -> EvalResult<T> {
+        match node {
+            ASTNode::Located(_, node) => self.eval(node),
+            ASTNode::Number(n) => literal(n, ""),
+            ASTNode::Variable(name) => {
+                let value = self
+                    .variables
+                    .get(name)
+                    .ok_or_else(|| EvalError::new(format!("Unknown variable: {}", name)))?;
+                T::try_from(*value)
+                    .map_err(|_| EvalError::new(format!("Variable out of range: {}", name)))
+            }
+            ASTNode::Add(l, r) => {
+                let (a, b) = (self.eval::<T>(l)?, self.eval::<T>(r)?);
+                self.overflowing(
+                    a.checked_add(b),
+                    || a.wrapping_add(b),
+                    || {
+                        if b > T::ZERO {
+                            T::MAX
+                        } else {
+                            T::MIN
+                        }
+                    },
+                )
+            }
+            ASTNode::Subtract(l, r) => {
+                let (a, b) = (self.eval::<T>(l)?, self.eval::<T>(r)?);
+                self.overflowing(
+                    a.checked_sub(b),
+                    || a.wrapping_sub(b),
+                    || {
+                        if b < T::ZERO {
+                            T::MAX
+                        } else {
+                            T::MIN
+                        }
+                    },
+                )
+            }
+            ASTNode::Multiply(l, r) => {
+                let (a, b) = (self.eval::<T>(l)?, self.eval::<T>(r)?);
+                self.overflowing(
+                    a.checked_mul(b),
+                    || a.wrapping_mul(b),
+                    || {
+                        if (a < T::ZERO) == (b < T::ZERO) {
+                            T::MAX
+                        } else {
+                            T::MIN
+                        }
+                    },
+                )
+            }
+            // A negated literal is read as one, so `-9223372036854775808`
+            // fits an `i64` even though its magnitude does not.
+            ASTNode::Negate(n) => {
+                if let ASTNode::Number(n) = n.unlocated() {
+                    return literal(n, "-");
+                }
+                let a = self.eval::<T>(n)?;
+                self.overflowing(
+                    T::ZERO.checked_sub(a),
+                    || T::ZERO.wrapping_sub(a),
+                    || T::MAX,
+                )
+            }
+            ASTNode::Divide(l, r) => {
+                let (a, b) = self.division_operands::<T>(l, r)?;
+                if self.division == IntegerDivision::Exact && a.rem(b) != T::ZERO {
+                    return Err(EvalError::new(format!("Inexact division: {} / {}", a, b)));
+                }
+                self.quotient(a, b)
+            }
+            ASTNode::IntDivide(l, r) => {
+                let (a, b) = self.division_operands::<T>(l, r)?;
+                let quotient = self.quotient(a, b)?;
+                if a.rem(b) != T::ZERO && (a < T::ZERO) != (b < T::ZERO) {
+                    Ok(quotient.wrapping_sub(T::ONE))
+                } else {
+                    Ok(quotient)
+                }
+            }
+            ASTNode::Modulo(l, r) => {
+                let (a, b) = self.division_operands::<T>(l, r)?;
+                let remainder = a.rem(b);
+                if remainder != T::ZERO && (remainder < T::ZERO) != (b < T::ZERO) {
+                    Ok(remainder.wrapping_add(b))
+                } else {
+                    Ok(remainder)
+                }
+            }
+            ASTNode::Power(l, r) => {
+                let (a, b) = (self.eval::<T>(l)?, self.eval::<T>(r)?);
+                let exponent = b.to_u32().ok_or_else(|| {
+                    EvalError::new(format!("Exponent must be a non-negative integer: {}", b))
+                })?;
+                self.overflowing(
+                    a.checked_pow(exponent),
+                    || a.wrapping_pow(exponent),
+                    || {
+                        if a < T::ZERO && exponent % 2 == 1 {
+                            T::MIN
+                        } else {
+                            T::MAX
+                        }
+                    },
+                )
+            }
+            ASTNode::Factorial(n) => {
+                let n = self.eval::<T>(n)?;
+                let n = n.to_u32().ok_or_else(|| {
+                    EvalError::new(format!("Factorial of a negative number: {}", n))
+                })?;
+                let mut product = T::ONE;
+                for k in 2..=n {
+                    let k = T::from_u32(k);
+                    product = self.overflowing(
+                        product.checked_mul(k),
+                        || product.wrapping_mul(k),
+                        || T::MAX,
+                    )?;
+                }
+                Ok(product)
+            }
+            node => Err(EvalError::new(format!(
+                "Not supported in integer mode: {}",
+                node
+            ))),
+        }
+    }
+
+    fn division_operands<T: Int>(&self, left: &ASTNode, right: &ASTNode) -> EvalResult<(T, T)> {
+        let a = self.eval::<T>(left)?;
+        let b = self.eval::<T>(right)?;
+        if b == T::ZERO {
+            return Err(EvalError::new("Division by zero"));
+        }
+        Ok((a, b))
+    }
+
+    /// Truncating `a / b`; only `MIN / -1` can overflow.
+    fn quotient<T: Int>(&self, a: T, b: T) -> EvalResult<T> {
+        self.overflowing(a.checked_div(b), || a.wrapping_div(b), || T::MAX)
+    }
+
+    fn overflowing<T: Int>(
+        &self,
+        checked: Option<T>,
+        wrapping: impl FnOnce() -> T,
+        saturating: impl FnOnce() -> T,
+    ) -> EvalResult<T> {
+        match (checked, self.overflow) {
+            (Some(value), _) => Ok(value),
+            (None, OverflowPolicy::Error) => Err(EvalError::new("Integer overflow")),
+            (None, OverflowPolicy::Wrapping) => Ok(wrapping()),
+            (None, OverflowPolicy::Saturating) => Ok(saturating()),
+        }
+    }
+}
+
+/// The integer `sign` followed by the digits of `n`.
+fn literal<T: Int>(n: &Number, sign: &str) -> EvalResult<T> {
+    if !n.is_integer() {
+        return Err(EvalError::new(format!(
+            "Non-integer literal in integer mode: {}",
+            n
+        )));
+    }
+    format!("{}{}", sign, n.text())
+        .parse()
+        .map_err(|_| EvalError::new(format!("Integer literal out of range: {}{}", sign, n)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval_with(evaluator: &IntegerEvaluator, input: &str) -> AppResult<i128> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        Ok(evaluator.evaluate(&ast)?)
+    }
+
+    fn eval(input: &str) -> AppResult<i128> {
+        eval_with(&IntegerEvaluator::new(), input)
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        assert_eq!(eval("2 + 3 * 4").unwrap(), 14);
+        assert_eq!(eval("2^62").unwrap(), 1 << 62);
+        assert_eq!(eval("-7 // 2").unwrap(), -4);
+        assert_eq!(eval("-7 % 2").unwrap(), 1);
+        assert_eq!(eval("20!").unwrap(), 2432902008176640000);
+        assert_eq!(eval("9007199254740993 + 0").unwrap(), 9007199254740993);
+        assert!(eval("1.5 + 1").is_err());
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("2 < 3").is_err());
+    }
+
+    #[test]
+    fn division_policy() {
+        assert_eq!(eval("8 / 2").unwrap(), 4);
+        assert!(eval("7 / 2").is_err());
+
+        let truncate = IntegerEvaluator::new().division(IntegerDivision::Truncate);
+        assert_eq!(eval_with(&truncate, "7 / 2").unwrap(), 3);
+        assert_eq!(eval_with(&truncate, "-7 / 2").unwrap(), -3);
+    }
+
+    #[test]
+    fn overflow_policies() {
+        assert!(eval("9223372036854775807 + 1").is_err());
+        assert!(eval("21!").is_err());
+        assert!(eval("9223372036854775808").is_err());
+        assert_eq!(eval("-9223372036854775808").unwrap(), i64::MIN as i128);
+        assert_eq!(
+            eval("-9223372036854775809").unwrap_err().to_string(),
+            "Error(Eval): Integer literal out of range: -9223372036854775809"
+        );
+        assert!(eval("-(9223372036854775807 + 1)").is_err());
+
+        let wrapping = IntegerEvaluator::new().overflow(OverflowPolicy::Wrapping);
+        assert_eq!(
+            eval_with(&wrapping, "9223372036854775807 + 1").unwrap(),
+            i64::MIN as i128
+        );
+        assert_eq!(eval_with(&wrapping, "2^64").unwrap(), 0);
+
+        let saturating = IntegerEvaluator::new().overflow(OverflowPolicy::Saturating);
+        assert_eq!(
+            eval_with(&saturating, "9223372036854775807 + 1").unwrap(),
+            i64::MAX as i128
+        );
+        assert_eq!(
+            eval_with(&saturating, "-3 * 9223372036854775807").unwrap(),
+            i64::MIN as i128
+        );
+        assert_eq!(
+            eval_with(&saturating, "(-2)^63 - 1").unwrap(),
+            i64::MIN as i128
+        );
+    }
+
+    #[test]
+    fn i128_width() {
+        let wide = IntegerEvaluator::new().width(IntegerWidth::I128);
+        assert_eq!(
+            eval_with(&wide, "9223372036854775807 + 1").unwrap(),
+            i64::MAX as i128 + 1
+        );
+        assert_eq!(
+            eval_with(&wide, "-170141183460469231731687303715884105728").unwrap(),
+            i128::MIN
+        );
+        assert_eq!(
+            eval_with(&wide, "2^127 - 1 + 2^127")
+                .unwrap_err()
+                .to_string(),
+            "Error(Eval): Integer overflow"
+        );
+    }
+}
@@ -0,0 +1,696 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use crate::{
+    evaluator::{arity_mismatch, unknown_function, EvalError, EvalResult},
+    parser::ASTNode,
+    rational::Rational,
+    tokens::Number,
+};
+
+/// A closed interval `[lo, hi]` of reals. Either end may be infinite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    lo: f64,
+    hi: f64,
+}
+
+impl Interval {
+    /// Returns `None` unless `lo <= hi` and the interval holds at least one
+    /// finite number.
+    pub fn new(lo: f64, hi: f64) -> Option<Self> {
+        (lo <= hi && lo < f64::INFINITY && hi > f64::NEG_INFINITY).then_some(Interval { lo, hi })
+    }
+
+    pub fn point(x: f64) -> Self {
+        Interval { lo: x, hi: x }
+    }
+
+    pub fn lo(&self) -> f64 {
+        self.lo
+    }
+
+    pub fn hi(&self) -> f64 {
+        self.hi
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.lo.is_finite() && self.hi.is_finite()
+    }
+
+    fn whole() -> Self {
+        Interval {
+            lo: f64::NEG_INFINITY,
+            hi: f64::INFINITY,
+        }
+    }
+
+    fn is_point(&self) -> bool {
+        self.lo == self.hi
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        Interval {
+            lo: add(self.lo, other.lo, Rounding::Down),
+            hi: add(self.hi, other.hi, Rounding::Up),
+        }
+    }
+
+    fn neg(self) -> Interval {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+
+    fn mul(self, other: Interval) -> Interval {
+        let corners = |rounding| {
+            [
+                mul(self.lo, other.lo, rounding),
+                mul(self.lo, other.hi, rounding),
+                mul(self.hi, other.lo, rounding),
+                mul(self.hi, other.hi, rounding),
+            ]
+        };
+        Interval {
+            lo: corners(Rounding::Down)
+                .into_iter()
+                .fold(f64::INFINITY, f64::min),
+            hi: corners(Rounding::Up)
+                .into_iter()
+                .fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    /// Divides, splitting into two unbounded pieces when the divisor
+    /// straddles zero.
+    fn div(self, divisor: Interval) -> EvalResult<Vec<Interval>> {
+        let Interval { lo: a, hi: b } = self;
+        let Interval { lo: c, hi: d } = divisor;
+        if c == 0.0 && d == 0.0 {
+            return Err(EvalError::new("Division by zero"));
+        }
+
+        if !divisor.contains(0.0) {
+            if self.is_bounded() && divisor.is_bounded() {
+                let corners = |rounding| {
+                    [
+                        div(a, c, rounding),
+                        div(a, d, rounding),
+                        div(b, c, rounding),
+                        div(b, d, rounding),
+                    ]
+                };
+                return Ok(vec![Interval {
+                    lo: corners(Rounding::Down)
+                        .into_iter()
+                        .fold(f64::INFINITY, f64::min),
+                    hi: corners(Rounding::Up)
+                        .into_iter()
+                        .fold(f64::NEG_INFINITY, f64::max),
+                }]);
+            }
+            let reciprocal = Interval {
+                lo: div(1.0, d, Rounding::Down),
+                hi: div(1.0, c, Rounding::Up),
+            };
+            return Ok(vec![self.mul(reciprocal)]);
+        }
+
+        let below = |hi| Interval {
+            lo: f64::NEG_INFINITY,
+            hi,
+        };
+        let above = |lo| Interval {
+            lo,
+            hi: f64::INFINITY,
+        };
+        Ok(if self.contains(0.0) {
+            vec![Interval::whole()]
+        } else if b < 0.0 {
+            match (c == 0.0, d == 0.0) {
+                (true, _) => vec![below(div(b, d, Rounding::Up))],
+                (_, true) => vec![above(div(b, c, Rounding::Down))],
+                _ => vec![
+                    below(div(b, d, Rounding::Up)),
+                    above(div(b, c, Rounding::Down)),
+                ],
+            }
+        } else {
+            match (c == 0.0, d == 0.0) {
+                (true, _) => vec![above(div(a, d, Rounding::Down))],
+                (_, true) => vec![below(div(a, c, Rounding::Up))],
+                _ => vec![
+                    below(div(a, c, Rounding::Up)),
+                    above(div(a, d, Rounding::Down)),
+                ],
+            }
+        })
+    }
+
+    /// `self^n` for `n > 0`, without the dependency problem of repeated
+    /// interval multiplication: `[-1, 2]^2` is `[0, 4]`.
+    fn powi(self, n: u32) -> Interval {
+        let signed = |x: f64, rounding: Rounding| {
+            if x >= 0.0 {
+                powi(x, n, rounding)
+            } else {
+                -powi(-x, n, rounding.opposite())
+            }
+        };
+        if n % 2 == 1 {
+            Interval {
+                lo: signed(self.lo, Rounding::Down),
+                hi: signed(self.hi, Rounding::Up),
+            }
+        } else if self.lo >= 0.0 {
+            Interval {
+                lo: powi(self.lo, n, Rounding::Down),
+                hi: powi(self.hi, n, Rounding::Up),
+            }
+        } else if self.hi <= 0.0 {
+            Interval {
+                lo: powi(-self.hi, n, Rounding::Down),
+                hi: powi(-self.lo, n, Rounding::Up),
+            }
+        } else {
+            Interval {
+                lo: 0.0,
+                hi: powi(self.hi.max(-self.lo), n, Rounding::Up),
+            }
+        }
+    }
+
+    fn pow(self, exponent: Interval) -> EvalResult<Vec<Interval>> {
+        let n = exponent.lo;
+        if exponent.is_point() && n.fract() == 0.0 && n.abs() <= u32::MAX as f64 {
+            return match n {
+                0.0 => Ok(vec![Interval::point(1.0)]),
+                _ if n > 0.0 => Ok(vec![self.powi(n as u32)]),
+                _ => Interval::point(1.0).div(self.powi(-n as u32)),
+            };
+        }
+
+        if self.lo < 0.0 {
+            return Err(EvalError::new(format!(
+                "Non-integer power of an interval with negative values: {}",
+                self
+            )));
+        }
+        // x^y is monotonic in each argument for x >= 0, so the corners bound
+        // it.
+        let corners = [
+            self.lo.powf(exponent.lo),
+            self.lo.powf(exponent.hi),
+            self.hi.powf(exponent.lo),
+            self.hi.powf(exponent.hi),
+        ];
+        Ok(vec![Interval {
+            lo: libm(
+                corners.into_iter().fold(f64::INFINITY, f64::min),
+                Rounding::Down,
+            )
+            .max(0.0),
+            hi: libm(
+                corners.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                Rounding::Up,
+            ),
+        }])
+    }
+
+    fn sqrt(self) -> EvalResult<Interval> {
+        if self.lo < 0.0 {
+            return Err(EvalError::new(format!(
+                "Square root of an interval with negative values: {}",
+                self
+            )));
+        }
+        Ok(Interval {
+            lo: sqrt(self.lo, Rounding::Down),
+            hi: sqrt(self.hi, Rounding::Up),
+        })
+    }
+
+    fn exp(self) -> Interval {
+        Interval {
+            lo: libm(self.lo.exp(), Rounding::Down).max(0.0),
+            hi: libm(self.hi.exp(), Rounding::Up),
+        }
+    }
+
+    fn ln(self) -> EvalResult<Interval> {
+        if self.lo < 0.0 {
+            return Err(EvalError::new(format!(
+                "Logarithm of an interval with negative values: {}",
+                self
+            )));
+        }
+        Ok(Interval {
+            lo: libm(self.lo.ln(), Rounding::Down),
+            hi: libm(self.hi.ln(), Rounding::Up),
+        })
+    }
+
+    fn abs(self) -> Interval {
+        if self.lo >= 0.0 {
+            self
+        } else if self.hi <= 0.0 {
+            self.neg()
+        } else {
+            Interval {
+                lo: 0.0,
+                hi: self.hi.max(-self.lo),
+            }
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+/// A union of disjoint intervals, sorted in increasing order.
+///
+/// Evaluation normally yields a single interval; dividing by an interval
+/// that straddles zero yields two unbounded pieces, such as
+/// `[-inf, -1] ∪ [1, inf]` for `1 / [-1, 1]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalSet {
+    pieces: Vec<Interval>,
+}
+
+impl IntervalSet {
+    fn from_pieces(mut pieces: Vec<Interval>) -> Self {
+        pieces.sort_by(|a, b| a.lo.total_cmp(&b.lo));
+        let mut merged: Vec<Interval> = Vec::with_capacity(pieces.len());
+        for piece in pieces {
+            match merged.last_mut() {
+                Some(last) if piece.lo <= last.hi => last.hi = last.hi.max(piece.hi),
+                _ => merged.push(piece),
+            }
+        }
+        IntervalSet { pieces: merged }
+    }
+
+    pub fn pieces(&self) -> &[Interval] {
+        &self.pieces
+    }
+
+    /// The smallest single interval enclosing every piece.
+    pub fn hull(&self) -> Interval {
+        Interval {
+            lo: self.pieces[0].lo,
+            hi: self.pieces[self.pieces.len() - 1].hi,
+        }
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.pieces.iter().any(|piece| piece.contains(x))
+    }
+
+    /// Whether every value of the set lies in `[lo, hi]`, which proves the
+    /// evaluated formula stays within those limits for every input.
+    pub fn is_within(&self, lo: f64, hi: f64) -> bool {
+        let hull = self.hull();
+        lo <= hull.lo && hull.hi <= hi
+    }
+}
+
+impl From<Interval> for IntervalSet {
+    fn from(interval: Interval) -> Self {
+        IntervalSet {
+            pieces: vec![interval],
+        }
+    }
+}
+
+impl Display for IntervalSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ∪ ")?;
+            }
+            write!(f, "{}", piece)?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates an [`ASTNode`] over intervals, giving a set guaranteed to
+/// enclose every value the expression takes when each variable ranges over
+/// its interval.
+///
+/// Bounds are rounded outwards: `+`, `-`, `*`, `/` and `sqrt` round each
+/// bound by at most one step in the safe direction, literals that are not
+/// exactly representable are widened to the neighbouring floats, and results
+/// of `exp`, `ln` and non-integer powers are widened by two steps on the
+/// assumption that the platform's maths library is accurate to within one.
+/// Like any interval method the enclosure can be wider than the true range
+/// when a variable appears more than once, as in `x - x`.
+#[derive(Debug, Default)]
+pub struct IntervalEvaluator {
+    variables: HashMap<String, Interval>,
+}
+
+impl IntervalEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: Interval) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn evaluate(&self, node: &ASTNode) -> EvalResult<IntervalSet> {
+        match node {
+            ASTNode::Located(_, node) => self.evaluate(node),
+            ASTNode::Number(n) => Ok(literal(n).into()),
+            ASTNode::Variable(name) => self.variable(name).map(IntervalSet::from),
+            ASTNode::Add(l, r) => self.binary(l, r, |a, b| Ok(vec![a.add(b)])),
+            ASTNode::Subtract(l, r) => self.binary(l, r, |a, b| Ok(vec![a.add(b.neg())])),
+            ASTNode::Multiply(l, r) => self.binary(l, r, |a, b| Ok(vec![a.mul(b)])),
+            ASTNode::Divide(l, r) => self.binary(l, r, Interval::div),
+            ASTNode::Power(l, r) => self.binary(l, r, Interval::pow),
+            ASTNode::Negate(n) => self.unary(n, |a| Ok(a.neg())),
+            ASTNode::Percent(n) => {
+                let value = self.evaluate(n)?;
+                combine(&value, &Interval::point(100.0).into(), Interval::div)
+            }
+            ASTNode::Call(name, args) => {
+                let function: fn(Interval) -> EvalResult<Interval> = match name.as_str() {
+                    "sqrt" => Interval::sqrt,
+                    "exp" => |a| Ok(a.exp()),
+                    "ln" => Interval::ln,
+                    "abs" => |a| Ok(a.abs()),
+                    _ => return Err(unknown_function(name)),
+                };
+                match args.as_slice() {
+                    [arg] => self.unary(arg, function),
+                    _ => Err(arity_mismatch(name, 1, args.len())),
+                }
+            }
+            node => Err(EvalError::new(format!(
+                "Not supported in interval mode: {}",
+                node
+            ))),
+        }
+    }
+
+    fn variable(&self, name: &str) -> EvalResult<Interval> {
+        if let Some(value) = self.variables.get(name) {
+            return Ok(*value);
+        }
+        let constant = match name {
+            "pi" => std::f64::consts::PI,
+            "e" => std::f64::consts::E,
+            _ => return Err(EvalError::new(format!("Unknown variable: {}", name))),
+        };
+        Ok(Interval {
+            lo: constant.next_down(),
+            hi: constant.next_up(),
+        })
+    }
+
+    fn unary(
+        &self,
+        operand: &ASTNode,
+        op: impl Fn(Interval) -> EvalResult<Interval>,
+    ) -> EvalResult<IntervalSet> {
+        let pieces = self
+            .evaluate(operand)?
+            .pieces
+            .into_iter()
+            .map(op)
+            .collect::<EvalResult<_>>()?;
+        Ok(IntervalSet::from_pieces(pieces))
+    }
+
+    fn binary(
+        &self,
+        left: &ASTNode,
+        right: &ASTNode,
+        op: impl Fn(Interval, Interval) -> EvalResult<Vec<Interval>>,
+    ) -> EvalResult<IntervalSet> {
+        combine(&self.evaluate(left)?, &self.evaluate(right)?, op)
+    }
+}
+
+/// Applies `op` to every pair of pieces.
+fn combine(
+    left: &IntervalSet,
+    right: &IntervalSet,
+    op: impl Fn(Interval, Interval) -> EvalResult<Vec<Interval>>,
+) -> EvalResult<IntervalSet> {
+    let mut pieces = Vec::new();
+    for a in &left.pieces {
+        for b in &right.pieces {
+            pieces.extend(op(*a, *b)?);
+        }
+    }
+    Ok(IntervalSet::from_pieces(pieces))
+}
+
+/// The literal itself when a float holds it exactly, otherwise the floats
+/// either side of it.
+fn literal(n: &Number) -> Interval {
+    let value = n.value();
+    let exact = Rational::parse_decimal(n.text()).is_some_and(|r| {
+        let numerator = r.numerator().to_i128();
+        let denominator = r.denominator().to_i128();
+        matches!(
+            (numerator, denominator),
+            (Some(n), Some(d)) if n.unsigned_abs() <= 1 << f64::MANTISSA_DIGITS && (d as u128).is_power_of_two()
+        )
+    });
+    if exact {
+        Interval::point(value)
+    } else {
+        Interval {
+            lo: value.next_down(),
+            hi: value.next_up(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Rounding {
+    Down,
+    Up,
+}
+
+impl Rounding {
+    fn opposite(self) -> Self {
+        match self {
+            Rounding::Down => Rounding::Up,
+            Rounding::Up => Rounding::Down,
+        }
+    }
+
+    /// Moves the rounded-to-nearest `value` one step in this direction if the
+    /// exact result lies that way, `error` having the sign of exact minus
+    /// `value`.
+    fn adjust(self, value: f64, error: f64) -> f64 {
+        match self {
+            Rounding::Down if error < 0.0 => value.next_down(),
+            Rounding::Up if error > 0.0 => value.next_up(),
+            _ => value,
+        }
+    }
+
+    fn widen(self, value: f64) -> f64 {
+        match self {
+            Rounding::Down => value.next_down(),
+            Rounding::Up => value.next_up(),
+        }
+    }
+}
+
+// The error terms below are exact (TwoSum and fused multiply-add) except when
+// the result underflows, where the bound is simply widened.
+
+fn add(a: f64, b: f64, rounding: Rounding) -> f64 {
+    let sum = a + b;
+    if sum.is_infinite() {
+        return if a.is_finite() && b.is_finite() {
+            rounding.adjust(sum, -sum)
+        } else {
+            sum
+        };
+    }
+    let b_virtual = sum - a;
+    let error = (a - (sum - b_virtual)) + (b - b_virtual);
+    rounding.adjust(sum, error)
+}
+
+fn mul(a: f64, b: f64, rounding: Rounding) -> f64 {
+    if a == 0.0 || b == 0.0 {
+        return 0.0;
+    }
+    let product = a * b;
+    if !a.is_finite() || !b.is_finite() {
+        return product;
+    }
+    if product.is_infinite() {
+        return rounding.adjust(product, -product);
+    }
+    if product.abs() < f64::MIN_POSITIVE {
+        return rounding.widen(product);
+    }
+    rounding.adjust(product, a.mul_add(b, -product))
+}
+
+fn div(a: f64, b: f64, rounding: Rounding) -> f64 {
+    if a == 0.0 {
+        return 0.0;
+    }
+    let quotient = a / b;
+    if !a.is_finite() || !b.is_finite() {
+        return quotient;
+    }
+    if quotient.is_infinite() {
+        return rounding.adjust(quotient, -quotient);
+    }
+    if quotient.abs() < f64::MIN_POSITIVE {
+        return rounding.widen(quotient);
+    }
+    let remainder = (-quotient).mul_add(b, a);
+    rounding.adjust(quotient, remainder * b.signum())
+}
+
+fn sqrt(x: f64, rounding: Rounding) -> f64 {
+    let root = x.sqrt();
+    if root == 0.0 || root.is_infinite() {
+        return root;
+    }
+    rounding.adjust(root, (-root).mul_add(root, x))
+}
+
+/// `x^n` for `x >= 0`, rounding every step the same way.
+fn powi(x: f64, mut n: u32, rounding: Rounding) -> f64 {
+    let mut base = x;
+    let mut result = 1.0;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = mul(result, base, rounding);
+        }
+        base = mul(base, base, rounding);
+        n >>= 1;
+    }
+    result
+}
+
+fn libm(value: f64, rounding: Rounding) -> f64 {
+    if value.is_infinite() {
+        return value;
+    }
+    rounding.widen(rounding.widen(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval_with(evaluator: &IntervalEvaluator, input: &str) -> AppResult<IntervalSet> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        Ok(evaluator.evaluate(&ast)?)
+    }
+
+    fn eval(input: &str) -> AppResult<IntervalSet> {
+        eval_with(&IntervalEvaluator::new(), input)
+    }
+
+    fn interval(lo: f64, hi: f64) -> Interval {
+        Interval::new(lo, hi).unwrap()
+    }
+
+    #[test]
+    fn exact_results_stay_tight() {
+        let mut evaluator = IntervalEvaluator::new();
+        evaluator.set_variable("x", interval(1.0, 2.0));
+        evaluator.set_variable("y", interval(-3.0, 4.0));
+        assert_eq!(
+            eval_with(&evaluator, "x + y").unwrap().to_string(),
+            "[-2, 6]"
+        );
+        assert_eq!(
+            eval_with(&evaluator, "x - y").unwrap().to_string(),
+            "[-3, 5]"
+        );
+        assert_eq!(
+            eval_with(&evaluator, "x * y").unwrap().to_string(),
+            "[-6, 8]"
+        );
+        assert_eq!(
+            eval_with(&evaluator, "y / x").unwrap().to_string(),
+            "[-3, 4]"
+        );
+        assert_eq!(eval_with(&evaluator, "y^2").unwrap().to_string(), "[0, 16]");
+        assert_eq!(
+            eval_with(&evaluator, "y^3").unwrap().to_string(),
+            "[-27, 64]"
+        );
+        assert_eq!(eval("0.5 * 4").unwrap().to_string(), "[2, 2]");
+    }
+
+    #[test]
+    fn rounding_is_outward() {
+        let third = eval("1 / 3").unwrap().hull();
+        assert!(third.lo() < third.hi());
+        assert!(third.lo() <= 1.0 / 3.0 && 1.0 / 3.0 <= third.hi());
+
+        // 0.1 is not a float, so its enclosure brackets the nearest one.
+        let tenth = eval("0.1").unwrap().hull();
+        assert!(tenth.lo() < 0.1 && 0.1 < tenth.hi());
+        let sum = eval("0.1 + 0.2").unwrap();
+        assert!(sum.contains(0.1 + 0.2) && sum.contains(0.3));
+        assert!(eval("sqrt(2)").unwrap().contains(std::f64::consts::SQRT_2));
+        assert!(eval("pi").unwrap().contains(std::f64::consts::PI));
+    }
+
+    #[test]
+    fn division_by_interval_containing_zero() {
+        let mut evaluator = IntervalEvaluator::new();
+        evaluator.set_variable("x", interval(-1.0, 1.0));
+        evaluator.set_variable("p", interval(0.0, 2.0));
+        assert_eq!(
+            eval_with(&evaluator, "1 / x").unwrap().to_string(),
+            "[-inf, -1] ∪ [1, inf]"
+        );
+        assert_eq!(
+            eval_with(&evaluator, "1 / p").unwrap().to_string(),
+            "[0.5, inf]"
+        );
+        assert_eq!(
+            eval_with(&evaluator, "x / x").unwrap().to_string(),
+            "[-inf, inf]"
+        );
+        assert_eq!(
+            eval_with(&evaluator, "1 / x + 10").unwrap().to_string(),
+            "[-inf, 9] ∪ [11, inf]"
+        );
+        assert!(eval("1 / 0").is_err());
+    }
+
+    #[test]
+    fn proves_bounds() {
+        let mut evaluator = IntervalEvaluator::new();
+        evaluator.set_variable("t", interval(0.0, 1.0));
+        let result = eval_with(&evaluator, "t * (1 - t) + 0.25").unwrap();
+        assert!(result.is_within(0.0, 1.25));
+        assert!(!result.is_within(0.0, 1.0));
+        assert!(eval_with(&evaluator, "sqrt(t - 1)").is_err());
+        assert!(eval_with(&evaluator, "(t - 1)^0.5").is_err());
+        let root = eval_with(&evaluator, "t^0.5").unwrap();
+        assert!(root.contains(0.5f64.sqrt()) && root.is_within(0.0, 1.000001));
+    }
+}
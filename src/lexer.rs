@@ -5,16 +5,30 @@ use std::{
 
 use crate::{
     location::Location,
-    tokens::{Divide, LeftParen, Minus, Multiply, Number, Plus, Power, RightParen, Token},
+    tokens::{
+        And, Arrow, Assign, Backslash, BitAnd, BitNot, BitOr, Cell, Colon, Comma, Divide, Equal,
+        Greater, GreaterEqual, Identifier, Imaginary, IntDivide, LeftBracket, LeftParen, Less,
+        LessEqual, Minus, Multiply, Newline, Not, NotEqual, Number, Or, Percent, Plus, PlusMinus,
+        Power, Question, Range, RightBracket, RightParen, Semicolon, ShiftLeft, ShiftRight, Token,
+        Xor,
+    },
 };
 
 type LexerResult<T> = Result<T, LexerError>;
 
+/// Tokens and the locations where they start, in the same order.
+type Tokens = (Vec<Box<dyn Token>>, Vec<Location>);
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
     input: &'a str,
-    length: usize,
     location: Location,
+    token_start: Location,
+    /// How many parentheses and brackets are open; line breaks inside them
+    /// are whitespace.
+    depth: usize,
+    cells: bool,
+    imaginary: bool,
 }
 
 #[derive(Debug)]
@@ -41,13 +55,57 @@ impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Lexer {
             input,
-            length: input.chars().count(),
             location: Location::new(),
+            token_start: Location::new(),
+            depth: 0,
+            cells: false,
+            imaginary: false,
         }
     }
 
+    /// Reads spreadsheet cell references such as `A1` and `$B$2` as
+    /// [`Cell`] tokens, and two of them joined by `:` without spaces, as in
+    /// `A1:C3`, as a [`Range`]. Other names stay identifiers.
+    pub fn cells(mut self, enabled: bool) -> Self {
+        self.cells = enabled;
+        self
+    }
+
+    /// Reads a number directly followed by `i`, as in `4i`, as an
+    /// [`Imaginary`] literal for complex mode. Otherwise `3i` is a number
+    /// and a name, which implicit multiplication reads as `3 * i`.
+    pub fn imaginary(mut self, enabled: bool) -> Self {
+        self.imaginary = enabled;
+        self
+    }
+
+    /// Where the token most recently returned by [`Lexer::next_token`]
+    /// starts.
+    pub fn token_location(&self) -> Location {
+        self.token_start
+    }
+
+    /// Reads the rest of the input, giving the tokens and where each one
+    /// starts followed by where the last one ends, as [`Parser::locations`]
+    /// expects.
+    ///
+    /// [`Parser::locations`]: crate::parser::Parser::locations
+    pub fn tokenize(mut self) -> LexerResult<Tokens> {
+        let mut tokens = Vec::new();
+        let mut locations = Vec::new();
+        let mut end = self.location;
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+            locations.push(self.token_location());
+            end = self.location;
+        }
+        locations.push(end);
+        Ok((tokens, locations))
+    }
+
     pub fn next_token(&mut self) -> LexerResult<Option<Box<dyn Token>>> {
         self.skip_whitespace()?;
+        self.token_start = self.location;
 
         let (_, after) = self
             .input
@@ -61,10 +119,27 @@ impl<'a> Lexer<'a> {
             Some(ch) => {
                 let token = match ch {
                     '0'..='9' => self.read_number(),
+                    'a'..='z' | 'A'..='Z' | '_'
+                        if self.cells && Cell::parse(cell_text(self.remaining())).is_some() =>
+                    {
+                        self.read_cell()
+                    }
+                    'a'..='z' | 'A'..='Z' | '_' => self.read_identifier(),
+                    '$' if self.cells => self.read_cell(),
+                    '+' if self.remaining().starts_with("+/-") => {
+                        for ch in "+/-".chars() {
+                            self.location.advance(ch);
+                        }
+                        Ok(PlusMinus.to_token())
+                    }
                     '+' => {
                         self.location.advance(ch);
                         Ok(Plus.to_token())
                     }
+                    '±' => {
+                        self.location.advance(ch);
+                        Ok(PlusMinus.to_token())
+                    }
                     '-' => {
                         self.location.advance(ch);
                         Ok(Minus.to_token())
@@ -75,20 +150,134 @@ impl<'a> Lexer<'a> {
                     }
                     '/' => {
                         self.location.advance(ch);
-                        Ok(Divide.to_token())
+                        if self.next_char_is('/') {
+                            self.location.advance('/');
+                            Ok(IntDivide.to_token())
+                        } else {
+                            Ok(Divide.to_token())
+                        }
+                    }
+                    '\\' => {
+                        self.location.advance(ch);
+                        Ok(Backslash.to_token())
+                    }
+                    '%' => {
+                        self.location.advance(ch);
+                        Ok(Percent.to_token())
                     }
                     '(' => {
                         self.location.advance(ch);
+                        self.depth += 1;
                         Ok(LeftParen.to_token())
                     }
                     ')' => {
                         self.location.advance(ch);
+                        self.depth = self.depth.saturating_sub(1);
                         Ok(RightParen.to_token())
                     }
+                    '[' => {
+                        self.location.advance(ch);
+                        self.depth += 1;
+                        Ok(LeftBracket.to_token())
+                    }
+                    ']' => {
+                        self.location.advance(ch);
+                        self.depth = self.depth.saturating_sub(1);
+                        Ok(RightBracket.to_token())
+                    }
                     '^' => {
                         self.location.advance(ch);
                         Ok(Power.to_token())
                     }
+                    '<' => {
+                        self.location.advance(ch);
+                        if self.next_char_is('=') {
+                            self.location.advance('=');
+                            Ok(LessEqual.to_token())
+                        } else if self.next_char_is('<') {
+                            self.location.advance('<');
+                            Ok(ShiftLeft.to_token())
+                        } else {
+                            Ok(Less.to_token())
+                        }
+                    }
+                    '>' => {
+                        self.location.advance(ch);
+                        if self.next_char_is('=') {
+                            self.location.advance('=');
+                            Ok(GreaterEqual.to_token())
+                        } else if self.next_char_is('>') {
+                            self.location.advance('>');
+                            Ok(ShiftRight.to_token())
+                        } else {
+                            Ok(Greater.to_token())
+                        }
+                    }
+                    '!' => {
+                        self.location.advance(ch);
+                        if self.next_char_is('=') {
+                            self.location.advance('=');
+                            Ok(NotEqual.to_token())
+                        } else {
+                            Ok(Not.to_token())
+                        }
+                    }
+                    '=' if self.char_after_next_is('=') => {
+                        self.location.advance(ch);
+                        self.location.advance('=');
+                        Ok(Equal.to_token())
+                    }
+                    '=' if self.char_after_next_is('>') => {
+                        self.location.advance(ch);
+                        self.location.advance('>');
+                        Ok(Arrow.to_token())
+                    }
+                    '=' => {
+                        self.location.advance(ch);
+                        Ok(Assign.to_token())
+                    }
+                    ';' => {
+                        self.location.advance(ch);
+                        Ok(Semicolon.to_token())
+                    }
+                    '\n' => {
+                        self.location.advance(ch);
+                        Ok(Newline.to_token())
+                    }
+                    '&' => {
+                        self.location.advance(ch);
+                        if self.next_char_is('&') {
+                            self.location.advance('&');
+                            Ok(And.to_token())
+                        } else {
+                            Ok(BitAnd.to_token())
+                        }
+                    }
+                    '|' => {
+                        self.location.advance(ch);
+                        if self.next_char_is('|') {
+                            self.location.advance('|');
+                            Ok(Or.to_token())
+                        } else {
+                            Ok(BitOr.to_token())
+                        }
+                    }
+                    '~' => {
+                        self.location.advance(ch);
+                        Ok(BitNot.to_token())
+                    }
+                    '?' => {
+                        self.location.advance(ch);
+                        Ok(Question.to_token())
+                    }
+                    ':' => {
+                        self.location.advance(ch);
+                        Ok(Colon.to_token())
+                    }
+                    ',' => {
+                        self.location.advance(ch);
+                        Ok(Comma.to_token())
+                    }
                     _ => Err(LexerError {
                         message: format!("Unexpected character: {}", ch),
                         location: self.location,
@@ -105,6 +294,16 @@ impl<'a> Lexer<'a> {
     }
 
     fn read_number(&mut self) -> LexerResult<Box<dyn Token>> {
+        let radix = match self.remaining().get(..2) {
+            Some("0x" | "0X") => Some(16),
+            Some("0b" | "0B") => Some(2),
+            Some("0o" | "0O") => Some(8),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return self.read_prefixed_number(radix);
+        }
+
         let mut number = String::new();
         let start_location = self.location;
         let mut has_decimal = false;
@@ -116,9 +315,8 @@ impl<'a> Lexer<'a> {
                 message: "Error on reading the end of input".into(),
                 location: self.location,
             })?;
-        let mut s = after.chars();
-        while let Some(ch) = s.next() {
-            if ch.is_digit(10) {
+        for ch in after.chars() {
+            if ch.is_ascii_digit() {
                 number.push(ch);
                 self.location.advance(ch);
             } else if ch == '.' && !has_decimal {
@@ -137,14 +335,103 @@ impl<'a> Lexer<'a> {
             });
         }
 
-        let token = number.parse::<f64>().map(Number).map_err(|_| LexerError {
+        let token = Number::parse(&number).ok_or_else(|| LexerError {
             message: format!("Invalid number: {}", number),
             location: start_location,
         })?;
 
+        // An `i` suffix makes an imaginary literal, unless it starts a longer
+        // name as in `3in`.
+        let mut rest = self.remaining().chars();
+        if self.imaginary
+            && rest.next() == Some('i')
+            && !rest
+                .next()
+                .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            self.location.advance('i');
+            return Ok(Imaginary(token).to_token());
+        }
+
+        Ok(token.to_token())
+    }
+
+    fn remaining(&self) -> &'a str {
+        self.input.get(self.location.index()..).unwrap_or_default()
+    }
+
+    fn next_char_is(&self, expected: char) -> bool {
+        self.remaining().starts_with(expected)
+    }
+
+    fn char_after_next_is(&self, expected: char) -> bool {
+        self.remaining().chars().nth(1) == Some(expected)
+    }
+
+    /// `0x1F`, `0b1010` and `0o17`. The literal is converted to decimal text
+    /// so every number type can read it like any other [`Number`].
+    fn read_prefixed_number(&mut self, radix: u32) -> LexerResult<Box<dyn Token>> {
+        let start_location = self.location;
+        let prefix = &self.remaining()[..2];
+        let digits: String = self.remaining()[2..]
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '_')
+            .collect();
+        for ch in prefix.chars().chain(digits.chars()) {
+            self.location.advance(ch);
+        }
+
+        let value =
+            u128::from_str_radix(&digits.replace('_', ""), radix).map_err(|_| LexerError {
+                message: format!("Invalid number: {}{}", prefix, digits),
+                location: start_location,
+            })?;
+        let token = Number::parse(&value.to_string()).ok_or_else(|| LexerError {
+            message: format!("Invalid number: {}{}", prefix, digits),
+            location: start_location,
+        })?;
+
         Ok(token.to_token())
     }
 
+    fn read_identifier(&mut self) -> LexerResult<Box<dyn Token>> {
+        let name: String = self
+            .remaining()
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '_')
+            .collect();
+        for ch in name.chars() {
+            self.location.advance(ch);
+        }
+
+        if name == Xor::as_str() {
+            return Ok(Xor.to_token());
+        }
+        Ok(Identifier(name).to_token())
+    }
+
+    fn read_cell(&mut self) -> LexerResult<Box<dyn Token>> {
+        let text = cell_text(self.remaining());
+        let cell = Cell::parse(text).ok_or_else(|| LexerError {
+            message: format!("Invalid cell reference: {}", text),
+            location: self.location,
+        })?;
+        for ch in text.chars() {
+            self.location.advance(ch);
+        }
+
+        if let Some(rest) = self.remaining().strip_prefix(':') {
+            let text = cell_text(rest);
+            if let Some(end) = Cell::parse(text) {
+                for ch in ":".chars().chain(text.chars()) {
+                    self.location.advance(ch);
+                }
+                return Ok(Range(cell, end).to_token());
+            }
+        }
+        Ok(cell.to_token())
+    }
+
     fn skip_whitespace(&mut self) -> LexerResult<()> {
         let (_, after) = self
             .input
@@ -153,9 +440,8 @@ impl<'a> Lexer<'a> {
                 message: "Error on reading the end of input".into(),
                 location: self.location,
             })?;
-        let mut s = after.chars();
-        while let Some(ch) = s.next() {
-            if ch.is_whitespace() {
+        for ch in after.chars() {
+            if ch.is_whitespace() && (ch != '\n' || self.depth > 0) {
                 self.location.advance(ch);
             } else {
                 break;
@@ -164,3 +450,11 @@ impl<'a> Lexer<'a> {
         Ok(())
     }
 }
+
+/// The longest prefix of `text` that could be a cell reference.
+fn cell_text(text: &str) -> &str {
+    let end = text
+        .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '$'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
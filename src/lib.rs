@@ -0,0 +1,25 @@
+#[allow(dead_code)]
+mod ast_parser;
+pub mod bigint;
+pub mod complex;
+pub mod decimal;
+pub mod dual;
+pub mod evaluator;
+pub mod formulas;
+mod graph;
+pub mod integer;
+pub mod interval;
+pub mod lexer;
+pub mod location;
+pub mod matrix;
+pub mod numeric;
+pub mod parser;
+pub mod programmer;
+pub mod query;
+pub mod rational;
+pub mod result;
+pub mod rewrite;
+pub mod tokens;
+pub mod uncertainty;
+pub mod units;
+pub mod workbook;
@@ -1,10 +1,16 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Location {
     col: usize,
     line: usize,
     index: usize,
 }
 
+impl Default for Location {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Location {
     pub fn col(&self) -> usize {
         self.col
@@ -26,8 +32,10 @@ impl Location {
         }
     }
 
+    /// Moves past `ch`. The index counts bytes so it can slice the input;
+    /// the column counts characters.
     pub fn advance(&mut self, ch: char) {
-        self.index += 1;
+        self.index += ch.len_utf8();
         if ch == '\n' {
             self.line += 1;
             self.col = 1;
@@ -35,8 +43,12 @@ impl Location {
             self.col += 1;
         }
     }
+
+    /// Steps back over the previous character of the line, if there is one.
     pub fn backtrack(&mut self) {
-        self.index -= 1;
-        self.col -= 1;
+        if self.col > 1 {
+            self.index -= 1;
+            self.col -= 1;
+        }
     }
 }
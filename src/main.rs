@@ -1,11 +1,4 @@
-mod ast_parser;
-mod lexer;
-mod location;
-mod parser;
-mod result;
-mod tokens;
-
-use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+use expression_parser::{evaluator::Evaluator, lexer::Lexer, parser::Parser, result::AppResult};
 
 fn main() -> AppResult<()> {
     let input = "3 + 4 * (2 + 1)^2";
@@ -24,5 +17,8 @@ fn main() -> AppResult<()> {
 
     dbg!(&ast);
     println!("AST: {}", ast);
+
+    let value = Evaluator::new().evaluate(&ast)?;
+    println!("Value: {}", value);
     Ok(())
 }
@@ -0,0 +1,441 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+};
+
+use crate::{
+    evaluator::{EvalError, EvalResult},
+    numeric::Numeric,
+    tokens::Number,
+};
+
+/// A rectangular matrix of numbers, stored row by row.
+///
+/// The decompositions use Gaussian elimination with partial pivoting, so the
+/// number type must support [`Numeric::compare`]. A matrix is singular when
+/// elimination meets a pivot that is exactly zero; nearly singular matrices
+/// give large, inaccurate results rather than an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix<T> {
+    rows: usize,
+    cols: usize,
+    items: Vec<T>,
+}
+
+impl<T: Numeric> Matrix<T> {
+    /// A matrix from its rows, or `None` if there are none or they are
+    /// empty or of different lengths.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Option<Self> {
+        let cols = rows.first()?.len();
+        if cols == 0 || rows.iter().any(|row| row.len() != cols) {
+            return None;
+        }
+        Some(Matrix {
+            rows: rows.len(),
+            cols,
+            items: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    pub fn identity(size: usize) -> EvalResult<Self> {
+        let (zero, one): (T, T) = (constant("0")?, constant("1")?);
+        let items = (0..size * size)
+            .map(|i| {
+                if i / size == i % size {
+                    one.clone()
+                } else {
+                    zero.clone()
+                }
+            })
+            .collect();
+        Ok(Matrix {
+            rows: size,
+            cols: size,
+            items,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> &T {
+        &self.items[row * self.cols + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.items[row * self.cols..(row + 1) * self.cols]
+    }
+
+    /// `rows x cols`, as used in error messages.
+    pub fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.cols)
+    }
+
+    /// Applies `op` to every item.
+    pub fn map(&self, op: impl Fn(&T) -> EvalResult<T>) -> EvalResult<Self> {
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            items: self.items.iter().map(op).collect::<EvalResult<_>>()?,
+        })
+    }
+
+    /// Applies `op` to the items in the same position of two matrices of the
+    /// same shape.
+    pub fn zip_with(&self, other: &Self, op: impl Fn(&T, &T) -> EvalResult<T>) -> EvalResult<Self> {
+        if self.rows != other.rows || self.cols != other.cols {
+            return Err(EvalError::new(format!(
+                "Shape mismatch: {} and {}",
+                self.shape(),
+                other.shape()
+            )));
+        }
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            items: self
+                .items
+                .iter()
+                .zip(&other.items)
+                .map(|(a, b)| op(a, b))
+                .collect::<EvalResult<_>>()?,
+        })
+    }
+
+    pub fn transpose(&self) -> Self {
+        let items = (0..self.rows * self.cols)
+            .map(|i| self.get(i % self.rows, i / self.rows).clone())
+            .collect();
+        Matrix {
+            rows: self.cols,
+            cols: self.rows,
+            items,
+        }
+    }
+
+    /// The matrix product `self * other`.
+    pub fn mul(&self, other: &Self, context: &T::Context) -> EvalResult<Self> {
+        if self.cols != other.rows {
+            return Err(EvalError::new(format!(
+                "Shape mismatch: cannot multiply {} by {}",
+                self.shape(),
+                other.shape()
+            )));
+        }
+        let mut items = Vec::with_capacity(self.rows * other.cols);
+        for row in 0..self.rows {
+            for col in 0..other.cols {
+                let mut sum = constant::<T>("0")?;
+                for k in 0..self.cols {
+                    let product = self.get(row, k).mul(other.get(k, col), context)?;
+                    sum = sum.add(&product, context)?;
+                }
+                items.push(sum);
+            }
+        }
+        Ok(Matrix {
+            rows: self.rows,
+            cols: other.cols,
+            items,
+        })
+    }
+
+    /// `self` multiplied by itself `exponent` times, by repeated squaring. A
+    /// negative exponent raises the inverse.
+    pub fn pow(&self, exponent: i64, context: &T::Context) -> EvalResult<Self> {
+        self.require_square("raise to a power")?;
+        let mut base = if exponent < 0 {
+            self.inv(context)?
+        } else {
+            self.clone()
+        };
+        let mut exponent = exponent.unsigned_abs();
+        let mut result = Matrix::identity(self.rows)?;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base, context)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base, context)?;
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn det(&self, context: &T::Context) -> EvalResult<T> {
+        self.require_square("take the determinant of")?;
+        let mut rows = self.to_rows();
+        let mut det = constant::<T>("1")?;
+        for col in 0..self.cols {
+            let Some(pivot) = pivot_row(&rows, col)? else {
+                return constant("0");
+            };
+            if pivot != col {
+                rows.swap(pivot, col);
+                det = det.neg()?;
+            }
+            det = det.mul(&rows[col][col], context)?;
+            eliminate(&mut rows, col, col + 1..self.rows, context)?;
+        }
+        Ok(det)
+    }
+
+    pub fn inv(&self, context: &T::Context) -> EvalResult<Self> {
+        self.require_square("invert")?;
+        self.solve(&Matrix::identity(self.rows)?, context)
+    }
+
+    /// The matrix `x` with `self * x = rhs`, for a square `self`.
+    pub fn solve(&self, rhs: &Self, context: &T::Context) -> EvalResult<Self> {
+        self.require_square("solve with")?;
+        if rhs.rows != self.rows {
+            return Err(EvalError::new(format!(
+                "Shape mismatch: cannot solve {} \\ {}",
+                self.shape(),
+                rhs.shape()
+            )));
+        }
+
+        // Gauss-Jordan elimination on the augmented matrix [self | rhs].
+        let mut rows: Vec<Vec<T>> = self
+            .to_rows()
+            .into_iter()
+            .zip(rhs.to_rows())
+            .map(|(mut row, right)| {
+                row.extend(right);
+                row
+            })
+            .collect();
+        for col in 0..self.cols {
+            let pivot =
+                pivot_row(&rows, col)?.ok_or_else(|| EvalError::new("Matrix is singular"))?;
+            rows.swap(pivot, col);
+            let scale = rows[col][col].clone();
+            for item in &mut rows[col] {
+                *item = item.div(&scale, context)?;
+            }
+            let targets = (0..self.rows).filter(|&row| row != col);
+            eliminate(&mut rows, col, targets, context)?;
+        }
+
+        let items = rows
+            .into_iter()
+            .flat_map(|row| row.into_iter().skip(self.cols))
+            .collect();
+        Ok(Matrix {
+            rows: self.rows,
+            cols: rhs.cols,
+            items,
+        })
+    }
+
+    fn to_rows(&self) -> Vec<Vec<T>> {
+        (0..self.rows).map(|row| self.row(row).to_vec()).collect()
+    }
+
+    fn require_square(&self, action: &str) -> EvalResult<()> {
+        if self.rows != self.cols {
+            return Err(EvalError::new(format!(
+                "Cannot {} a non-square {} matrix",
+                action,
+                self.shape()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Display> Display for Matrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for row in 0..self.rows {
+            if row > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "[")?;
+            for col in 0..self.cols {
+                if col > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", self.items[row * self.cols + col])?;
+            }
+            write!(f, "]")?;
+        }
+        write!(f, "]")
+    }
+}
+
+fn constant<T: Numeric>(text: &str) -> EvalResult<T> {
+    T::from_literal(&Number::parse(text).expect("valid literal"))
+}
+
+/// The row at or below `col` with the largest item in column `col`, or
+/// `None` if they are all zero.
+fn pivot_row<T: Numeric>(rows: &[Vec<T>], col: usize) -> EvalResult<Option<usize>> {
+    let zero = constant::<T>("0")?;
+    let mut best: Option<(usize, T)> = None;
+    for (row, items) in rows.iter().enumerate().skip(col) {
+        let magnitude = match items[col].compare(&zero)? {
+            Some(Ordering::Less) => items[col].neg()?,
+            _ => items[col].clone(),
+        };
+        let larger = match &best {
+            Some((_, largest)) => magnitude.compare(largest)? == Some(Ordering::Greater),
+            None => magnitude != zero,
+        };
+        if larger {
+            best = Some((row, magnitude));
+        }
+    }
+    Ok(best.map(|(row, _)| row))
+}
+
+/// Subtracts multiples of row `col` from `targets` to clear column `col`.
+fn eliminate<T: Numeric>(
+    rows: &mut [Vec<T>],
+    col: usize,
+    targets: impl Iterator<Item = usize>,
+    context: &T::Context,
+) -> EvalResult<()> {
+    for row in targets {
+        let factor = rows[row][col].div(&rows[col][col], context)?;
+        for k in col..rows[row].len() {
+            let delta = factor.mul(&rows[col][k], context)?;
+            rows[row][k] = rows[row][k].sub(&delta, context)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluator::{Evaluator, Value},
+        lexer::Lexer,
+        parser::Parser,
+        result::AppResult,
+    };
+
+    fn eval(input: &str) -> AppResult<Value> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).locations(locations).parse()?;
+        Ok(Evaluator::new().evaluate(&ast)?)
+    }
+
+    fn show(input: &str) -> String {
+        eval(input).unwrap().to_string()
+    }
+
+    fn matrix(rows: &[&[f64]]) -> Value {
+        let rows = rows.iter().map(|row| row.to_vec()).collect();
+        Value::Matrix(Matrix::from_rows(rows).unwrap())
+    }
+
+    #[test]
+    fn literals_become_matrices() {
+        assert_eq!(
+            eval("[[1, 2], [3, 4]]").unwrap(),
+            matrix(&[&[1.0, 2.0], &[3.0, 4.0]])
+        );
+        assert_eq!(eval("[[1, 2], [3]]").unwrap().type_name(), "list");
+        assert_eq!(eval("[[]]").unwrap().type_name(), "list");
+        assert_eq!(show("[[1, 2], [3, 4]][1]"), "[3, 4]");
+        assert_eq!(
+            show("transpose([[1, 2, 3], [4, 5, 6]])"),
+            "[[1, 4], [2, 5], [3, 6]]"
+        );
+    }
+
+    #[test]
+    fn matrices_are_lists_of_rows() {
+        assert_eq!(show("map([[1, 2], [3, 4]], r => sum(r))"), "[3, 7]");
+        assert_eq!(show("filter([[1, 2], [3, 4]], r => r[0] > 1)"), "[[3, 4]]");
+        assert_eq!(show("count([[1, 2], [3, 4]])"), "2");
+        assert_eq!(
+            show("[[1, 2], [3, 4]] == map([1, 3], x => [x, x + 1])"),
+            "true"
+        );
+        assert_eq!(show("[[1, 2]] != [[1, 3]]"), "true");
+
+        // Rows built by `map` work as the matrix they spell out.
+        let rows = "map([1, 3], x => [x, x + 1])";
+        assert_eq!(show(&format!("{0} * {0}", rows)), "[[7, 10], [15, 22]]");
+        assert_eq!(show(&format!("{} * [1, 1]", rows)), "[3, 7]");
+        assert_eq!(show(&format!("{}^2", rows)), "[[7, 10], [15, 22]]");
+        assert_eq!(show(&format!("det({})", rows)), "-2");
+        assert_eq!(
+            show(&format!("[[1, 1], [1, 1]] + {}", rows)),
+            "[[2, 3], [4, 5]]"
+        );
+        assert_eq!(show("map([2, 4], x => [x, x - 2]) \\ [2, 6]"), "[1, 1]");
+    }
+
+    #[test]
+    fn multiplication_is_the_matrix_product() {
+        assert_eq!(show("[[1, 2], [3, 4]] * [[5], [6]]"), "[[17], [39]]");
+        assert_eq!(
+            show("[[1, 2], [3, 4]] * [[5, 6], [7, 8]]"),
+            "[[19, 22], [43, 50]]"
+        );
+        assert_eq!(show("[[1, 2], [3, 4]] * [1, 1]"), "[3, 7]");
+        assert_eq!(show("[1, 1] * [[1, 2], [3, 4]]"), "[4, 6]");
+        assert_eq!(show("2 * [[1, 2], [3, 4]] + 1"), "[[3, 5], [7, 9]]");
+        assert_eq!(show("[[1, 2]] + [[10, 20]]"), "[[11, 22]]");
+        assert_eq!(show("-[[1, -2]]"), "[[-1, 2]]");
+    }
+
+    #[test]
+    fn powers_repeat_the_product() {
+        assert_eq!(show("[[1, 1], [1, 0]]^10"), "[[89, 55], [55, 34]]");
+        assert_eq!(show("[[2, 0], [0, 2]]^0"), "[[1, 0], [0, 1]]");
+        assert_eq!(show("[[2, 0], [0, 4]]^-1"), "[[0.5, 0], [0, 0.25]]");
+        assert!(eval("[[1, 2], [3, 4]]^0.5").is_err());
+        assert!(eval("[[1, 2, 3], [4, 5, 6]]^2").is_err());
+    }
+
+    #[test]
+    fn determinant_inverse_and_solve() {
+        assert_eq!(eval("det([[1, 2], [3, 4]])").unwrap(), Value::Number(-2.0));
+        assert_eq!(
+            eval("det([[0, 1, 2], [1, 0, 3], [4, -3, 8]])").unwrap(),
+            Value::Number(-2.0)
+        );
+        assert_eq!(eval("det([[1, 2], [2, 4]])").unwrap(), Value::Number(0.0));
+        assert_eq!(show("inv([[2, 1], [1, 1]])"), "[[1, -1], [-1, 2]]");
+        assert_eq!(show("inv([[0, 4], [8, 0]])"), "[[0, 0.125], [0.25, 0]]");
+        assert_eq!(show("[[2, 1], [1, 3]] \\ [3, 5]"), "[0.8, 1.4]");
+        assert_eq!(
+            show("[[0, 1], [1, 0]] \\ [[1, 2], [3, 4]]"),
+            "[[3, 4], [1, 2]]"
+        );
+
+        assert!(eval("inv([[1, 2], [2, 4]])").is_err());
+        assert!(eval("det([[1, 2, 3]])").is_err());
+        assert!(eval("det([1, 2])").is_err());
+        assert!(eval("[1, 2] \\ [1, 2]").is_err());
+    }
+
+    #[test]
+    fn shape_errors_point_at_the_operator() {
+        let err = eval("[[1, 2], [3, 4]] * [[1, 2, 3]]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error(Eval): Shape mismatch: cannot multiply 2x2 by 1x3 in line 1 at column 18"
+        );
+        let err = eval("[[1]] + ([[1, 2]] - [[1], [2]])").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error(Eval): Shape mismatch: 1x2 and 2x1 in line 1 at column 19"
+        );
+        let err = eval("[[1, 2], [3, 4]] \\ [1, 2, 3]").unwrap_err();
+        assert!(err.to_string().ends_with("in line 1 at column 18"));
+        assert!(eval("[[1, 2]] + [1, 2]").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Debug, Display},
+};
+
+use crate::{
+    evaluator::{arity_mismatch, unknown_function, EvalError, EvalResult},
+    tokens::Number,
+};
+
+/// The operations [`Evaluator`] needs from a number type.
+///
+/// Only arithmetic and reading literals are required. The remaining methods
+/// default to an error, so a type that is not ordered, or has no `floor` or
+/// factorial, still evaluates every expression that does not use those
+/// operators. Implementations report their own domain errors, such as
+/// division by zero. `==` compares numbers with [`PartialEq`].
+///
+/// The arithmetic operations get the [`Numeric::Context`] the evaluator was
+/// given with [`Evaluator::context`], for types whose results depend on a
+/// setting such as a precision.
+///
+/// [`Evaluator`]: crate::evaluator::Evaluator
+/// [`Evaluator::context`]: crate::evaluator::Evaluator::context
+pub trait Numeric: Clone + Debug + Display + PartialEq {
+    /// Settings for the arithmetic, such as how many digits a result keeps;
+    /// `()` for types that need none.
+    type Context: Clone + Debug + Default;
+
+    /// Reads a number literal from its source text.
+    fn from_literal(literal: &Number) -> EvalResult<Self>;
+
+    /// Reads an imaginary literal such as `4i`.
+    fn from_imaginary(literal: &Number) -> EvalResult<Self> {
+        Err(EvalError::new(format!(
+            "Imaginary numbers need complex mode: {}i",
+            literal
+        )))
+    }
+
+    fn add(&self, other: &Self, context: &Self::Context) -> EvalResult<Self>;
+
+    fn sub(&self, other: &Self, context: &Self::Context) -> EvalResult<Self>;
+
+    fn mul(&self, other: &Self, context: &Self::Context) -> EvalResult<Self>;
+
+    fn div(&self, other: &Self, context: &Self::Context) -> EvalResult<Self>;
+
+    fn pow(&self, exponent: &Self, context: &Self::Context) -> EvalResult<Self>;
+
+    /// `⌊self / other⌋`, as used by `//` and `%`.
+    fn div_floor(&self, other: &Self, context: &Self::Context) -> EvalResult<Self> {
+        self.div(other, context)?.floor()
+    }
+
+    /// `self%`, a hundredth of `self`.
+    fn percent(&self, context: &Self::Context) -> EvalResult<Self> {
+        self.div(&Self::from_literal(&Number::from(100.0))?, context)
+    }
+
+    fn neg(&self) -> EvalResult<Self> {
+        Self::from_literal(&Number::from(0.0))?.sub(self, &Self::Context::default())
+    }
+
+    /// Orders two numbers for `<`, `<=`, `>` and `>=`; `None` when they are
+    /// unordered, as with NaN.
+    fn compare(&self, _other: &Self) -> EvalResult<Option<Ordering>> {
+        Err(unsupported("Comparisons"))
+    }
+
+    /// Rounds towards negative infinity, for `//` and `%`.
+    fn floor(&self) -> EvalResult<Self> {
+        Err(unsupported("Floor"))
+    }
+
+    fn factorial(&self) -> EvalResult<Self> {
+        Err(unsupported("Factorial"))
+    }
+
+    /// The list position this number names, for `xs[i]`.
+    fn to_index(&self) -> EvalResult<usize> {
+        Err(unsupported("Indexing"))
+    }
+
+    /// Calls a built-in function such as `sqrt`.
+    fn call(name: &str, _args: &[Self]) -> EvalResult<Self> {
+        Err(unknown_function(name))
+    }
+
+    /// A named constant such as `pi`.
+    fn constant(_name: &str) -> Option<Self> {
+        None
+    }
+}
+
+fn unsupported(operation: &str) -> EvalError {
+    EvalError::new(format!("{} not supported for this number type", operation))
+}
+
+/// The one-argument functions every float type provides.
+macro_rules! float_function {
+    ($t:ty, $name:expr) => {{
+        let function: Option<fn($t) -> $t> = match $name {
+            "sqrt" => Some(<$t>::sqrt),
+            "abs" => Some(<$t>::abs),
+            "exp" => Some(<$t>::exp),
+            "ln" => Some(<$t>::ln),
+            "log10" => Some(<$t>::log10),
+            "sin" => Some(<$t>::sin),
+            "cos" => Some(<$t>::cos),
+            "tan" => Some(<$t>::tan),
+            "asin" => Some(<$t>::asin),
+            "acos" => Some(<$t>::acos),
+            "atan" => Some(<$t>::atan),
+            "floor" => Some(<$t>::floor),
+            "ceil" => Some(<$t>::ceil),
+            "round" => Some(<$t>::round),
+            _ => None,
+        };
+        function
+    }};
+}
+
+pub(crate) fn unary_function(name: &str) -> Option<fn(f64) -> f64> {
+    float_function!(f64, name)
+}
+
+macro_rules! impl_float {
+    ($t:ident) => {
+        impl Numeric for $t {
+            type Context = ();
+
+            /// Parses the literal's text directly, so it is rounded once to
+            /// the nearest value of this type.
+            fn from_literal(literal: &Number) -> EvalResult<Self> {
+                literal
+                    .text()
+                    .parse()
+                    .map_err(|_| EvalError::new(format!("Invalid number: {}", literal)))
+            }
+
+            fn add(&self, other: &Self, _: &()) -> EvalResult<Self> {
+                Ok(self + other)
+            }
+
+            fn sub(&self, other: &Self, _: &()) -> EvalResult<Self> {
+                Ok(self - other)
+            }
+
+            fn mul(&self, other: &Self, _: &()) -> EvalResult<Self> {
+                Ok(self * other)
+            }
+
+            fn div(&self, other: &Self, _: &()) -> EvalResult<Self> {
+                if *other == 0.0 {
+                    return Err(EvalError::new("Division by zero"));
+                }
+                Ok(self / other)
+            }
+
+            fn pow(&self, exponent: &Self, _: &()) -> EvalResult<Self> {
+                Ok(self.powf(*exponent))
+            }
+
+            fn neg(&self) -> EvalResult<Self> {
+                Ok(-self)
+            }
+
+            fn compare(&self, other: &Self) -> EvalResult<Option<Ordering>> {
+                Ok(self.partial_cmp(other))
+            }
+
+            fn floor(&self) -> EvalResult<Self> {
+                Ok($t::floor(*self))
+            }
+
+            fn factorial(&self) -> EvalResult<Self> {
+                let n = *self;
+                if n < 0.0 {
+                    return Err(EvalError::new(format!(
+                        "Factorial of a negative number: {}",
+                        n
+                    )));
+                }
+                if n.fract() != 0.0 {
+                    return Err(EvalError::new(format!("Factorial of a non-integer: {}", n)));
+                }
+                // 171! no longer fits in an f64; stop multiplying once the
+                // result is infinite.
+                if n > 170.0 {
+                    return Ok($t::INFINITY);
+                }
+                Ok((1..=n as u64).fold(1.0, |acc, k| acc * k as $t))
+            }
+
+            fn to_index(&self) -> EvalResult<usize> {
+                if *self < 0.0 || self.fract() != 0.0 || *self > usize::MAX as $t {
+                    return Err(EvalError::new(format!("Invalid index: {}", self)));
+                }
+                Ok(*self as usize)
+            }
+
+            fn call(name: &str, args: &[Self]) -> EvalResult<Self> {
+                match (name, args) {
+                    ("min", [first, rest @ ..]) => {
+                        Ok(rest.iter().fold(*first, |acc, &x| acc.min(x)))
+                    }
+                    ("max", [first, rest @ ..]) => {
+                        Ok(rest.iter().fold(*first, |acc, &x| acc.max(x)))
+                    }
+                    ("min" | "max", []) => Err(EvalError::new(format!(
+                        "Function {} expects at least 1 argument, got 0",
+                        name
+                    ))),
+                    _ => {
+                        let function =
+                            float_function!($t, name).ok_or_else(|| unknown_function(name))?;
+                        match args {
+                            [x] => Ok(function(*x)),
+                            _ => Err(arity_mismatch(name, 1, args.len())),
+                        }
+                    }
+                }
+            }
+
+            fn constant(name: &str) -> Option<Self> {
+                match name {
+                    "pi" => Some(std::$t::consts::PI),
+                    "e" => Some(std::$t::consts::E),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_float!(f64);
+impl_float!(f32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluator::{Evaluator, Value},
+        lexer::Lexer,
+        parser::Parser,
+        result::AppResult,
+    };
+    use std::fmt;
+
+    fn eval_with<T: Numeric>(evaluator: &Evaluator<T>, input: &str) -> AppResult<Value<T>> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        Ok(evaluator.evaluate(&ast)?)
+    }
+
+    /// Thousandths, as a downstream crate might define.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Fixed(i64);
+
+    impl Display for Fixed {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}.{:03}", self.0 / 1000, self.0.abs() % 1000)
+        }
+    }
+
+    impl Numeric for Fixed {
+        type Context = ();
+
+        fn from_literal(literal: &Number) -> EvalResult<Self> {
+            Ok(Fixed((literal.value() * 1000.0).round() as i64))
+        }
+
+        fn add(&self, other: &Self, _: &()) -> EvalResult<Self> {
+            Ok(Fixed(self.0 + other.0))
+        }
+
+        fn sub(&self, other: &Self, _: &()) -> EvalResult<Self> {
+            Ok(Fixed(self.0 - other.0))
+        }
+
+        fn mul(&self, other: &Self, _: &()) -> EvalResult<Self> {
+            Ok(Fixed(self.0 * other.0 / 1000))
+        }
+
+        fn div(&self, other: &Self, _: &()) -> EvalResult<Self> {
+            if other.0 == 0 {
+                return Err(EvalError::new("Division by zero"));
+            }
+            Ok(Fixed(self.0 * 1000 / other.0))
+        }
+
+        fn pow(&self, _exponent: &Self, _: &()) -> EvalResult<Self> {
+            Err(EvalError::new("Powers not supported for fixed-point"))
+        }
+    }
+
+    #[test]
+    fn evaluates_in_single_precision() {
+        let evaluator = Evaluator::<f32>::default();
+        assert_eq!(
+            eval_with(&evaluator, "0.1 + 0.2").unwrap(),
+            Value::Number(0.1f32 + 0.2f32)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "max(sqrt(16), 2) * pi").unwrap(),
+            Value::Number(4.0 * std::f32::consts::PI)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "7 // 2 + 7 % (0 - 2)").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            eval_with(&evaluator, "2 < 3").unwrap(),
+            Value::Boolean(true)
+        );
+        assert!(eval_with(&evaluator, "1 / 0").is_err());
+    }
+
+    #[test]
+    fn downstream_types_get_only_what_they_implement() {
+        let mut evaluator = Evaluator::<Fixed>::default();
+        evaluator.set_variable("x", Value::Number(Fixed(2500)));
+        let result = eval_with(&evaluator, "(x + 0.25) * 2 - 1 / 4").unwrap();
+        assert_eq!(result.to_string(), "5.250");
+        assert_eq!(
+            eval_with(&evaluator, "-x == 0 - x").unwrap(),
+            Value::Boolean(true)
+        );
+
+        assert!(eval_with(&evaluator, "1 / 0").is_err());
+        let err = eval_with(&evaluator, "1 < 2").unwrap_err();
+        assert!(err.to_string().contains("Comparisons not supported"));
+        assert!(eval_with(&evaluator, "3!").is_err());
+        assert!(eval_with(&evaluator, "sqrt(4)").is_err());
+        assert!(eval_with(&evaluator, "pi").is_err());
+    }
+}
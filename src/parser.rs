@@ -1,20 +1,378 @@
 use std::{
+    cmp::Ordering,
     error::Error,
     fmt::{self, Display},
+    hash::{Hash, Hasher},
 };
 
-use crate::tokens::{Divide, LeftParen, Minus, Multiply, Number, Plus, Power, Token};
+use crate::{
+    location::Location,
+    tokens::{
+        And, Arrow, Assign, Backslash, BitAnd, BitNot, BitOr, Cell, Colon, Comma, Divide, Equal,
+        Greater, GreaterEqual, Identifier, Imaginary, IntDivide, LeftBracket, LeftParen, Less,
+        LessEqual, Minus, Multiply, Newline, Not, NotEqual, Number, Or, Percent, Plus, PlusMinus,
+        Power, Question, Range, RightBracket, RightParen, Semicolon, ShiftLeft, ShiftRight, Token,
+        Xor,
+    },
+};
 
 type ParserResult<T> = Result<T, ParserError>;
 
-#[derive(Debug)]
+/// The unit conversion keyword, only reserved when parsing with units.
+const CONVERSION: &str = "to";
+
+/// The optional keyword before an assignment, as in `let x = 3`.
+const LET: &str = "let";
+
+/// The boolean literals, which cannot be used as names.
+const TRUE: &str = "true";
+const FALSE: &str = "false";
+
+/// An expression tree.
+///
+/// Trees compare, hash and order by structure, so they can key a map of
+/// cached results: source locations are ignored, as if every
+/// [`ASTNode::Located`] were replaced by the node it wraps, and literals
+/// compare as [`Number`] does. Nodes order first by kind, in the order the
+/// variants are declared, then by their names, literals or units, then by
+/// their children.
+#[derive(Debug, Clone)]
 pub enum ASTNode {
-    Number(f64),
+    Number(Number),
     Add(Box<ASTNode>, Box<ASTNode>),
     Subtract(Box<ASTNode>, Box<ASTNode>),
     Multiply(Box<ASTNode>, Box<ASTNode>),
     Divide(Box<ASTNode>, Box<ASTNode>),
     Power(Box<ASTNode>, Box<ASTNode>),
+    Negate(Box<ASTNode>),
+    Not(Box<ASTNode>),
+    Less(Box<ASTNode>, Box<ASTNode>),
+    LessEqual(Box<ASTNode>, Box<ASTNode>),
+    Greater(Box<ASTNode>, Box<ASTNode>),
+    GreaterEqual(Box<ASTNode>, Box<ASTNode>),
+    Equal(Box<ASTNode>, Box<ASTNode>),
+    NotEqual(Box<ASTNode>, Box<ASTNode>),
+    And(Box<ASTNode>, Box<ASTNode>),
+    Or(Box<ASTNode>, Box<ASTNode>),
+    Conditional(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
+    /// `true` or `false`.
+    Boolean(bool),
+    Modulo(Box<ASTNode>, Box<ASTNode>),
+    IntDivide(Box<ASTNode>, Box<ASTNode>),
+    Factorial(Box<ASTNode>),
+    Percent(Box<ASTNode>),
+    Variable(String),
+    BitAnd(Box<ASTNode>, Box<ASTNode>),
+    BitOr(Box<ASTNode>, Box<ASTNode>),
+    BitXor(Box<ASTNode>, Box<ASTNode>),
+    ShiftLeft(Box<ASTNode>, Box<ASTNode>),
+    ShiftRight(Box<ASTNode>, Box<ASTNode>),
+    BitNot(Box<ASTNode>),
+    Imaginary(Number),
+    Call(String, Vec<ASTNode>),
+    PlusMinus(Box<ASTNode>, Box<ASTNode>),
+    /// A literal with a unit attached, as in `9.81 m/s^2`.
+    Quantity(Number, UnitExpr),
+    /// `expr to unit`.
+    Convert(Box<ASTNode>, UnitExpr),
+    /// Where in the source the wrapped node's operator or name appears. Only
+    /// produced when the parser is given [`Parser::locations`].
+    Located(Location, Box<ASTNode>),
+    /// `(x, y) => body`, an anonymous function.
+    Lambda(Vec<String>, Box<ASTNode>),
+    /// `[a, b, c]`.
+    List(Vec<ASTNode>),
+    /// `A \ b`, the solution `x` of `A * x = b`.
+    Solve(Box<ASTNode>, Box<ASTNode>),
+    /// `xs[i]`, counting from zero.
+    Index(Box<ASTNode>, Box<ASTNode>),
+    /// `sum(i, from, to, body)`, the sum of `body` for every integer `i`
+    /// from `from` to `to`. The index is only bound inside `body`.
+    Sum(String, Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
+    /// `prod(i, from, to, body)`, the product of the same terms.
+    Product(String, Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
+    /// `piecewise(c1, v1, c2, v2, ..., default)`, the value after the first
+    /// condition that holds, or `default` if none does.
+    Piecewise(Vec<(ASTNode, ASTNode)>, Box<ASTNode>),
+    /// A spreadsheet cell reference, only lexed in [`Lexer::cells`] mode.
+    ///
+    /// [`Lexer::cells`]: crate::lexer::Lexer::cells
+    Cell(Cell),
+    /// The cells of a rectangle, as in `A1:C3`.
+    Range(Range),
+}
+
+impl ASTNode {
+    /// The nodes directly below this one, in source order.
+    pub fn children(&self) -> Vec<&ASTNode> {
+        match self {
+            ASTNode::Number(_)
+            | ASTNode::Boolean(_)
+            | ASTNode::Variable(_)
+            | ASTNode::Imaginary(_)
+            | ASTNode::Quantity(..)
+            | ASTNode::Cell(_)
+            | ASTNode::Range(_) => vec![],
+            ASTNode::Negate(n)
+            | ASTNode::Not(n)
+            | ASTNode::Factorial(n)
+            | ASTNode::Percent(n)
+            | ASTNode::BitNot(n)
+            | ASTNode::Convert(n, _)
+            | ASTNode::Located(_, n)
+            | ASTNode::Lambda(_, n) => vec![n],
+            ASTNode::Add(l, r)
+            | ASTNode::Subtract(l, r)
+            | ASTNode::Multiply(l, r)
+            | ASTNode::Divide(l, r)
+            | ASTNode::Power(l, r)
+            | ASTNode::Less(l, r)
+            | ASTNode::LessEqual(l, r)
+            | ASTNode::Greater(l, r)
+            | ASTNode::GreaterEqual(l, r)
+            | ASTNode::Equal(l, r)
+            | ASTNode::NotEqual(l, r)
+            | ASTNode::And(l, r)
+            | ASTNode::Or(l, r)
+            | ASTNode::Modulo(l, r)
+            | ASTNode::IntDivide(l, r)
+            | ASTNode::BitAnd(l, r)
+            | ASTNode::BitOr(l, r)
+            | ASTNode::BitXor(l, r)
+            | ASTNode::ShiftLeft(l, r)
+            | ASTNode::ShiftRight(l, r)
+            | ASTNode::PlusMinus(l, r)
+            | ASTNode::Solve(l, r)
+            | ASTNode::Index(l, r) => vec![l, r],
+            ASTNode::Conditional(c, t, e) => vec![c, t, e],
+            ASTNode::Sum(_, from, to, body) | ASTNode::Product(_, from, to, body) => {
+                vec![from, to, body]
+            }
+            ASTNode::Call(_, args) | ASTNode::List(args) => args.iter().collect(),
+            ASTNode::Piecewise(cases, default) => cases
+                .iter()
+                .flat_map(|(condition, value)| [condition, value])
+                .chain([&**default])
+                .collect(),
+        }
+    }
+    /// A copy of this node with each child replaced by `f` of it.
+    pub fn map_children(&self, f: &mut dyn FnMut(&ASTNode) -> ASTNode) -> ASTNode {
+        let mut map = |node: &ASTNode| Box::new(f(node));
+        match self {
+            ASTNode::Number(_)
+            | ASTNode::Boolean(_)
+            | ASTNode::Variable(_)
+            | ASTNode::Imaginary(_)
+            | ASTNode::Quantity(..)
+            | ASTNode::Cell(_)
+            | ASTNode::Range(_) => self.clone(),
+            ASTNode::Negate(n) => ASTNode::Negate(map(n)),
+            ASTNode::Not(n) => ASTNode::Not(map(n)),
+            ASTNode::Factorial(n) => ASTNode::Factorial(map(n)),
+            ASTNode::Percent(n) => ASTNode::Percent(map(n)),
+            ASTNode::BitNot(n) => ASTNode::BitNot(map(n)),
+            ASTNode::Add(l, r) => ASTNode::Add(map(l), map(r)),
+            ASTNode::Subtract(l, r) => ASTNode::Subtract(map(l), map(r)),
+            ASTNode::Multiply(l, r) => ASTNode::Multiply(map(l), map(r)),
+            ASTNode::Divide(l, r) => ASTNode::Divide(map(l), map(r)),
+            ASTNode::Power(l, r) => ASTNode::Power(map(l), map(r)),
+            ASTNode::Less(l, r) => ASTNode::Less(map(l), map(r)),
+            ASTNode::LessEqual(l, r) => ASTNode::LessEqual(map(l), map(r)),
+            ASTNode::Greater(l, r) => ASTNode::Greater(map(l), map(r)),
+            ASTNode::GreaterEqual(l, r) => ASTNode::GreaterEqual(map(l), map(r)),
+            ASTNode::Equal(l, r) => ASTNode::Equal(map(l), map(r)),
+            ASTNode::NotEqual(l, r) => ASTNode::NotEqual(map(l), map(r)),
+            ASTNode::And(l, r) => ASTNode::And(map(l), map(r)),
+            ASTNode::Or(l, r) => ASTNode::Or(map(l), map(r)),
+            ASTNode::Modulo(l, r) => ASTNode::Modulo(map(l), map(r)),
+            ASTNode::IntDivide(l, r) => ASTNode::IntDivide(map(l), map(r)),
+            ASTNode::BitAnd(l, r) => ASTNode::BitAnd(map(l), map(r)),
+            ASTNode::BitOr(l, r) => ASTNode::BitOr(map(l), map(r)),
+            ASTNode::BitXor(l, r) => ASTNode::BitXor(map(l), map(r)),
+            ASTNode::ShiftLeft(l, r) => ASTNode::ShiftLeft(map(l), map(r)),
+            ASTNode::ShiftRight(l, r) => ASTNode::ShiftRight(map(l), map(r)),
+            ASTNode::PlusMinus(l, r) => ASTNode::PlusMinus(map(l), map(r)),
+            ASTNode::Solve(l, r) => ASTNode::Solve(map(l), map(r)),
+            ASTNode::Index(l, r) => ASTNode::Index(map(l), map(r)),
+            ASTNode::Conditional(c, t, e) => ASTNode::Conditional(map(c), map(t), map(e)),
+            ASTNode::Convert(n, unit) => ASTNode::Convert(map(n), unit.clone()),
+            ASTNode::Located(location, n) => ASTNode::Located(*location, map(n)),
+            ASTNode::Lambda(params, body) => ASTNode::Lambda(params.clone(), map(body)),
+            ASTNode::Call(name, args) => {
+                ASTNode::Call(name.clone(), args.iter().map(|arg| *map(arg)).collect())
+            }
+            ASTNode::List(items) => ASTNode::List(items.iter().map(|item| *map(item)).collect()),
+            ASTNode::Sum(index, from, to, body) => {
+                ASTNode::Sum(index.clone(), map(from), map(to), map(body))
+            }
+            ASTNode::Product(index, from, to, body) => {
+                ASTNode::Product(index.clone(), map(from), map(to), map(body))
+            }
+            ASTNode::Piecewise(cases, default) => {
+                let cases = cases
+                    .iter()
+                    .map(|(condition, value)| (*map(condition), *map(value)))
+                    .collect();
+                ASTNode::Piecewise(cases, map(default))
+            }
+        }
+    }
+}
+
+/// The data of a node other than its children, which with the kind of node
+/// and the children determines its structure.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Payload<'a> {
+    None,
+    Number(&'a Number),
+    Boolean(bool),
+    Name(&'a str),
+    Names(&'a [String]),
+    Quantity(&'a Number, &'a UnitExpr),
+    Unit(&'a UnitExpr),
+    Cell(&'a Cell),
+    Range(&'a Range),
+}
+
+impl ASTNode {
+    /// This node without its [`ASTNode::Located`] wrappers.
+    pub(crate) fn unlocated(&self) -> &ASTNode {
+        match self {
+            ASTNode::Located(_, node) => node.unlocated(),
+            node => node,
+        }
+    }
+
+    /// The position of the variant in the declaration of [`ASTNode`].
+    fn rank(&self) -> u8 {
+        match self {
+            ASTNode::Number(_) => 0,
+            ASTNode::Add(..) => 1,
+            ASTNode::Subtract(..) => 2,
+            ASTNode::Multiply(..) => 3,
+            ASTNode::Divide(..) => 4,
+            ASTNode::Power(..) => 5,
+            ASTNode::Negate(_) => 6,
+            ASTNode::Not(_) => 7,
+            ASTNode::Less(..) => 8,
+            ASTNode::LessEqual(..) => 9,
+            ASTNode::Greater(..) => 10,
+            ASTNode::GreaterEqual(..) => 11,
+            ASTNode::Equal(..) => 12,
+            ASTNode::NotEqual(..) => 13,
+            ASTNode::And(..) => 14,
+            ASTNode::Or(..) => 15,
+            ASTNode::Conditional(..) => 16,
+            ASTNode::Boolean(_) => 17,
+            ASTNode::Modulo(..) => 18,
+            ASTNode::IntDivide(..) => 19,
+            ASTNode::Factorial(_) => 20,
+            ASTNode::Percent(_) => 21,
+            ASTNode::Variable(_) => 22,
+            ASTNode::BitAnd(..) => 23,
+            ASTNode::BitOr(..) => 24,
+            ASTNode::BitXor(..) => 25,
+            ASTNode::ShiftLeft(..) => 26,
+            ASTNode::ShiftRight(..) => 27,
+            ASTNode::BitNot(_) => 28,
+            ASTNode::Imaginary(_) => 29,
+            ASTNode::Call(..) => 30,
+            ASTNode::PlusMinus(..) => 31,
+            ASTNode::Quantity(..) => 32,
+            ASTNode::Convert(..) => 33,
+            ASTNode::Located(..) => 34,
+            ASTNode::Lambda(..) => 35,
+            ASTNode::List(_) => 36,
+            ASTNode::Solve(..) => 37,
+            ASTNode::Index(..) => 38,
+            ASTNode::Sum(..) => 39,
+            ASTNode::Product(..) => 40,
+            ASTNode::Piecewise(..) => 41,
+            ASTNode::Cell(_) => 42,
+            ASTNode::Range(_) => 43,
+        }
+    }
+
+    fn payload(&self) -> Payload<'_> {
+        match self {
+            ASTNode::Number(n) | ASTNode::Imaginary(n) => Payload::Number(n),
+            ASTNode::Boolean(b) => Payload::Boolean(*b),
+            ASTNode::Variable(name)
+            | ASTNode::Call(name, _)
+            | ASTNode::Sum(name, ..)
+            | ASTNode::Product(name, ..) => Payload::Name(name),
+            ASTNode::Lambda(params, _) => Payload::Names(params),
+            ASTNode::Quantity(n, unit) => Payload::Quantity(n, unit),
+            ASTNode::Convert(_, unit) => Payload::Unit(unit),
+            ASTNode::Cell(cell) => Payload::Cell(cell),
+            ASTNode::Range(range) => Payload::Range(range),
+            _ => Payload::None,
+        }
+    }
+}
+
+impl PartialEq for ASTNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ASTNode {}
+
+impl PartialOrd for ASTNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ASTNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = (self.unlocated(), other.unlocated());
+        a.rank()
+            .cmp(&b.rank())
+            .then_with(|| a.payload().cmp(&b.payload()))
+            .then_with(|| a.children().cmp(&b.children()))
+    }
+}
+
+impl Hash for ASTNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let node = self.unlocated();
+        node.rank().hash(state);
+        node.payload().hash(state);
+        node.children().hash(state);
+    }
+}
+
+/// A product of unit names raised to integer powers, such as `kg*m/s^2`,
+/// kept as written so a unit system can resolve the names.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnitExpr {
+    pub factors: Vec<(String, i32)>,
+}
+
+impl Display for UnitExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, exponent)) in self.factors.iter().enumerate() {
+            let exponent = match i {
+                0 => *exponent,
+                _ if *exponent < 0 => {
+                    write!(f, "/")?;
+                    -exponent
+                }
+                _ => {
+                    write!(f, "*")?;
+                    *exponent
+                }
+            };
+            write!(f, "{}", name)?;
+            if exponent != 1 {
+                write!(f, "^{}", exponent)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Display for ASTNode {
@@ -26,13 +384,215 @@ impl Display for ASTNode {
             ASTNode::Multiply(l, r) => write!(f, "({} * {})", l, r),
             ASTNode::Divide(l, r) => write!(f, "({} / {})", l, r),
             ASTNode::Power(l, r) => write!(f, "({} ^ {})", l, r),
+            ASTNode::Negate(n) => write!(f, "(-{})", n),
+            ASTNode::Not(n) => write!(f, "(!{})", n),
+            ASTNode::Less(l, r) => write!(f, "({} < {})", l, r),
+            ASTNode::LessEqual(l, r) => write!(f, "({} <= {})", l, r),
+            ASTNode::Greater(l, r) => write!(f, "({} > {})", l, r),
+            ASTNode::GreaterEqual(l, r) => write!(f, "({} >= {})", l, r),
+            ASTNode::Equal(l, r) => write!(f, "({} == {})", l, r),
+            ASTNode::NotEqual(l, r) => write!(f, "({} != {})", l, r),
+            ASTNode::And(l, r) => write!(f, "({} && {})", l, r),
+            ASTNode::Or(l, r) => write!(f, "({} || {})", l, r),
+            ASTNode::Conditional(c, t, e) => write!(f, "({} ? {} : {})", c, t, e),
+            ASTNode::Boolean(b) => write!(f, "{}", b),
+            ASTNode::Modulo(l, r) => write!(f, "({} % {})", l, r),
+            ASTNode::IntDivide(l, r) => write!(f, "({} // {})", l, r),
+            ASTNode::Factorial(n) => write!(f, "({}!)", n),
+            ASTNode::Percent(n) => write!(f, "({}%)", n),
+            ASTNode::Variable(name) => write!(f, "{}", name),
+            ASTNode::BitAnd(l, r) => write!(f, "({} & {})", l, r),
+            ASTNode::BitOr(l, r) => write!(f, "({} | {})", l, r),
+            ASTNode::BitXor(l, r) => write!(f, "({} xor {})", l, r),
+            ASTNode::ShiftLeft(l, r) => write!(f, "({} << {})", l, r),
+            ASTNode::ShiftRight(l, r) => write!(f, "({} >> {})", l, r),
+            ASTNode::BitNot(n) => write!(f, "(~{})", n),
+            ASTNode::Imaginary(n) => write!(f, "{}i", n),
+            ASTNode::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            ASTNode::PlusMinus(l, r) => write!(f, "({} ± {})", l, r),
+            ASTNode::Quantity(n, unit) => write!(f, "({} {})", n, unit),
+            ASTNode::Convert(n, unit) => write!(f, "({} to {})", n, unit),
+            ASTNode::Located(_, n) => write!(f, "{}", n),
+            ASTNode::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            ASTNode::Index(list, index) => write!(f, "{}[{}]", list, index),
+            ASTNode::Solve(l, r) => write!(f, "({} \\ {})", l, r),
+            ASTNode::Lambda(params, body) => match params.as_slice() {
+                [param] => write!(f, "({} => {})", param, body),
+                _ => write!(f, "(({}) => {})", params.join(", "), body),
+            },
+            ASTNode::Sum(index, from, to, body) => {
+                write!(f, "(Σ[{} = {}..{}] {})", index, from, to, body)
+            }
+            ASTNode::Product(index, from, to, body) => {
+                write!(f, "(Π[{} = {}..{}] {})", index, from, to, body)
+            }
+            ASTNode::Piecewise(cases, default) => {
+                write!(f, "piecewise(")?;
+                for (condition, value) in cases {
+                    write!(f, "{}, {}, ", condition, value)?;
+                }
+                write!(f, "{})", default)
+            }
+            ASTNode::Cell(cell) => write!(f, "{}", cell),
+            ASTNode::Range(range) => write!(f, "{}", range),
+        }
+    }
+}
+
+/// A user-defined function, the parameters and body of `f(x, y) = body`.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: ASTNode,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}) = {}", self.params.join(", "), self.body)
+    }
+}
+
+/// One statement of a [`Program`].
+#[derive(Debug)]
+pub enum Statement {
+    /// `let x = expr` or `x = expr`.
+    Assign(String, ASTNode),
+    /// `f(x, y) = body`.
+    Define(String, Function),
+    Expression(ASTNode),
+    /// Where the statement starts, when parsed with [`Parser::locations`].
+    Located(Location, Box<Statement>),
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Assign(name, value) => write!(f, "{} = {}", name, value),
+            Statement::Define(name, function) => write!(f, "{}{}", name, function),
+            Statement::Expression(node) => write!(f, "{}", node),
+            Statement::Located(_, statement) => write!(f, "{}", statement),
+        }
+    }
+}
+
+/// Statements separated by line breaks or `;`, run in order.
+#[derive(Debug)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", statement)?;
         }
+        Ok(())
     }
 }
 
+/// A call of `sum` or `prod` whose first of four arguments is a plain name
+/// binds that name as the index of a series; any other call stays a call, so
+/// `sum(xs)` still adds up a list. `piecewise` always makes a
+/// [`ASTNode::Piecewise`], and `if` an [`ASTNode::Conditional`].
+fn call(name: String, args: Vec<ASTNode>) -> ParserResult<ASTNode> {
+    let series: fn(String, Box<ASTNode>, Box<ASTNode>, Box<ASTNode>) -> ASTNode =
+        match name.as_str() {
+            "sum" => ASTNode::Sum,
+            "prod" => ASTNode::Product,
+            "piecewise" => return piecewise(args),
+            "if" => return conditional(args),
+            _ => return Ok(ASTNode::Call(name, args)),
+        };
+    let [index, from, to, body] = match <[ASTNode; 4]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return Ok(ASTNode::Call(name, args)),
+    };
+    let name_of = |node: &ASTNode| match node {
+        ASTNode::Variable(index) => Some(index.clone()),
+        ASTNode::Located(_, node) => match node.as_ref() {
+            ASTNode::Variable(index) => Some(index.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    Ok(match name_of(&index) {
+        Some(index) => series(index, Box::new(from), Box::new(to), Box::new(body)),
+        None => ASTNode::Call(name, vec![index, from, to, body]),
+    })
+}
+
+/// `if(condition, a, b)`, another way to write `condition ? a : b`.
+fn conditional(args: Vec<ASTNode>) -> ParserResult<ASTNode> {
+    match <[ASTNode; 3]>::try_from(args) {
+        Ok([condition, then_branch, else_branch]) => Ok(ASTNode::Conditional(
+            Box::new(condition),
+            Box::new(then_branch),
+            Box::new(else_branch),
+        )),
+        Err(args) => Err(ParserError(format!(
+            "Function if expects a condition and two values, as in if(x > 0, x, -x), got {} arguments",
+            args.len()
+        ))),
+    }
+}
+
+/// Pairs up the conditions and values of `piecewise(c1, v1, ..., default)`.
+fn piecewise(mut args: Vec<ASTNode>) -> ParserResult<ASTNode> {
+    if args.len().is_multiple_of(2) {
+        return Err(ParserError(format!(
+            "Function piecewise expects condition, value pairs and a default, got {} arguments",
+            args.len()
+        )));
+    }
+    let default = args.pop().unwrap_or_else(|| unreachable!("odd length"));
+    let mut args = args.into_iter();
+    let mut cases = Vec::new();
+    while let (Some(condition), Some(value)) = (args.next(), args.next()) {
+        cases.push((condition, value));
+    }
+    Ok(ASTNode::Piecewise(cases, Box::new(default)))
+}
+
+/// Rejects a parameter list that names the same parameter twice.
+fn check_params(params: &[String], function: &str) -> ParserResult<()> {
+    for (i, param) in params.iter().enumerate() {
+        if params[..i].contains(param) {
+            return Err(ParserError(format!(
+                "Duplicate parameter {} in {}",
+                param, function
+            )));
+        }
+    }
+    Ok(())
+}
+
 pub struct Parser {
     tokens: Vec<Box<dyn Token>>,
     pos: usize,
+    implicit_multiplication: bool,
+    units: bool,
+    locations: Option<Vec<Location>>,
 }
 
 #[derive(Debug)]
@@ -45,29 +605,431 @@ impl Display for ParserError {
 
 impl Error for ParserError {}
 
+impl ParserError {
+    /// Appends where the error happened, worded like a [`LexerError`].
+    ///
+    /// [`LexerError`]: crate::lexer::LexerError
+    pub fn at(self, location: Location) -> Self {
+        ParserError(format!(
+            "{} in line {} at column {}",
+            self.0,
+            location.line(),
+            location.col()
+        ))
+    }
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Box<dyn Token>>) -> Self {
-        Parser { tokens, pos: 0 }
+        Parser {
+            tokens,
+            pos: 0,
+            implicit_multiplication: false,
+            units: false,
+            locations: None,
+        }
+    }
+
+    /// Reads juxtaposed operands as a product: `2(3 + 4)`, `(a + b)(a - b)`,
+    /// `3x`, `2pi`.
+    ///
+    /// Juxtaposition binds tighter than `*` and `/` but looser than `^`, so
+    /// `1/2x` is `1 / (2 * x)` and `2x^2` is `2 * x^2`. Only the first factor
+    /// of such a product may be a number literal, so `2 3` and `(1)2` are
+    /// still rejected. `xy` is a single name; write `x y` for the product.
+    /// A name directly followed by `(` is always a function call, so `f(2)`
+    /// calls `f` rather than multiplying by it.
+    pub fn implicit_multiplication(mut self, enabled: bool) -> Self {
+        self.implicit_multiplication = enabled;
+        self
+    }
+
+    /// Reads a name after a number literal as its unit, as in `3 m`,
+    /// `9.81 m/s^2` or `2 kg*m`, and `expr to unit` as a conversion with the
+    /// lowest precedence.
+    ///
+    /// A unit continues through `*`, `/` and juxtaposition for as long as
+    /// another name follows, so `3 m * x` multiplies by a unit called `x`;
+    /// parenthesise variables there, as in `3 m * (x)`. Unit exponents must
+    /// be integer literals. After a literal, units take priority over
+    /// [`Parser::implicit_multiplication`].
+    pub fn units(mut self, enabled: bool) -> Self {
+        self.units = enabled;
+        self
+    }
+
+    /// The source location of each token, in the same order as the tokens,
+    /// optionally followed by where the last token ends, which errors at the
+    /// end of the input then point at. Operators, names and calls are
+    /// wrapped in [`ASTNode::Located`] so evaluators can point their errors
+    /// at them.
+    pub fn locations(mut self, locations: Vec<Location>) -> Self {
+        self.locations = Some(locations);
+        self
     }
 
+    /// Parses a single expression. Line breaks are whitespace here, so an
+    /// expression may span several lines.
     pub fn parse(&mut self) -> ParserResult<ASTNode> {
-        self.parse_expression()
+        let keep: Vec<bool> = self
+            .tokens
+            .iter()
+            .map(|token| token.as_any().downcast_ref::<Newline>().is_none())
+            .collect();
+        let mut kept = keep.iter();
+        self.tokens.retain(|_| *kept.next().unwrap_or(&true));
+        if let Some(locations) = &mut self.locations {
+            let mut kept = keep.iter();
+            locations.retain(|_| *kept.next().unwrap_or(&true));
+        }
+
+        let node = self.parse_expression()?;
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(ParserError(format!("Unexpected token: {:?}", token))),
+            None => Ok(node),
+        }
+    }
+
+    /// Parses statements separated by line breaks or `;`. Empty statements
+    /// are skipped, and line breaks inside parentheses do not end a
+    /// statement. With [`Parser::locations`], errors say where they happened
+    /// and each statement is wrapped in [`Statement::Located`].
+    pub fn parse_program(&mut self) -> ParserResult<Program> {
+        let mut statements = Vec::new();
+
+        loop {
+            while self.next_is::<Newline>() || self.next_is::<Semicolon>() {
+                self.pos += 1;
+            }
+            if self.pos >= self.tokens.len() {
+                break;
+            }
+
+            let start = self.location();
+            let statement =
+                self.parse_statement()
+                    .map_err(|error| match self.location().or(start) {
+                        Some(location) => error.at(location),
+                        None => error,
+                    })?;
+            statements.push(match start {
+                Some(location) => Statement::Located(location, Box::new(statement)),
+                None => statement,
+            });
+        }
+
+        Ok(Program { statements })
+    }
+
+    /// `let x = expr`, `x = expr`, `f(x, y) = body` or an expression,
+    /// followed by the end of the statement.
+    fn parse_statement(&mut self) -> ParserResult<Statement> {
+        if let Some((name, params)) = self.definition_head()? {
+            let body = self.parse_expression()?;
+            return self
+                .end_statement()
+                .map(|()| Statement::Define(name, Function { params, body }));
+        }
+
+        let declared = self.next_is_name(LET) && self.name_at(self.pos + 1).is_some();
+        if declared {
+            self.pos += 1;
+        }
+
+        let statement = match self.name_at(self.pos) {
+            Some(name) if self.is_at::<Assign>(self.pos + 1) => {
+                self.pos += 2;
+                Statement::Assign(name, self.parse_expression()?)
+            }
+            Some(name) if declared => {
+                return Err(ParserError(format!(
+                    "Expected '{}' after '{} {}'",
+                    Assign::as_str(),
+                    LET,
+                    name
+                )))
+            }
+            _ => Statement::Expression(self.parse_expression()?),
+        };
+        self.end_statement().map(|()| statement)
+    }
+
+    /// Reads `f(x, y) =` when the statement starts with one, leaving the
+    /// position at the body. Anything else, such as the call `f(x, 2)`, is
+    /// left for the expression parser.
+    fn definition_head(&mut self) -> ParserResult<Option<(String, Vec<String>)>> {
+        let Some(name) = self.name_at(self.pos) else {
+            return Ok(None);
+        };
+        match self.params_at(self.pos + 1) {
+            Some((params, pos)) if self.is_at::<Assign>(pos) => {
+                check_params(&params, &name)?;
+                self.pos = pos + 1;
+                Ok(Some((name, params)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// A parenthesised list of names such as `(x, y)` or `()` starting at
+    /// `pos`, and the position after it.
+    fn params_at(&self, pos: usize) -> Option<(Vec<String>, usize)> {
+        if !self.is_at::<LeftParen>(pos) {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut pos = pos + 1;
+        if !self.is_at::<RightParen>(pos) {
+            loop {
+                params.push(self.name_at(pos)?);
+                pos += 1;
+                if self.is_at::<Comma>(pos) {
+                    pos += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.is_at::<RightParen>(pos).then_some((params, pos + 1))
+    }
+
+    fn end_statement(&self) -> ParserResult<()> {
+        match self.tokens.get(self.pos) {
+            Some(token)
+                if token.as_any().downcast_ref::<Newline>().is_none()
+                    && token.as_any().downcast_ref::<Semicolon>().is_none() =>
+            {
+                Err(ParserError(format!("Unexpected token: {:?}", token)))
+            }
+            _ => Ok(()),
+        }
     }
 
     fn parse_expression(&mut self) -> ParserResult<ASTNode> {
-        let mut left = self.parse_term()?;
+        self.parse_lambda()
+    }
+
+    /// `x => body` or `(x, y) => body`, with the lowest precedence: the body
+    /// extends as far as an expression can, so `x => x + 1` is a lambda
+    /// returning `x + 1`.
+    fn parse_lambda(&mut self) -> ParserResult<ASTNode> {
+        let params = match self.name_at(self.pos) {
+            Some(name) if self.is_at::<Arrow>(self.pos + 1) => Some((vec![name], self.pos + 1)),
+            _ => self.params_at(self.pos),
+        };
+        let Some((params, pos)) = params.filter(|(_, pos)| self.is_at::<Arrow>(*pos)) else {
+            return self.parse_conversion();
+        };
+
+        check_params(&params, "lambda")?;
+        let location = self.location();
+        self.pos = pos + 1;
+        let body = self.parse_expression()?;
+        Ok(self.located(location, ASTNode::Lambda(params, Box::new(body))))
+    }
+
+    fn parse_conversion(&mut self) -> ParserResult<ASTNode> {
+        let mut node = self.parse_conditional()?;
+
+        while self.units && self.next_is_name(CONVERSION) {
+            let location = self.location();
+            self.pos += 1;
+            if self.unit_name_at(self.pos).is_none() {
+                return Err(ParserError(format!(
+                    "Expected a unit after '{}'",
+                    CONVERSION
+                )));
+            }
+            let unit = self.parse_unit()?;
+            node = self.located(location, ASTNode::Convert(Box::new(node), unit));
+        }
+
+        Ok(node)
+    }
+
+    /// `cond ? a : b`, right associative and with the lowest precedence, so
+    /// `a ? b : c ? d : e` reads as `a ? b : (c ? d : e)`.
+    fn parse_conditional(&mut self) -> ParserResult<ASTNode> {
+        let condition = self.parse_or()?;
+
+        if !self.next_is::<Question>() {
+            return Ok(condition);
+        }
+        let location = self.location();
+        self.pos += 1;
+        let then_branch = self.parse_expression()?;
+        self.expect::<Colon>(Colon::as_str())?;
+        let else_branch = self.parse_conditional()?;
+
+        Ok(self.located(
+            location,
+            ASTNode::Conditional(
+                Box::new(condition),
+                Box::new(then_branch),
+                Box::new(else_branch),
+            ),
+        ))
+    }
+
+    fn parse_or(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_and()?;
+
+        while self.next_is::<Or>() {
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = self.located(location, ASTNode::Or(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_equality()?;
+
+        while self.next_is::<And>() {
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_equality()?;
+            left = self.located(location, ASTNode::And(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_equality(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_comparison()?;
+
+        loop {
+            if self.next_is::<Equal>() {
+                let location = self.location();
+                self.pos += 1;
+                let right = self.parse_comparison()?;
+                left = self.located(location, ASTNode::Equal(Box::new(left), Box::new(right)));
+            } else if self.next_is::<NotEqual>() {
+                let location = self.location();
+                self.pos += 1;
+                let right = self.parse_comparison()?;
+                left = self.located(location, ASTNode::NotEqual(Box::new(left), Box::new(right)));
+            } else {
+                break;
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_bit_or()?;
+
+        loop {
+            let node: fn(Box<ASTNode>, Box<ASTNode>) -> ASTNode = if self.next_is::<Less>() {
+                ASTNode::Less
+            } else if self.next_is::<LessEqual>() {
+                ASTNode::LessEqual
+            } else if self.next_is::<Greater>() {
+                ASTNode::Greater
+            } else if self.next_is::<GreaterEqual>() {
+                ASTNode::GreaterEqual
+            } else {
+                break;
+            };
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_bit_or()?;
+            left = self.located(location, node(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    // The bitwise levels sit between comparisons and `+`/`-`, as in Python,
+    // so `x & 1 == 1` reads as `(x & 1) == 1` rather than C's `x & (1 == 1)`.
+    fn parse_bit_or(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_bit_xor()?;
+
+        while self.next_is::<BitOr>() {
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_bit_xor()?;
+            left = self.located(location, ASTNode::BitOr(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_bit_xor(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_bit_and()?;
+
+        while self.next_is::<Xor>() {
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_bit_and()?;
+            left = self.located(location, ASTNode::BitXor(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_bit_and(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_shift()?;
+
+        while self.next_is::<BitAnd>() {
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_shift()?;
+            left = self.located(location, ASTNode::BitAnd(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_shift(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_additive()?;
+
+        loop {
+            if self.next_is::<ShiftLeft>() {
+                let location = self.location();
+                self.pos += 1;
+                let right = self.parse_additive()?;
+                left = self.located(
+                    location,
+                    ASTNode::ShiftLeft(Box::new(left), Box::new(right)),
+                );
+            } else if self.next_is::<ShiftRight>() {
+                let location = self.location();
+                self.pos += 1;
+                let right = self.parse_additive()?;
+                left = self.located(
+                    location,
+                    ASTNode::ShiftRight(Box::new(left), Box::new(right)),
+                );
+            } else {
+                break;
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_uncertain()?;
 
         while self.pos < self.tokens.len() {
             match self.tokens.get(self.pos) {
                 Some(token) => {
                     if token.as_any().downcast_ref::<Plus>().is_some() {
+                        let location = self.location();
                         self.pos += 1;
-                        let right = self.parse_term()?;
-                        left = ASTNode::Add(Box::new(left), Box::new(right));
+                        let right = self.parse_uncertain()?;
+                        left =
+                            self.located(location, ASTNode::Add(Box::new(left), Box::new(right)));
                     } else if token.as_any().downcast_ref::<Minus>().is_some() {
+                        let location = self.location();
                         self.pos += 1;
-                        let right = self.parse_term()?;
-                        left = ASTNode::Subtract(Box::new(left), Box::new(right));
+                        let right = self.parse_uncertain()?;
+                        left = self
+                            .located(location, ASTNode::Subtract(Box::new(left), Box::new(right)));
                     } else {
                         break;
                     }
@@ -79,20 +1041,63 @@ impl Parser {
         Ok(left)
     }
 
+    /// `value ± uncertainty`, also written `+/-`. It binds looser than `*`
+    /// and `/` but tighter than `+` and `-`, so `2 * 3 ± 0.1` is
+    /// `(2 * 3) ± 0.1` and `1 + 9.81 ± 0.02` is `1 + (9.81 ± 0.02)`.
+    fn parse_uncertain(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_term()?;
+
+        while self.next_is::<PlusMinus>() {
+            let location = self.location();
+            self.pos += 1;
+            let right = self.parse_term()?;
+            left = self.located(
+                location,
+                ASTNode::PlusMinus(Box::new(left), Box::new(right)),
+            );
+        }
+
+        Ok(left)
+    }
+
     fn parse_term(&mut self) -> ParserResult<ASTNode> {
-        let mut left = self.parse_factor()?;
+        let mut left = self.parse_implicit()?;
 
         while self.pos < self.tokens.len() {
             match self.tokens.get(self.pos) {
                 Some(token) => {
                     if token.as_any().downcast_ref::<Multiply>().is_some() {
+                        let location = self.location();
                         self.pos += 1;
-                        let right = self.parse_factor()?;
-                        left = ASTNode::Multiply(Box::new(left), Box::new(right));
+                        let right = self.parse_implicit()?;
+                        left = self
+                            .located(location, ASTNode::Multiply(Box::new(left), Box::new(right)));
                     } else if token.as_any().downcast_ref::<Divide>().is_some() {
+                        let location = self.location();
+                        self.pos += 1;
+                        let right = self.parse_implicit()?;
+                        left = self
+                            .located(location, ASTNode::Divide(Box::new(left), Box::new(right)));
+                    } else if token.as_any().downcast_ref::<IntDivide>().is_some() {
+                        let location = self.location();
+                        self.pos += 1;
+                        let right = self.parse_implicit()?;
+                        left = self.located(
+                            location,
+                            ASTNode::IntDivide(Box::new(left), Box::new(right)),
+                        );
+                    } else if token.as_any().downcast_ref::<Percent>().is_some() {
+                        let location = self.location();
                         self.pos += 1;
-                        let right = self.parse_factor()?;
-                        left = ASTNode::Divide(Box::new(left), Box::new(right));
+                        let right = self.parse_implicit()?;
+                        left = self
+                            .located(location, ASTNode::Modulo(Box::new(left), Box::new(right)));
+                    } else if token.as_any().downcast_ref::<Backslash>().is_some() {
+                        let location = self.location();
+                        self.pos += 1;
+                        let right = self.parse_implicit()?;
+                        left =
+                            self.located(location, ASTNode::Solve(Box::new(left), Box::new(right)));
                     } else {
                         break;
                     }
@@ -105,34 +1110,134 @@ impl Parser {
         Ok(left)
     }
 
+    fn parse_implicit(&mut self) -> ParserResult<ASTNode> {
+        let mut left = self.parse_unary()?;
+
+        while self.implicit_multiplication && self.starts_juxtaposed_operand() {
+            let location = self.location();
+            let right = self.parse_factor()?;
+            left = self.located(location, ASTNode::Multiply(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    /// Prefix `-`, `!` and `~`. They bind looser than `^`, so `-2^2` is
+    /// `-(2^2)`.
+    fn parse_unary(&mut self) -> ParserResult<ASTNode> {
+        if self.next_is::<Minus>() {
+            self.pos += 1;
+            let operand = self.parse_unary()?;
+            Ok(ASTNode::Negate(Box::new(operand)))
+        } else if self.next_is::<Not>() {
+            self.pos += 1;
+            let operand = self.parse_unary()?;
+            Ok(ASTNode::Not(Box::new(operand)))
+        } else if self.next_is::<BitNot>() {
+            self.pos += 1;
+            let operand = self.parse_unary()?;
+            Ok(ASTNode::BitNot(Box::new(operand)))
+        } else {
+            self.parse_factor()
+        }
+    }
+
     fn parse_factor(&mut self) -> ParserResult<ASTNode> {
-        let base = self.parse_primary()?;
+        let base = self.parse_postfix()?;
         let maybe_token = self
             .tokens
             .get(self.pos)
-            .map(|x| x.as_any().downcast_ref::<Power>())
-            .flatten();
+            .and_then(|x| x.as_any().downcast_ref::<Power>());
 
         if self.pos < self.tokens.len() && maybe_token == Some(&Power) {
+            let location = self.location();
             self.pos += 1;
-            let exponent = self.parse_factor()?;
-            Ok(ASTNode::Power(Box::new(base), Box::new(exponent)))
+            let exponent = self.parse_unary()?;
+            Ok(self.located(location, ASTNode::Power(Box::new(base), Box::new(exponent))))
         } else {
             Ok(base)
         }
     }
 
+    /// Postfix `!` (factorial) and `%` (percent), binding tighter than `^`.
+    ///
+    /// A `%` directly followed by something that can start an operand is the
+    /// binary modulo operator and is left for [`Parser::parse_term`], so
+    /// `7 % 3` and `7 % -3` are modulos while `20% * 3` and `50 + 20%` are
+    /// percentages. A percentage followed by a subtraction needs parentheses,
+    /// as in `(20%) - 3`.
+    fn parse_postfix(&mut self) -> ParserResult<ASTNode> {
+        let mut operand = self.parse_primary()?;
+
+        loop {
+            if self.next_is::<Not>() {
+                self.pos += 1;
+                operand = ASTNode::Factorial(Box::new(operand));
+            } else if self.next_is::<Percent>() && !self.starts_operand(self.pos + 1) {
+                self.pos += 1;
+                operand = ASTNode::Percent(Box::new(operand));
+            } else if self.next_is::<LeftBracket>() {
+                let location = self.location();
+                self.pos += 1;
+                let index = self.parse_expression()?;
+                self.expect::<RightBracket>(RightBracket::as_str())?;
+                operand =
+                    self.located(location, ASTNode::Index(Box::new(operand), Box::new(index)));
+            } else {
+                break;
+            }
+        }
+
+        Ok(operand)
+    }
+
     fn parse_primary(&mut self) -> ParserResult<ASTNode> {
         match &self.tokens.get(self.pos) {
             Some(token) => {
                 if let Some(number) = token.as_any().downcast_ref::<Number>() {
+                    let number = number.clone();
+                    self.pos += 1;
+                    if self.units && self.unit_name_at(self.pos).is_some() {
+                        let unit = self.parse_unit()?;
+                        Ok(ASTNode::Quantity(number, unit))
+                    } else {
+                        Ok(ASTNode::Number(number))
+                    }
+                } else if let Some(imaginary) = token.as_any().downcast_ref::<Imaginary>() {
+                    self.pos += 1;
+                    Ok(ASTNode::Imaginary(imaginary.0.clone()))
+                } else if let Some(identifier) = token.as_any().downcast_ref::<Identifier>() {
+                    let name = identifier.0.clone();
+                    let location = self.location();
                     self.pos += 1;
-                    Ok(ASTNode::Number(number.0))
+                    if let TRUE | FALSE = name.as_str() {
+                        Ok(ASTNode::Boolean(name == TRUE))
+                    } else if self.next_is::<LeftParen>() {
+                        self.pos += 1;
+                        let args = self.parse_items::<RightParen>(RightParen::as_str())?;
+                        Ok(self.located(location, call(name, args)?))
+                    } else {
+                        Ok(self.located(location, ASTNode::Variable(name)))
+                    }
+                } else if let Some(cell) = token.as_any().downcast_ref::<Cell>() {
+                    let node = ASTNode::Cell(*cell);
+                    let location = self.location();
+                    self.pos += 1;
+                    Ok(self.located(location, node))
+                } else if let Some(range) = token.as_any().downcast_ref::<Range>() {
+                    let node = ASTNode::Range(*range);
+                    let location = self.location();
+                    self.pos += 1;
+                    Ok(self.located(location, node))
                 } else if token.as_any().downcast_ref::<LeftParen>().is_some() {
                     self.pos += 1;
                     let expr = self.parse_expression()?;
-                    self.pos += 1; // Consume right paren
+                    self.expect::<RightParen>(RightParen::as_str())?;
                     Ok(expr)
+                } else if token.as_any().downcast_ref::<LeftBracket>().is_some() {
+                    self.pos += 1;
+                    let items = self.parse_items::<RightBracket>(RightBracket::as_str())?;
+                    Ok(ASTNode::List(items))
                 } else {
                     Err(ParserError("Unexpected token".into()))
                 }
@@ -141,4 +1246,408 @@ impl Parser {
             _ => Err(ParserError("Unexpected token".into())),
         }
     }
+
+    /// Comma-separated arguments or list items up to and including the
+    /// closing `T`.
+    fn parse_items<T: Token>(&mut self, closing: &str) -> ParserResult<Vec<ASTNode>> {
+        let mut items = Vec::new();
+        if self.next_is::<T>() {
+            self.pos += 1;
+            return Ok(items);
+        }
+
+        loop {
+            items.push(self.parse_expression()?);
+            if self.next_is::<Comma>() {
+                self.pos += 1;
+            } else {
+                self.expect::<T>(closing)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    /// `kg*m/s^2`, `N m` or `km/h`, starting at a name.
+    fn parse_unit(&mut self) -> ParserResult<UnitExpr> {
+        let mut factors = vec![self.parse_unit_factor(1)?];
+
+        loop {
+            let sign = if self.next_is::<Multiply>() {
+                1
+            } else if self.next_is::<Divide>() {
+                -1
+            } else if self.unit_name_at(self.pos).is_some() {
+                factors.push(self.parse_unit_factor(1)?);
+                continue;
+            } else {
+                break;
+            };
+            if self.unit_name_at(self.pos + 1).is_none() {
+                break;
+            }
+            self.pos += 1;
+            factors.push(self.parse_unit_factor(sign)?);
+        }
+
+        Ok(UnitExpr { factors })
+    }
+
+    fn parse_unit_factor(&mut self, sign: i32) -> ParserResult<(String, i32)> {
+        let name = self
+            .unit_name_at(self.pos)
+            .ok_or_else(|| ParserError("Expected a unit".into()))?;
+        self.pos += 1;
+        if !self.next_is::<Power>() {
+            return Ok((name, sign));
+        }
+
+        self.pos += 1;
+        let negative = self.next_is::<Minus>();
+        if negative {
+            self.pos += 1;
+        }
+        let exponent = self
+            .tokens
+            .get(self.pos)
+            .and_then(|token| token.as_any().downcast_ref::<Number>())
+            .filter(|number| number.is_integer())
+            .and_then(|number| number.text().parse::<i32>().ok())
+            .ok_or_else(|| {
+                ParserError(format!("Expected an integer exponent for unit {}", name))
+            })?;
+        self.pos += 1;
+        Ok((name, if negative { -exponent } else { exponent } * sign))
+    }
+
+    /// The name at `pos` if it can be a unit: not the conversion keyword and
+    /// not the name of a call.
+    fn unit_name_at(&self, pos: usize) -> Option<String> {
+        let identifier = self
+            .tokens
+            .get(pos)?
+            .as_any()
+            .downcast_ref::<Identifier>()?;
+        let is_call = self
+            .tokens
+            .get(pos + 1)
+            .is_some_and(|token| token.as_any().downcast_ref::<LeftParen>().is_some());
+        (identifier.0 != CONVERSION && !is_call).then(|| identifier.0.clone())
+    }
+
+    /// The identifier at `pos`, unless it is a boolean literal.
+    fn name_at(&self, pos: usize) -> Option<String> {
+        let identifier = self
+            .tokens
+            .get(pos)?
+            .as_any()
+            .downcast_ref::<Identifier>()?;
+        match identifier.0.as_str() {
+            TRUE | FALSE => None,
+            name => Some(name.to_string()),
+        }
+    }
+
+    fn is_at<T: Token>(&self, pos: usize) -> bool {
+        self.tokens
+            .get(pos)
+            .is_some_and(|token| token.as_any().downcast_ref::<T>().is_some())
+    }
+
+    fn next_is_name(&self, name: &str) -> bool {
+        self.tokens.get(self.pos).is_some_and(|token| {
+            token
+                .as_any()
+                .downcast_ref::<Identifier>()
+                .is_some_and(|identifier| identifier.0 == name)
+        })
+    }
+
+    fn location(&self) -> Option<Location> {
+        self.locations
+            .as_ref()
+            .and_then(|locations| locations.get(self.pos).copied())
+    }
+
+    fn located(&self, location: Option<Location>, node: ASTNode) -> ASTNode {
+        match location {
+            Some(location) => ASTNode::Located(location, Box::new(node)),
+            None => node,
+        }
+    }
+
+    fn next_is<T: Token>(&self) -> bool {
+        self.is_at::<T>(self.pos)
+    }
+
+    /// Whether the token at `pos` can begin an operand, including a prefix
+    /// operator or a list literal.
+    fn starts_operand(&self, pos: usize) -> bool {
+        self.tokens.get(pos).is_some_and(|token| {
+            let token = token.as_any();
+            token.is::<Number>()
+                || token.is::<Imaginary>()
+                || token.is::<Identifier>()
+                || token.is::<Cell>()
+                || token.is::<Range>()
+                || token.is::<LeftParen>()
+                || token.is::<LeftBracket>()
+                || token.is::<Minus>()
+                || token.is::<Not>()
+                || token.is::<BitNot>()
+        })
+    }
+
+    fn starts_juxtaposed_operand(&self) -> bool {
+        if self.units && self.next_is_name(CONVERSION) {
+            return false;
+        }
+        self.next_is::<Identifier>() || self.next_is::<LeftParen>()
+    }
+
+    fn expect<T: Token>(&mut self, expected: &str) -> ParserResult<()> {
+        if self.next_is::<T>() {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(ParserError(format!("Expected '{}'", expected)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, result::AppResult};
+
+    fn parse_implicit(input: &str) -> AppResult<String> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).implicit_multiplication(true).parse()?;
+        Ok(ast.to_string())
+    }
+
+    #[test]
+    fn implicit_multiplication_of_juxtaposed_primaries() {
+        assert_eq!(parse_implicit("2(3+4)").unwrap(), "(2 * (3 + 4))");
+        assert_eq!(parse_implicit("(a+b)(a-b)").unwrap(), "((a + b) * (a - b))");
+        assert_eq!(parse_implicit("3x").unwrap(), "(3 * x)");
+        assert_eq!(parse_implicit("2pi").unwrap(), "(2 * pi)");
+        assert_eq!(parse_implicit("2 x y").unwrap(), "((2 * x) * y)");
+    }
+
+    #[test]
+    fn implicit_multiplication_binds_tighter_than_division() {
+        assert_eq!(parse_implicit("1/2x").unwrap(), "(1 / (2 * x))");
+        assert_eq!(parse_implicit("1/2x*3").unwrap(), "((1 / (2 * x)) * 3)");
+        assert_eq!(parse_implicit("2x^2").unwrap(), "(2 * (x ^ 2))");
+        assert_eq!(parse_implicit("2^3x").unwrap(), "((2 ^ 3) * x)");
+        assert_eq!(parse_implicit("-2x").unwrap(), "((-2) * x)");
+        assert_eq!(parse_implicit("3x!").unwrap(), "(3 * (x!))");
+        assert_eq!(parse_implicit("2sqrt(x)").unwrap(), "(2 * sqrt(x))");
+        assert_eq!(parse_implicit("f(x)(y)").unwrap(), "(f(x) * y)");
+        assert_eq!(parse_implicit("2x - 3").unwrap(), "((2 * x) - 3)");
+        assert_eq!(parse_implicit("1 +\n2x").unwrap(), "(1 + (2 * x))");
+    }
+
+    #[test]
+    fn implicit_multiplication_rejects_adjacent_numbers() {
+        assert!(parse_implicit("2 3").is_err());
+        assert!(parse_implicit("(1)2").is_err());
+        assert!(parse_implicit("x 2").is_err());
+    }
+
+    #[test]
+    fn implicit_multiplication_is_opt_in() {
+        let (tokens, _) = Lexer::new("2x").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    fn parse_program(input: &str) -> AppResult<String> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let program = Parser::new(tokens).parse_program()?;
+        Ok(program.to_string())
+    }
+
+    #[test]
+    fn programs_split_on_line_breaks_and_semicolons() {
+        assert_eq!(
+            parse_program("let x = 1 + 2\ny = x * 2; y").unwrap(),
+            "x = (1 + 2)\ny = (x * 2)\ny"
+        );
+        assert_eq!(parse_program("\n\n1;;2;\n").unwrap(), "1\n2");
+        assert_eq!(parse_program("f(1,\n 2)").unwrap(), "f(1, 2)");
+        assert_eq!(parse_program("let + 1").unwrap(), "(let + 1)");
+        assert!(parse_program("1 = 2").is_err());
+        assert!(parse_program("x = 1 2").is_err());
+        assert!(parse_program("x == 1 = 2").is_err());
+    }
+
+    #[test]
+    fn modulo_takes_prefixed_operands() {
+        assert_eq!(parse_program("7 % -3").unwrap(), "(7 % (-3))");
+        assert_eq!(parse_program("7 % !x").unwrap(), "(7 % (!x))");
+        assert_eq!(parse_program("7 % ~x").unwrap(), "(7 % (~x))");
+        assert_eq!(parse_program("7 % [3, 5]").unwrap(), "(7 % [3, 5])");
+        assert_eq!(parse_program("(20%) - 3").unwrap(), "((20%) - 3)");
+        assert_eq!(parse_program("20% * 3").unwrap(), "((20%) * 3)");
+    }
+
+    #[test]
+    fn lambdas_have_the_lowest_precedence() {
+        assert_eq!(parse_program("x => x + 1").unwrap(), "(x => (x + 1))");
+        assert_eq!(
+            parse_program("(a, b) => a > b ? a : b").unwrap(),
+            "((a, b) => ((a > b) ? a : b))"
+        );
+        assert_eq!(parse_program("() => 1").unwrap(), "(() => 1)");
+        assert_eq!(
+            parse_program("f = x => y => x + y").unwrap(),
+            "f = (x => (y => (x + y)))"
+        );
+        assert_eq!(
+            parse_program("map(xs, x => x * 2)").unwrap(),
+            "map(xs, (x => (x * 2)))"
+        );
+        assert_eq!(parse_program("(x) + 1").unwrap(), "(x + 1)");
+        assert!(parse_program("1 + x => x").is_err());
+        assert!(parse_program("(x, x) => x").is_err());
+        assert!(parse_program("(x, 1) => x").is_err());
+    }
+
+    #[test]
+    fn list_literals_and_indexing() {
+        assert_eq!(parse_program("[1, x + 1, []]").unwrap(), "[1, (x + 1), []]");
+        assert_eq!(parse_program("xs[i + 1]^2").unwrap(), "(xs[(i + 1)] ^ 2)");
+        assert_eq!(parse_program("m[0][1]").unwrap(), "m[0][1]");
+        assert_eq!(parse_program("-xs[0]").unwrap(), "(-xs[0])");
+        assert!(parse_program("[1, 2").is_err());
+        assert!(parse_program("xs[]").is_err());
+    }
+
+    #[test]
+    fn sums_and_products_print_as_series() {
+        assert_eq!(
+            parse_program("sum(i, 1, 10, i^2)").unwrap(),
+            "(Σ[i = 1..10] (i ^ 2))"
+        );
+        assert_eq!(
+            parse_program("prod(k, 1, n + 1, k)").unwrap(),
+            "(Π[k = 1..(n + 1)] k)"
+        );
+        assert_eq!(parse_program("sum(xs)").unwrap(), "sum(xs)");
+        assert_eq!(
+            parse_program("sum(2, 1, 10, 3)").unwrap(),
+            "sum(2, 1, 10, 3)"
+        );
+        assert_eq!(parse_program("prod(k, 1, 10)").unwrap(), "prod(k, 1, 10)");
+    }
+
+    #[test]
+    fn piecewise_pairs_conditions_with_values() {
+        assert_eq!(
+            parse_program("piecewise(x < 10, 0, x < 20, x * 0.1, 2)").unwrap(),
+            "piecewise((x < 10), 0, (x < 20), (x * 0.1), 2)"
+        );
+        assert_eq!(parse_program("piecewise(1)").unwrap(), "piecewise(1)");
+        assert_eq!(
+            parse_program("piecewise(x < 1, 2)")
+                .unwrap_err()
+                .to_string(),
+            "Error(Parser): Function piecewise expects condition, value pairs and a default, got 2 arguments"
+        );
+        assert!(parse_program("piecewise()").is_err());
+    }
+
+    #[test]
+    fn boolean_literals_are_not_names() {
+        assert_eq!(
+            parse_program("true && !false").unwrap(),
+            "(true && (!false))"
+        );
+        assert!(parse_program("true = 1").is_err());
+        assert!(parse_program("false => 1").is_err());
+    }
+
+    #[test]
+    fn if_call_is_a_conditional() {
+        assert_eq!(
+            parse_program("if(x > 0, x, -x)").unwrap(),
+            "((x > 0) ? x : (-x))"
+        );
+        assert_eq!(
+            parse_program("if(x > 0, x)").unwrap_err().to_string(),
+            "Error(Parser): Function if expects a condition and two values, as in if(x > 0, x, -x), got 2 arguments"
+        );
+    }
+
+    fn parse_located(input: &str) -> AppResult<ASTNode> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        Ok(Parser::new(tokens).locations(locations).parse()?)
+    }
+
+    #[test]
+    fn trees_compare_by_structure_not_location() {
+        let a = parse_located("price * (1 + rate)").unwrap();
+        let b = parse_located("price*(1+rate)\n").unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, parse_located("price * (1 + tax)").unwrap());
+        assert_ne!(a, parse_located("(1 + rate) * price").unwrap());
+        assert_ne!(parse_located("1").unwrap(), parse_located("1.0").unwrap());
+
+        let formulas = ["x + 1", "x+1", "  x +  1", "x - 1", "f(x) + 1"];
+        let unique: std::collections::HashSet<_> = formulas
+            .iter()
+            .map(|formula| parse_located(formula).unwrap())
+            .collect();
+        assert_eq!(unique.len(), 3);
+    }
+
+    #[test]
+    fn trees_have_a_total_order() {
+        let mut nodes: Vec<_> = ["x", "10", "2", "2.0", "a + b", "0.5"]
+            .iter()
+            .map(|input| parse_located(input).unwrap())
+            .collect();
+        nodes.sort();
+        let sorted: Vec<_> = nodes.iter().map(ASTNode::to_string).collect();
+        assert_eq!(sorted, ["0.5", "2", "2.0", "10", "(a + b)", "x"]);
+    }
+
+    #[test]
+    fn nan_and_negative_zero_literals_are_ordered() {
+        let literal = |value: f64| ASTNode::Number(Number::from(value));
+        assert_eq!(literal(f64::NAN), literal(-f64::NAN));
+        assert_ne!(literal(-0.0), literal(0.0));
+
+        let mut nodes = [
+            literal(f64::NAN),
+            literal(0.0),
+            literal(f64::INFINITY),
+            literal(-0.0),
+            literal(-1.0),
+        ];
+        nodes.sort();
+        let sorted: Vec<_> = nodes.iter().map(ASTNode::to_string).collect();
+        assert_eq!(sorted, ["-1", "-0", "0", "inf", "NaN"]);
+    }
+
+    #[test]
+    fn literals_order_by_exact_value() {
+        let literal = |text: &str| Number::parse(text).unwrap();
+        // Both round to the same `f64`.
+        assert!(literal("09007199254740993") > literal("9007199254740992.5"));
+        assert!(literal("0.30000000000000001") > literal("0.3"));
+        assert!(literal("0.5") < literal("0.50"));
+        assert!(literal("1.25") < literal("12.5"));
+
+        let mut numbers = [
+            Number::from(-2.5),
+            literal("0.10"),
+            Number::from(-10.0),
+            Number::from(f64::NEG_INFINITY),
+            literal("0.1"),
+        ];
+        numbers.sort();
+        let sorted: Vec<_> = numbers.iter().map(Number::to_string).collect();
+        assert_eq!(sorted, ["-inf", "-10", "-2.5", "0.1", "0.10"]);
+    }
 }
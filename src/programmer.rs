@@ -0,0 +1,323 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use crate::{
+    evaluator::{EvalError, EvalResult},
+    parser::ASTNode,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WordSize {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    #[default]
+    I64,
+}
+
+impl WordSize {
+    pub const fn bits(self) -> u32 {
+        match self {
+            WordSize::U8 | WordSize::I8 => 8,
+            WordSize::U16 | WordSize::I16 => 16,
+            WordSize::U32 | WordSize::I32 => 32,
+            WordSize::U64 | WordSize::I64 => 64,
+        }
+    }
+
+    pub const fn is_signed(self) -> bool {
+        matches!(
+            self,
+            WordSize::I8 | WordSize::I16 | WordSize::I32 | WordSize::I64
+        )
+    }
+
+    const fn mask(self) -> u64 {
+        u64::MAX >> (64 - self.bits())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+/// A two's complement integer of a fixed [`WordSize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Word {
+    bits: u64,
+    size: WordSize,
+}
+
+impl Word {
+    /// Keeps the low `size.bits()` bits of `value`.
+    pub fn new(value: i128, size: WordSize) -> Self {
+        Word {
+            bits: value as u64 & size.mask(),
+            size,
+        }
+    }
+
+    /// The raw bit pattern.
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn size(&self) -> WordSize {
+        self.size
+    }
+
+    /// The bit pattern read as a signed or unsigned number, as the word size
+    /// says.
+    pub fn value(&self) -> i128 {
+        let bits = self.size.bits();
+        if self.size.is_signed() && self.bits >> (bits - 1) == 1 {
+            self.bits as i128 - (1i128 << bits)
+        } else {
+            self.bits as i128
+        }
+    }
+
+    /// Binary, octal and hexadecimal show the bit pattern with a `0b`, `0o`
+    /// or `0x` prefix, so `-1` as an `I8` is `0xff`.
+    pub fn format(&self, radix: Radix) -> String {
+        match radix {
+            Radix::Binary => format!("0b{:b}", self.bits),
+            Radix::Octal => format!("0o{:o}", self.bits),
+            Radix::Decimal => self.value().to_string(),
+            Radix::Hexadecimal => format!("0x{:x}", self.bits),
+        }
+    }
+}
+
+impl Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+/// Evaluates an [`ASTNode`] over fixed-width [`Word`]s.
+///
+/// Arithmetic wraps like two's complement hardware: `+`, `-`, `*`, `^` and
+/// negation keep the low bits, `/` and `%` truncate as in C, and `>>` is an
+/// arithmetic shift for signed sizes and a logical one for unsigned sizes.
+/// Literals that do not fit in the word are rejected rather than
+/// truncated. Division by zero and negative shift amounts are errors.
+#[derive(Debug, Default)]
+pub struct ProgrammerEvaluator {
+    size: WordSize,
+    variables: HashMap<String, i128>,
+}
+
+impl ProgrammerEvaluator {
+    pub fn new(size: WordSize) -> Self {
+        ProgrammerEvaluator {
+            size,
+            variables: HashMap::new(),
+        }
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: i128) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn evaluate(&self, node: &ASTNode) -> EvalResult<Word> {
+        match node {
+            ASTNode::Located(_, node) => self.evaluate(node),
+            ASTNode::Number(n) => {
+                let value = n
+                    .is_integer()
+                    .then(|| n.text().parse::<u128>().ok())
+                    .flatten()
+                    .ok_or_else(|| {
+                        EvalError::new(format!("Non-integer literal in programmer mode: {}", n))
+                    })?;
+                if value > self.size.mask() as u128 {
+                    return Err(EvalError::new(format!(
+                        "Literal does not fit in {} bits: {}",
+                        self.size.bits(),
+                        n
+                    )));
+                }
+                Ok(Word::new(value as i128, self.size))
+            }
+            ASTNode::Variable(name) => {
+                let value = *self
+                    .variables
+                    .get(name)
+                    .ok_or_else(|| EvalError::new(format!("Unknown variable: {}", name)))?;
+                let word = Word::new(value, self.size);
+                if word.value() != value && word.bits() as i128 != value {
+                    return Err(EvalError::new(format!(
+                        "Variable does not fit in {} bits: {}",
+                        self.size.bits(),
+                        name
+                    )));
+                }
+                Ok(word)
+            }
+            ASTNode::Add(l, r) => self.binary(l, r, |a, b| Ok(a.wrapping_add(b))),
+            ASTNode::Subtract(l, r) => self.binary(l, r, |a, b| Ok(a.wrapping_sub(b))),
+            ASTNode::Multiply(l, r) => self.binary(l, r, |a, b| Ok(a.wrapping_mul(b))),
+            ASTNode::Divide(l, r) => self.binary(l, r, |a, b| Ok(a / nonzero(b)?)),
+            ASTNode::Modulo(l, r) => self.binary(l, r, |a, b| Ok(a % nonzero(b)?)),
+            ASTNode::IntDivide(l, r) => self.binary(l, r, |a, b| {
+                let b = nonzero(b)?;
+                let quotient = a / b;
+                if a % b != 0 && (a < 0) != (b < 0) {
+                    Ok(quotient - 1)
+                } else {
+                    Ok(quotient)
+                }
+            }),
+            ASTNode::Power(l, r) => self.binary(l, r, |a, b| {
+                let exponent = u32::try_from(b).map_err(|_| {
+                    EvalError::new(format!("Exponent must be a non-negative integer: {}", b))
+                })?;
+                Ok(a.wrapping_pow(exponent))
+            }),
+            ASTNode::Negate(n) => Ok(Word::new(
+                self.evaluate(n)?.value().wrapping_neg(),
+                self.size,
+            )),
+            ASTNode::Factorial(n) => {
+                let n = self.evaluate(n)?.value();
+                if n < 0 {
+                    return Err(EvalError::new(format!(
+                        "Factorial of a negative number: {}",
+                        n
+                    )));
+                }
+                // Past 2^bits every product is 0 in the low bits anyway.
+                let product = (2..=n.min(130)).fold(1i128, |acc, k| acc.wrapping_mul(k));
+                Ok(Word::new(product, self.size))
+            }
+            ASTNode::BitAnd(l, r) => self.binary(l, r, |a, b| Ok(a & b)),
+            ASTNode::BitOr(l, r) => self.binary(l, r, |a, b| Ok(a | b)),
+            ASTNode::BitXor(l, r) => self.binary(l, r, |a, b| Ok(a ^ b)),
+            ASTNode::BitNot(n) => Ok(Word::new(!self.evaluate(n)?.value(), self.size)),
+            ASTNode::ShiftLeft(l, r) => self.binary(l, r, |a, b| {
+                let amount = shift_amount(b)?;
+                Ok(if amount >= 64 { 0 } else { a << amount })
+            }),
+            ASTNode::ShiftRight(l, r) => {
+                let word = self.evaluate(l)?;
+                let amount = shift_amount(self.evaluate(r)?.value())?;
+                let shifted = if self.size.is_signed() {
+                    word.value() >> amount.min(127)
+                } else if amount >= 64 {
+                    0
+                } else {
+                    (word.bits() >> amount) as i128
+                };
+                Ok(Word::new(shifted, self.size))
+            }
+            node => Err(EvalError::new(format!(
+                "Not supported in programmer mode: {}",
+                node
+            ))),
+        }
+    }
+
+    fn binary(
+        &self,
+        left: &ASTNode,
+        right: &ASTNode,
+        op: impl FnOnce(i128, i128) -> EvalResult<i128>,
+    ) -> EvalResult<Word> {
+        let a = self.evaluate(left)?.value();
+        let b = self.evaluate(right)?.value();
+        Ok(Word::new(op(a, b)?, self.size))
+    }
+}
+
+fn nonzero(divisor: i128) -> EvalResult<i128> {
+    if divisor == 0 {
+        return Err(EvalError::new("Division by zero"));
+    }
+    Ok(divisor)
+}
+
+fn shift_amount(amount: i128) -> EvalResult<u32> {
+    u32::try_from(amount).map_err(|_| EvalError::new(format!("Negative shift amount: {}", amount)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval(size: WordSize, input: &str) -> AppResult<Word> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        Ok(ProgrammerEvaluator::new(size).evaluate(&ast)?)
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(eval(WordSize::U8, "0xF0 | 0x0F").unwrap().value(), 0xFF);
+        assert_eq!(
+            eval(WordSize::U8, "0b1100 & 0b1010").unwrap().value(),
+            0b1000
+        );
+        assert_eq!(
+            eval(WordSize::U8, "0b1100 xor 0b1010").unwrap().value(),
+            0b0110
+        );
+        assert_eq!(eval(WordSize::U8, "~0").unwrap().value(), 255);
+        assert_eq!(eval(WordSize::I8, "~0").unwrap().value(), -1);
+        assert_eq!(eval(WordSize::U16, "1 << 4 | 1").unwrap().value(), 17);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval(WordSize::U8, "1 + 1 << 2").unwrap().value(), 8);
+        assert_eq!(eval(WordSize::U8, "6 & 3 xor 1 | 8").unwrap().value(), 11);
+    }
+
+    #[test]
+    fn twos_complement_wrapping() {
+        assert_eq!(eval(WordSize::U8, "255 + 1").unwrap().value(), 0);
+        assert_eq!(eval(WordSize::I8, "127 + 1").unwrap().value(), -128);
+        assert_eq!(eval(WordSize::U8, "0 - 1").unwrap().value(), 255);
+        assert_eq!(eval(WordSize::U64, "0 - 1").unwrap().bits(), u64::MAX);
+        assert_eq!(
+            eval(WordSize::U64, "0xFFFFFFFFFFFFFFFF * 2")
+                .unwrap()
+                .bits(),
+            u64::MAX - 1
+        );
+        assert_eq!(eval(WordSize::I32, "-7 / 2").unwrap().value(), -3);
+        assert_eq!(eval(WordSize::I32, "-7 % 2").unwrap().value(), -1);
+        assert!(eval(WordSize::U8, "256").is_err());
+        assert!(eval(WordSize::U8, "1 / 0").is_err());
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(eval(WordSize::I8, "-16 >> 2").unwrap().value(), -4);
+        assert_eq!(eval(WordSize::U8, "0xF0 >> 4").unwrap().value(), 0x0F);
+        assert_eq!(eval(WordSize::U8, "1 << 8").unwrap().value(), 0);
+        assert_eq!(eval(WordSize::I8, "-1 >> 100").unwrap().value(), -1);
+        assert!(eval(WordSize::I8, "1 << -1").is_err());
+    }
+
+    #[test]
+    fn radix_output() {
+        let word = eval(WordSize::I8, "-1").unwrap();
+        assert_eq!(word.format(Radix::Hexadecimal), "0xff");
+        assert_eq!(word.format(Radix::Binary), "0b11111111");
+        assert_eq!(word.format(Radix::Octal), "0o377");
+        assert_eq!(word.format(Radix::Decimal), "-1");
+        assert_eq!(eval(WordSize::U16, "0o17").unwrap().to_string(), "15");
+    }
+}
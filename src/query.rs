@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use crate::{location::Location, parser::ASTNode};
+
+/// The free variables an expression reads and the functions it calls, each
+/// with where it appears.
+///
+/// Names bound inside the expression itself, the parameters of a lambda and
+/// the index of a `sum` or `prod`, are left out within their scope. A called
+/// name is listed under `functions` even though the call goes to a lambda
+/// when a variable of that name holds one, so an input can appear there. Every
+/// occurrence has a location when the tree was parsed with
+/// [`Parser::locations`]; otherwise the lists are empty and only the names
+/// are known.
+///
+/// [`Parser::locations`]: crate::parser::Parser::locations
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct References {
+    pub variables: BTreeMap<String, Vec<Location>>,
+    pub functions: BTreeMap<String, Vec<Location>>,
+}
+
+impl ASTNode {
+    /// The variables and functions this expression refers to.
+    pub fn references(&self) -> References {
+        let mut references = References::default();
+        collect(self, None, &mut Vec::new(), &mut references);
+        references
+    }
+
+    /// Whether the value cannot depend on any binding, because the
+    /// expression reads no variables and no spreadsheet cells and calls no
+    /// functions. Names such as `pi` count as variables, since a binding can
+    /// shadow them, and calls count too, since a variable holding a lambda
+    /// or a user function of the same name takes the place of a built-in,
+    /// so `sqrt(2)` is not constant on its own.
+    pub fn is_constant(&self) -> bool {
+        self.is_constant_calling(|_| false)
+    }
+
+    /// Like [`ASTNode::is_constant`], but calls to the functions `builtin`
+    /// accepts, which are known to reach a built-in, are allowed.
+    pub(crate) fn is_constant_calling(&self, builtin: impl Fn(&str) -> bool) -> bool {
+        let references = self.references();
+        references.variables.is_empty()
+            && references.functions.keys().all(|name| builtin(name))
+            && !reads_cells(self)
+    }
+}
+
+fn reads_cells(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::Cell(_) | ASTNode::Range(_))
+        || node.children().into_iter().any(reads_cells)
+}
+
+/// Adds what `node` refers to, where `location` is that of an enclosing
+/// [`ASTNode::Located`] and `bound` the names bound around it.
+fn collect(
+    node: &ASTNode,
+    location: Option<Location>,
+    bound: &mut Vec<String>,
+    references: &mut References,
+) {
+    let add = |names: &mut BTreeMap<String, Vec<Location>>, name: &String| {
+        if !bound.contains(name) {
+            names.entry(name.clone()).or_default().extend(location);
+        }
+    };
+    match node {
+        ASTNode::Located(location, node) => collect(node, Some(*location), bound, references),
+        ASTNode::Variable(name) => add(&mut references.variables, name),
+        ASTNode::Call(name, args) => {
+            add(&mut references.functions, name);
+            for arg in args {
+                collect(arg, None, bound, references);
+            }
+        }
+        ASTNode::Lambda(params, body) => {
+            let outer = bound.len();
+            bound.extend(params.iter().cloned());
+            collect(body, None, bound, references);
+            bound.truncate(outer);
+        }
+        ASTNode::Sum(index, from, to, body) | ASTNode::Product(index, from, to, body) => {
+            collect(from, None, bound, references);
+            collect(to, None, bound, references);
+            bound.push(index.clone());
+            collect(body, None, bound, references);
+            bound.pop();
+        }
+        node => {
+            for child in node.children() {
+                collect(child, None, bound, references);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn parse(input: &str) -> AppResult<ASTNode> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        Ok(Parser::new(tokens).locations(locations).parse()?)
+    }
+
+    /// Each name with the `(line, column)` of its occurrences.
+    fn positions(names: &BTreeMap<String, Vec<Location>>) -> Vec<(&str, Vec<(usize, usize)>)> {
+        names
+            .iter()
+            .map(|(name, locations)| {
+                let positions = locations.iter().map(|l| (l.line(), l.col())).collect();
+                (name.as_str(), positions)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn variables_and_functions_with_their_locations() {
+        let node = parse("rate * gross + max(gross, floor(bonus))\n - rate").unwrap();
+        let references = node.references();
+        assert_eq!(
+            positions(&references.variables),
+            [
+                ("bonus", vec![(1, 33)]),
+                ("gross", vec![(1, 8), (1, 20)]),
+                ("rate", vec![(1, 1), (2, 4)]),
+            ]
+        );
+        assert_eq!(
+            positions(&references.functions),
+            [("floor", vec![(1, 27)]), ("max", vec![(1, 16)])]
+        );
+    }
+
+    #[test]
+    fn bound_names_are_not_free() {
+        let node = parse("map(xs, x => x * k) + sum(i, 1, n, i * x)").unwrap();
+        let references = node.references();
+        assert_eq!(
+            references.variables.keys().collect::<Vec<_>>(),
+            ["k", "n", "x", "xs"]
+        );
+        assert_eq!(positions(&references.variables)[2], ("x", vec![(1, 40)]));
+        assert_eq!(references.functions.keys().collect::<Vec<_>>(), ["map"]);
+    }
+
+    #[test]
+    fn constants_read_no_variables() {
+        let constant = |input| parse(input).unwrap().is_constant();
+        assert!(constant("1 + 2 * 3"));
+        assert!(constant("[1, 2][0] + sum(i, 1, 10, i^2)"));
+        assert!(constant("x => x + 1"));
+        assert!(constant("f => f(2)"));
+        assert!(!constant("sqrt(2) + max(1, 2)"));
+        assert!(!constant("f(3)"));
+        assert!(!constant("x + 1"));
+        assert!(!constant("2 * pi"));
+        assert!(!constant("sum(i, 1, n, i)"));
+        assert!(!constant("x => x + y"));
+
+        let node = parse("f(3) + sqrt(4)").unwrap();
+        assert!(node.is_constant_calling(|name| name == "sqrt" || name == "f"));
+        assert!(!node.is_constant_calling(|name| name == "sqrt"));
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    ops::{Add, Mul, Neg, Sub},
+};
+
+use crate::{
+    bigint::BigInt,
+    evaluator::{EvalError, EvalResult, Evaluator},
+    numeric::Numeric,
+    tokens::Number,
+};
+
+/// An exact fraction, kept in lowest terms with a positive denominator.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: BigInt,
+    denominator: BigInt,
+}
+
+impl Rational {
+    /// Returns `None` when `denominator` is zero.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Option<Self> {
+        if denominator.is_zero() {
+            return None;
+        }
+        let gcd = numerator.gcd(&denominator);
+        let (mut numerator, _) = numerator.div_rem(&gcd);
+        let (mut denominator, _) = denominator.div_rem(&gcd);
+        if denominator.is_negative() {
+            numerator = -numerator;
+            denominator = -denominator;
+        }
+        Some(Rational {
+            numerator,
+            denominator,
+        })
+    }
+
+    pub fn from_integer(n: BigInt) -> Self {
+        Rational {
+            numerator: n,
+            denominator: BigInt::one(),
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::from_integer(BigInt::zero())
+    }
+
+    /// Parses a decimal literal such as `12`, `-0.125` or `3.10` exactly.
+    pub fn parse_decimal(text: &str) -> Option<Self> {
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let digits = format!("{}{}", integer, fraction);
+        let mut numerator = BigInt::parse(&digits)?;
+        if negative {
+            numerator = -numerator;
+        }
+        let denominator = BigInt::from(10u64).pow(fraction.len() as u32);
+        Rational::new(numerator, denominator)
+    }
+
+    pub fn numerator(&self) -> &BigInt {
+        &self.numerator
+    }
+
+    pub fn denominator(&self) -> &BigInt {
+        &self.denominator
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator.is_zero()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator.is_one()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.numerator.is_negative()
+    }
+
+    /// Returns `None` when dividing by zero.
+    pub fn checked_div(&self, divisor: &Rational) -> Option<Rational> {
+        Rational::new(
+            &self.numerator * &divisor.denominator,
+            &self.denominator * &divisor.numerator,
+        )
+    }
+
+    /// Raises to an integer power. Fails for a negative power of zero and
+    /// when the numerator or denominator would exceed
+    /// [`MAX_POWER_BITS`](crate::bigint::MAX_POWER_BITS).
+    pub fn pow(&self, exponent: i64) -> EvalResult<Rational> {
+        let too_large = || {
+            EvalError::new(format!(
+                "Power of {} too large: exponent {}",
+                self, exponent
+            ))
+        };
+        let magnitude = u32::try_from(exponent.unsigned_abs()).map_err(|_| too_large())?;
+        let power = Rational {
+            numerator: self
+                .numerator
+                .checked_pow(magnitude)
+                .ok_or_else(too_large)?,
+            denominator: self
+                .denominator
+                .checked_pow(magnitude)
+                .ok_or_else(too_large)?,
+        };
+        if exponent < 0 {
+            Rational::from_integer(BigInt::one())
+                .checked_div(&power)
+                .ok_or_else(|| EvalError::new("Division by zero"))
+        } else {
+            Ok(power)
+        }
+    }
+
+    /// Rounds towards negative infinity.
+    pub fn floor(&self) -> BigInt {
+        self.numerator.div_floor(&self.denominator)
+    }
+
+    /// Formats as a decimal with at most `max_places` fractional digits,
+    /// rounding half away from zero. Terminating expansions that fit are
+    /// exact and printed without trailing zeros, so `1/8` is `0.125` while
+    /// `1/3` with four places is `0.3333`.
+    pub fn to_decimal_string(&self, max_places: usize) -> String {
+        let scale = BigInt::from(10u64).pow(max_places as u32);
+        let (mut scaled, remainder) = (&self.numerator.abs() * &scale).div_rem(&self.denominator);
+        if &remainder.abs() * &BigInt::from(2u64) >= self.denominator {
+            scaled = scaled + BigInt::one();
+        }
+
+        let digits = format!("{:0>width$}", scaled.to_string(), width = max_places + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - max_places);
+        let fraction = fraction.trim_end_matches('0');
+
+        let mut output = String::new();
+        if self.is_negative() && !scaled.is_zero() {
+            output.push('-');
+        }
+        output.push_str(integer);
+        if !fraction.is_empty() {
+            output.push('.');
+            output.push_str(fraction);
+        }
+        output
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.numerator * &other.denominator).cmp(&(&other.numerator * &self.denominator))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, other: &Rational) -> Rational {
+        Rational::new(
+            &(&self.numerator * &other.denominator) + &(&other.numerator * &self.denominator),
+            &self.denominator * &other.denominator,
+        )
+        .expect("denominators are never zero")
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, other: &Rational) -> Rational {
+        self + &-other
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(
+            &self.numerator * &other.numerator,
+            &self.denominator * &other.denominator,
+        )
+        .expect("denominators are never zero")
+    }
+}
+
+impl Neg for &Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational {
+            numerator: -&self.numerator,
+            denominator: self.denominator.clone(),
+        }
+    }
+}
+
+impl Numeric for Rational {
+    type Context = ();
+
+    fn from_literal(literal: &Number) -> EvalResult<Self> {
+        Rational::parse_decimal(literal.text())
+            .ok_or_else(|| EvalError::new(format!("Invalid number: {}", literal)))
+    }
+
+    fn add(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(self + other)
+    }
+
+    fn sub(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(self - other)
+    }
+
+    fn mul(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        Ok(self * other)
+    }
+
+    fn div(&self, other: &Self, _: &()) -> EvalResult<Self> {
+        self.checked_div(other)
+            .ok_or_else(|| EvalError::new("Division by zero"))
+    }
+
+    fn pow(&self, exponent: &Self, _: &()) -> EvalResult<Self> {
+        let exponent = exponent
+            .is_integer()
+            .then(|| exponent.numerator().to_i128())
+            .flatten()
+            .and_then(|n| i64::try_from(n).ok())
+            .ok_or_else(|| {
+                EvalError::new(format!(
+                    "Exponent {} has no exact rational result",
+                    exponent
+                ))
+            })?;
+        Rational::pow(self, exponent)
+    }
+
+    fn neg(&self) -> EvalResult<Self> {
+        Ok(-self)
+    }
+
+    fn compare(&self, other: &Self) -> EvalResult<Option<Ordering>> {
+        Ok(Some(self.cmp(other)))
+    }
+
+    fn floor(&self) -> EvalResult<Self> {
+        Ok(Rational::from_integer(Rational::floor(self)))
+    }
+
+    fn factorial(&self) -> EvalResult<Self> {
+        if self.is_negative() {
+            return Err(EvalError::new(format!(
+                "Factorial of a negative number: {}",
+                self
+            )));
+        }
+        if !self.is_integer() {
+            return Err(EvalError::new(format!(
+                "Factorial of a non-integer: {}",
+                self
+            )));
+        }
+        let n = self
+            .numerator()
+            .to_i128()
+            .and_then(|n| u64::try_from(n).ok())
+            .ok_or_else(|| EvalError::new(format!("Factorial argument too large: {}", self)))?;
+        let product = (1..=n).fold(BigInt::one(), |acc, k| &acc * &BigInt::from(k));
+        Ok(Rational::from_integer(product))
+    }
+
+    fn to_index(&self) -> EvalResult<usize> {
+        self.is_integer()
+            .then(|| self.numerator().to_i128())
+            .flatten()
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or_else(|| EvalError::new(format!("Invalid index: {}", self)))
+    }
+}
+
+/// Evaluates an [`ASTNode`] exactly over [`Rational`]s.
+///
+/// Literals are parsed from their source text, so `0.1 + 0.2` is exactly
+/// `3/10` and `1/3 + 1/3 + 1/3` is exactly `1`. `^` only accepts integer
+/// exponents, since anything else generally has no rational result. There
+/// are no built-in functions or `pi`/`e` constants, which are irrational;
+/// everything else works as with [`Evaluator`].
+pub type RationalEvaluator = Evaluator<Rational>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluator::Value, lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn run(input: &str) -> AppResult<Value<Rational>> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        let program = Parser::new(tokens).locations(locations).parse_program()?;
+        Ok(RationalEvaluator::default().run(&program)?)
+    }
+
+    fn eval(input: &str) -> AppResult<Rational> {
+        match run(input)? {
+            Value::Number(n) => Ok(n),
+            value => panic!("Expected a number, got {}", value),
+        }
+    }
+
+    fn rational(text: &str) -> Rational {
+        Rational::parse_decimal(text).unwrap()
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        assert_eq!(eval("1/3 + 1/3 + 1/3").unwrap(), rational("1"));
+        assert_eq!(eval("0.1 + 0.2").unwrap(), rational("0.3"));
+        assert_eq!(eval("0.1 + 0.2").unwrap().to_string(), "3/10");
+        assert_eq!(eval("7 // 2").unwrap(), rational("3"));
+        assert_eq!(eval("-7 % 2").unwrap(), rational("1"));
+        assert_eq!(eval("20!").unwrap().to_string(), "2432902008176640000");
+        assert!(eval("1 / (3 - 3)").is_err());
+    }
+
+    #[test]
+    fn integer_powers_stay_exact() {
+        assert_eq!(eval("(2/3)^3").unwrap().to_string(), "8/27");
+        assert_eq!(eval("2^-2").unwrap().to_string(), "1/4");
+        assert_eq!(
+            eval("2^100").unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert!(eval("2^0.5").is_err());
+        assert_eq!(
+            eval("0^-1").unwrap_err().to_string(),
+            "Error(Eval): Division by zero in line 1 at column 2"
+        );
+        assert_eq!(
+            eval("2^3000000000").unwrap_err().to_string(),
+            "Error(Eval): Power of 2 too large: exponent 3000000000 in line 1 at column 2"
+        );
+        assert!(eval("(1/2)^-300000").is_err());
+        assert_eq!(eval("1^3000000000").unwrap().to_string(), "1");
+        assert_eq!(eval("(-1)^3000000001").unwrap().to_string(), "-1");
+    }
+
+    #[test]
+    fn programs_lists_and_comparisons() {
+        assert_eq!(
+            eval("third(x) = x / 3\nthird(1) + third(2)").unwrap(),
+            rational("1")
+        );
+        assert_eq!(
+            run("map([1, 2, 3], n => 1 / n)").unwrap().to_string(),
+            "[1, 1/2, 1/3]"
+        );
+        assert_eq!(eval("sum([1/2, 1/3, 1/6])").unwrap(), rational("1"));
+        assert_eq!(eval("median([1/3, 1/4, 1/2])").unwrap().to_string(), "1/3");
+        assert_eq!(run("1/3 < 0.34").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("0.1 * 3 == 0.3 ? 1 : 0").unwrap(), rational("1"));
+        assert!(eval("sqrt(2)").is_err());
+        assert!(eval("pi").is_err());
+    }
+
+    #[test]
+    fn decimal_output() {
+        assert_eq!(eval("1/8").unwrap().to_decimal_string(10), "0.125");
+        assert_eq!(eval("1/3").unwrap().to_decimal_string(4), "0.3333");
+        assert_eq!(eval("-2/3").unwrap().to_decimal_string(4), "-0.6667");
+        assert_eq!(eval("5").unwrap().to_decimal_string(2), "5");
+        assert_eq!(eval("-1/1000").unwrap().to_decimal_string(2), "0");
+    }
+}
@@ -1,6 +1,6 @@
 use std::{error::Error, fmt::Display};
 
-use crate::{lexer::LexerError, parser::ParserError};
+use crate::{evaluator::EvalError, lexer::LexerError, parser::ParserError};
 
 pub type AppResult<T> = Result<T, AppError>;
 
@@ -8,6 +8,7 @@ pub type AppResult<T> = Result<T, AppError>;
 pub enum AppError {
     Lexer(LexerError),
     Parser(ParserError),
+    Eval(EvalError),
 }
 impl From<LexerError> for AppError {
     fn from(error: LexerError) -> Self {
@@ -19,12 +20,18 @@ impl From<ParserError> for AppError {
         Self::Parser(error)
     }
 }
+impl From<EvalError> for AppError {
+    fn from(error: EvalError) -> Self {
+        Self::Eval(error)
+    }
+}
 impl Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut output = "Error".to_string();
         let s = match self {
             AppError::Lexer(err) => format!("(Lexer): {err}"),
             AppError::Parser(err) => format!("(Parser): {err}"),
+            AppError::Eval(err) => format!("(Eval): {err}"),
         };
         output.push_str(s.as_str());
 
@@ -0,0 +1,193 @@
+use std::collections::BTreeSet;
+
+use crate::parser::ASTNode;
+
+/// `expr` with every free occurrence of the variable `name` replaced by
+/// `replacement`.
+///
+/// Occurrences bound by a lambda parameter or the index of a `sum` or `prod`
+/// of the same name are left alone, and so are calls such as `name(x)`. A
+/// parameter or index that would capture a free variable of `replacement`
+/// is renamed first, so substituting `x` for `y` in `x => x + y` gives
+/// `x1 => x1 + x`.
+pub fn substitute(expr: &ASTNode, name: &str, replacement: &ASTNode) -> ASTNode {
+    let free = replacement.references().variables.into_keys().collect();
+    replace(expr, name, replacement, &free)
+}
+
+fn replace(node: &ASTNode, name: &str, replacement: &ASTNode, free: &BTreeSet<String>) -> ASTNode {
+    match node {
+        ASTNode::Variable(variable) if variable == name => replacement.clone(),
+        ASTNode::Located(_, inner) if is_variable(inner, name) => replacement.clone(),
+        ASTNode::Lambda(params, _) if params.iter().any(|param| param == name) => node.clone(),
+        ASTNode::Lambda(params, body) => {
+            let (params, body) = unbind(params, body, name, free);
+            ASTNode::Lambda(params, Box::new(replace(&body, name, replacement, free)))
+        }
+        ASTNode::Sum(index, from, to, body) | ASTNode::Product(index, from, to, body) => {
+            let from = replace(from, name, replacement, free);
+            let to = replace(to, name, replacement, free);
+            let (index, body) = if index == name {
+                (index.clone(), (**body).clone())
+            } else {
+                let (mut index, body) = unbind(std::slice::from_ref(index), body, name, free);
+                let body = replace(&body, name, replacement, free);
+                (index.remove(0), body)
+            };
+            let series = match node {
+                ASTNode::Sum(..) => ASTNode::Sum,
+                _ => ASTNode::Product,
+            };
+            series(index, Box::new(from), Box::new(to), Box::new(body))
+        }
+        node => node.map_children(&mut |child| replace(child, name, replacement, free)),
+    }
+}
+
+fn is_variable(node: &ASTNode, name: &str) -> bool {
+    matches!(node, ASTNode::Variable(variable) if variable == name)
+}
+
+/// Renames the `params` of `body` that are among `free`, when substituting
+/// `name` in `body` would otherwise put a free variable under them.
+fn unbind(
+    params: &[String],
+    body: &ASTNode,
+    name: &str,
+    free: &BTreeSet<String>,
+) -> (Vec<String>, ASTNode) {
+    let mut params = params.to_vec();
+    let mut body = body.clone();
+    if !body.references().variables.contains_key(name) {
+        return (params, body);
+    }
+
+    for i in 0..params.len() {
+        if !free.contains(&params[i]) {
+            continue;
+        }
+        let references = body.references();
+        let taken = |candidate: &String| {
+            free.contains(candidate)
+                || params.contains(candidate)
+                || candidate == name
+                || references.variables.contains_key(candidate)
+                || references.functions.contains_key(candidate)
+                || binds(&body, candidate)
+        };
+        let fresh = (1..)
+            .map(|n| format!("{}{}", params[i], n))
+            .find(|candidate| !taken(candidate))
+            .unwrap_or_else(|| unreachable!("names are unbounded"));
+        body = rename(&body, &params[i], &fresh);
+        params[i] = fresh;
+    }
+    (params, body)
+}
+
+/// `node` with the free uses of `old`, as a variable or as the function of a
+/// call, renamed to `new`.
+fn rename(node: &ASTNode, old: &str, new: &str) -> ASTNode {
+    match node {
+        ASTNode::Variable(variable) if variable == old => ASTNode::Variable(new.to_string()),
+        ASTNode::Call(function, args) if function == old => ASTNode::Call(
+            new.to_string(),
+            args.iter().map(|arg| rename(arg, old, new)).collect(),
+        ),
+        ASTNode::Lambda(params, _) if params.iter().any(|param| param == old) => node.clone(),
+        ASTNode::Sum(index, from, to, body) | ASTNode::Product(index, from, to, body)
+            if index == old =>
+        {
+            let series = match node {
+                ASTNode::Sum(..) => ASTNode::Sum,
+                _ => ASTNode::Product,
+            };
+            series(
+                index.clone(),
+                Box::new(rename(from, old, new)),
+                Box::new(rename(to, old, new)),
+                body.clone(),
+            )
+        }
+        node => node.map_children(&mut |child| rename(child, old, new)),
+    }
+}
+
+/// Whether a lambda parameter or a series index in `node` is called `name`.
+fn binds(node: &ASTNode, name: &str) -> bool {
+    let bound = match node {
+        ASTNode::Lambda(params, _) => params.iter().any(|param| param == name),
+        ASTNode::Sum(index, ..) | ASTNode::Product(index, ..) => index == name,
+        _ => false,
+    };
+    bound || node.children().into_iter().any(|child| binds(child, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn parse(input: &str) -> AppResult<ASTNode> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        Ok(Parser::new(tokens).locations(locations).parse()?)
+    }
+
+    fn substituted(expr: &str, name: &str, replacement: &str) -> String {
+        let expr = parse(expr).unwrap();
+        let replacement = parse(replacement).unwrap();
+        substitute(&expr, name, &replacement).to_string()
+    }
+
+    #[test]
+    fn free_occurrences_are_replaced() {
+        assert_eq!(
+            substituted("price * qty + qty", "qty", "n + 1"),
+            "((price * (n + 1)) + (n + 1))"
+        );
+        assert_eq!(substituted("f(x) + g", "f", "2"), "(f(x) + g)");
+        assert_eq!(substituted("[x, x^2][0]", "x", "3"), "[3, (3 ^ 2)][0]");
+    }
+
+    #[test]
+    fn bound_occurrences_are_left_alone() {
+        assert_eq!(
+            substituted("x + map(xs, x => x * 2)", "x", "5"),
+            "(5 + map(xs, (x => (x * 2))))"
+        );
+        assert_eq!(
+            substituted("sum(i, i, 10, i * k)", "i", "1"),
+            "(Σ[i = 1..10] (i * k))"
+        );
+    }
+
+    #[test]
+    fn binders_are_renamed_to_avoid_capture() {
+        assert_eq!(substituted("x => x + y", "y", "x"), "(x1 => (x1 + x))");
+        assert_eq!(
+            substituted("(x, x1) => x + x1 + y", "y", "x * 2"),
+            "((x2, x1) => ((x2 + x1) + (x * 2)))"
+        );
+        assert_eq!(
+            substituted("sum(k, 1, 3, k * rate)", "rate", "k / 100"),
+            "(Σ[k1 = 1..3] (k1 * (k / 100)))"
+        );
+        assert_eq!(substituted("x => x + z", "y", "x"), "(x => (x + z))");
+    }
+
+    #[test]
+    fn renamed_binders_rename_their_calls() {
+        assert_eq!(
+            substituted("f => f(2) + y", "y", "f"),
+            "(f1 => (f1(2) + f))"
+        );
+        assert_eq!(
+            substituted("sum(f, 1, 3, f(2) + y)", "y", "f"),
+            "(Σ[f1 = 1..3] (f1(2) + f))"
+        );
+        assert_eq!(
+            substituted("f => (f1 => f(f1)) + y", "y", "f"),
+            "(f2 => ((f1 => f2(f1)) + f))"
+        );
+    }
+}
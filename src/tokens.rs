@@ -1,196 +1,1141 @@
 use std::{
     any::Any,
+    cmp::Ordering,
     fmt::{Debug, Display},
 };
 
-use crate::ast_parser::AstToken;
+pub trait Token: Any + Debug {
+    // fn as_str(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
+    fn to_token(self) -> Box<dyn Token>;
+}
+
+impl Token for Number {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Imaginary {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Identifier {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Cell {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Range {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Plus {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Minus {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Multiply {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Divide {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Power {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for LeftParen {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for RightParen {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Less {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for LessEqual {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Greater {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for GreaterEqual {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Equal {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for NotEqual {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for And {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Or {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Not {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Question {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Colon {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Percent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for IntDivide {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for BitAnd {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for BitOr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Xor {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for BitNot {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for ShiftLeft {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for ShiftRight {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Comma {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for PlusMinus {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Semicolon {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Newline {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Assign {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Arrow {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for LeftBracket {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for RightBracket {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+impl Token for Backslash {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_token(self) -> Box<dyn Token> {
+        Box::new(self)
+    }
+}
+
+/// A numeric literal, kept as the exact decimal text that was written so
+/// number types other than `f64` can parse it without losing digits.
+///
+/// Two literals are equal when their text is, so `1` and `1.0` differ: an
+/// exact number type can tell them apart. Literals order by their exact
+/// value, compared digit by digit rather than through `f64`, ties broken by
+/// text. The only literals that are not plain digits come from
+/// `From<f64>`: every `NaN` is equal to every other and orders after all
+/// numbers, and `-0` differs from `0` and orders just before it, as
+/// [`f64::total_cmp`] does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Number {
+    text: String,
+}
+
+impl Number {
+    /// Accepts `digits` or `digits.digits`, the forms the lexer produces.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (integer, fraction) = match text.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (text, None),
+        };
+        let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !is_digits(integer) || !fraction.is_none_or(is_digits) {
+            return None;
+        }
+        Some(Number {
+            text: text.to_string(),
+        })
+    }
+
+    pub fn value(&self) -> f64 {
+        self.text.parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the literal was written without a decimal point.
+    pub fn is_integer(&self) -> bool {
+        !self.text.contains('.')
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Number {
+            text: value.to_string(),
+        }
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = (self.text.as_str(), other.text.as_str());
+        let by_value = sign(a).cmp(&sign(b)).then_with(|| match sign(a) {
+            Sign::Negative => magnitude(b).cmp(&magnitude(a)),
+            Sign::Positive => magnitude(a).cmp(&magnitude(b)),
+            _ => Ordering::Equal,
+        });
+        by_value.then_with(|| a.cmp(b))
+    }
+}
+
+/// Where a literal's text falls on the number line, in order.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Sign {
+    NegativeInfinity,
+    Negative,
+    Zero,
+    Positive,
+    Infinity,
+    NaN,
+}
+
+fn sign(text: &str) -> Sign {
+    match text {
+        "-inf" => Sign::NegativeInfinity,
+        "inf" => Sign::Infinity,
+        "NaN" => Sign::NaN,
+        _ if magnitude(text) == (0, "", "") => Sign::Zero,
+        _ if text.starts_with('-') => Sign::Negative,
+        _ => Sign::Positive,
+    }
+}
+
+/// The number of integer digits, the integer digits and the fraction digits
+/// of a finite literal, without leading or trailing zeros, which order as
+/// the literal's absolute value does.
+fn magnitude(text: &str) -> (usize, &str, &str) {
+    let text = text.trim_start_matches('-');
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let integer = integer.trim_start_matches('0');
+    (integer.len(), integer, fraction.trim_end_matches('0'))
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// A numeric literal with an `i` suffix, such as `4i` or `0.5i`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Imaginary(pub Number);
+impl Display for Imaginary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}i", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Identifier(pub String);
+impl Display for Identifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A spreadsheet cell reference such as `B2`, where a `$` before the column
+/// letters or the row number marks that part as absolute, as in `$B$2`.
+/// Columns and rows count from zero. References are ordered row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cell {
+    pub row: u32,
+    pub column: u32,
+    pub absolute_row: bool,
+    pub absolute_column: bool,
+}
+
+impl Cell {
+    /// The cell in the zero-based `column` and `row`, with relative parts.
+    pub const fn new(column: u32, row: u32) -> Self {
+        Cell {
+            row,
+            column,
+            absolute_row: false,
+            absolute_column: false,
+        }
+    }
+
+    /// Accepts one to three column letters followed by a row number from 1,
+    /// each optionally preceded by `$`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (absolute_column, text) = match text.strip_prefix('$') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let letters = text.bytes().take_while(u8::is_ascii_uppercase).count();
+        let (letters, text) = text.split_at(letters);
+        let (absolute_row, digits) = match text.strip_prefix('$') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if !(1..=3).contains(&letters.len())
+            || digits.is_empty()
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+            || digits.starts_with('0')
+        {
+            return None;
+        }
+
+        // Bijective base 26: A is 1, Z is 26 and AA is 27.
+        let column = letters.bytes().fold(0, |column, letter| {
+            column * 26 + u32::from(letter - b'A') + 1
+        });
+        let row: u32 = digits.parse().ok()?;
+        Some(Cell {
+            row: row - 1,
+            column: column - 1,
+            absolute_row,
+            absolute_column,
+        })
+    }
+
+    /// The same cell with both parts relative, which is how a workbook
+    /// identifies it.
+    pub const fn position(self) -> Self {
+        Cell::new(self.column, self.row)
+    }
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut letters = Vec::new();
+        let mut column = self.column + 1;
+        while column > 0 {
+            letters.push(char::from(b'A' + ((column - 1) % 26) as u8));
+            column = (column - 1) / 26;
+        }
+        if self.absolute_column {
+            write!(f, "$")?;
+        }
+        write!(f, "{}", letters.iter().rev().collect::<String>())?;
+        if self.absolute_row {
+            write!(f, "$")?;
+        }
+        write!(f, "{}", self.row + 1)
+    }
+}
+
+/// A rectangle of cells between two corners, such as `A1:C3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Range(pub Cell, pub Cell);
+
+impl Range {
+    /// The top left and bottom right cells, whichever corners were given.
+    pub fn corners(&self) -> (Cell, Cell) {
+        let Range(a, b) = *self;
+        (
+            Cell::new(a.column.min(b.column), a.row.min(b.row)),
+            Cell::new(a.column.max(b.column), a.row.max(b.row)),
+        )
+    }
+
+    /// The cells of the rectangle row by row, whichever corners were given.
+    pub fn cells(&self) -> impl Iterator<Item = Cell> {
+        let (first, last) = self.corners();
+        (first.row..=last.row).flat_map(move |row| {
+            (first.column..=last.column).map(move |column| Cell::new(column, row))
+        })
+    }
+
+    /// How many cells the rectangle has.
+    pub fn area(&self) -> u64 {
+        let (first, last) = self.corners();
+        u64::from(last.row - first.row + 1) * u64::from(last.column - first.column + 1)
+    }
+
+    /// Whether the position of `cell` lies inside the rectangle.
+    pub fn contains(&self, cell: Cell) -> bool {
+        let (first, last) = self.corners();
+        (first.row..=last.row).contains(&cell.row)
+            && (first.column..=last.column).contains(&cell.column)
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.0, self.1)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Plus;
+
+impl Plus {
+    pub const fn op_name() -> &'static str {
+        "plus"
+    }
+    pub const fn as_str() -> &'static str {
+        "+"
+    }
+}
+impl Display for Plus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Minus;
+
+impl Minus {
+    pub const fn op_name() -> &'static str {
+        "minus"
+    }
+    pub const fn as_str() -> &'static str {
+        "-"
+    }
+}
+impl Display for Minus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Multiply;
+
+impl Multiply {
+    pub const fn op_name() -> &'static str {
+        "multiply"
+    }
+    pub const fn as_str() -> &'static str {
+        "*"
+    }
+}
+impl Display for Multiply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Divide;
+
+impl Divide {
+    pub const fn op_name() -> &'static str {
+        "divide"
+    }
+    pub const fn as_str() -> &'static str {
+        "/"
+    }
+}
+impl Display for Divide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Power;
+
+impl Power {
+    pub const fn op_name() -> &'static str {
+        "power"
+    }
+    pub const fn as_str() -> &'static str {
+        "^"
+    }
+}
+impl Display for Power {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LeftParen;
+impl LeftParen {
+    pub const fn as_str() -> &'static str {
+        "("
+    }
+}
+impl Display for LeftParen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RightParen;
+impl RightParen {
+    pub const fn as_str() -> &'static str {
+        ")"
+    }
+}
+impl Display for RightParen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Less;
+
+impl Less {
+    pub const fn op_name() -> &'static str {
+        "less"
+    }
+    pub const fn as_str() -> &'static str {
+        "<"
+    }
+}
+impl Display for Less {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LessEqual;
+
+impl LessEqual {
+    pub const fn op_name() -> &'static str {
+        "less_equal"
+    }
+    pub const fn as_str() -> &'static str {
+        "<="
+    }
+}
+impl Display for LessEqual {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
 
-pub(crate) trait Token: Any + Debug {
-    // fn as_str(&self) -> &'static str;
-    fn as_any(&self) -> &dyn Any;
-    fn to_token(self) -> Box<dyn Token>;
+#[derive(Debug, PartialEq, Eq)]
+pub struct Greater;
+
+impl Greater {
+    pub const fn op_name() -> &'static str {
+        "greater"
+    }
+    pub const fn as_str() -> &'static str {
+        ">"
+    }
+}
+impl Display for Greater {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
 }
 
-impl Token for Number {
-    fn as_any(&self) -> &dyn Any {
-        self
+#[derive(Debug, PartialEq, Eq)]
+pub struct GreaterEqual;
+
+impl GreaterEqual {
+    pub const fn op_name() -> &'static str {
+        "greater_equal"
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+    pub const fn as_str() -> &'static str {
+        ">="
     }
 }
-impl Token for Plus {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for GreaterEqual {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Equal;
+
+impl Equal {
+    pub const fn op_name() -> &'static str {
+        "equal"
+    }
+    pub const fn as_str() -> &'static str {
+        "=="
     }
 }
-impl Token for Minus {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for Equal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct NotEqual;
+
+impl NotEqual {
+    pub const fn op_name() -> &'static str {
+        "not_equal"
+    }
+    pub const fn as_str() -> &'static str {
+        "!="
     }
 }
-impl Token for Multiply {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for NotEqual {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct And;
+
+impl And {
+    pub const fn op_name() -> &'static str {
+        "and"
+    }
+    pub const fn as_str() -> &'static str {
+        "&&"
     }
 }
-impl Token for Divide {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for And {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Or;
+
+impl Or {
+    pub const fn op_name() -> &'static str {
+        "or"
+    }
+    pub const fn as_str() -> &'static str {
+        "||"
     }
 }
-impl Token for Power {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for Or {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Not;
+
+impl Not {
+    pub const fn op_name() -> &'static str {
+        "not"
+    }
+    pub const fn as_str() -> &'static str {
+        "!"
     }
 }
-impl Token for LeftParen {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for Not {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Question;
+impl Question {
+    pub const fn as_str() -> &'static str {
+        "?"
     }
 }
-impl Token for RightParen {
-    fn as_any(&self) -> &dyn Any {
-        self
+impl Display for Question {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
     }
-    fn to_token(self) -> Box<dyn Token> {
-        Box::new(self)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Colon;
+impl Colon {
+    pub const fn as_str() -> &'static str {
+        ":"
+    }
+}
+impl Display for Colon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) struct Number(pub f64);
-impl Display for Number {
+#[derive(Debug, PartialEq, Eq)]
+pub struct Percent;
+
+impl Percent {
+    pub const fn op_name() -> &'static str {
+        "percent"
+    }
+    pub const fn as_str() -> &'static str {
+        "%"
+    }
+}
+impl Display for Percent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", Self::op_name())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Plus;
+pub struct IntDivide;
 
-impl Plus {
+impl IntDivide {
     pub const fn op_name() -> &'static str {
-        "plus"
+        "int_divide"
     }
     pub const fn as_str() -> &'static str {
-        "+"
+        "//"
     }
 }
-impl Display for Plus {
+impl Display for IntDivide {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::op_name())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Minus;
+pub struct BitAnd;
 
-impl Minus {
+impl BitAnd {
     pub const fn op_name() -> &'static str {
-        "minus"
+        "bit_and"
     }
     pub const fn as_str() -> &'static str {
-        "-"
+        "&"
     }
 }
-impl Display for Minus {
+impl Display for BitAnd {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::op_name())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Multiply;
+pub struct BitOr;
 
-impl Multiply {
+impl BitOr {
     pub const fn op_name() -> &'static str {
-        "multiply"
+        "bit_or"
     }
     pub const fn as_str() -> &'static str {
-        "*"
+        "|"
     }
 }
-impl Display for Multiply {
+impl Display for BitOr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::op_name())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Divide;
+pub struct Xor;
 
-impl Divide {
+impl Xor {
     pub const fn op_name() -> &'static str {
-        "divide"
+        "xor"
     }
     pub const fn as_str() -> &'static str {
-        "/"
+        "xor"
     }
 }
-impl Display for Divide {
+impl Display for Xor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::op_name())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Power;
+pub struct BitNot;
 
-impl Power {
+impl BitNot {
     pub const fn op_name() -> &'static str {
-        "power"
+        "bit_not"
     }
     pub const fn as_str() -> &'static str {
-        "^"
+        "~"
     }
 }
-impl Display for Power {
+impl Display for BitNot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::op_name())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct LeftParen;
-impl LeftParen {
+pub struct ShiftLeft;
+
+impl ShiftLeft {
+    pub const fn op_name() -> &'static str {
+        "shift_left"
+    }
     pub const fn as_str() -> &'static str {
-        "("
+        "<<"
     }
 }
-impl Display for LeftParen {
+impl Display for ShiftLeft {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ShiftRight;
+
+impl ShiftRight {
+    pub const fn op_name() -> &'static str {
+        "shift_right"
+    }
+    pub const fn as_str() -> &'static str {
+        ">>"
+    }
+}
+impl Display for ShiftRight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Comma;
+impl Comma {
+    pub const fn as_str() -> &'static str {
+        ","
+    }
+}
+impl Display for Comma {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::as_str())
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(crate) struct RightParen;
-impl RightParen {
+pub struct PlusMinus;
+
+impl PlusMinus {
+    pub const fn op_name() -> &'static str {
+        "plus_minus"
+    }
     pub const fn as_str() -> &'static str {
-        ")"
+        "±"
     }
 }
-impl Display for RightParen {
+impl Display for PlusMinus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Semicolon;
+impl Semicolon {
+    pub const fn as_str() -> &'static str {
+        ";"
+    }
+}
+impl Display for Semicolon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Newline;
+impl Newline {
+    pub const fn as_str() -> &'static str {
+        "\n"
+    }
+}
+impl Display for Newline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Assign;
+
+impl Assign {
+    pub const fn op_name() -> &'static str {
+        "assign"
+    }
+    pub const fn as_str() -> &'static str {
+        "="
+    }
+}
+impl Display for Assign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Arrow;
+
+impl Arrow {
+    pub const fn op_name() -> &'static str {
+        "arrow"
+    }
+    pub const fn as_str() -> &'static str {
+        "=>"
+    }
+}
+impl Display for Arrow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LeftBracket;
+impl LeftBracket {
+    pub const fn as_str() -> &'static str {
+        "["
+    }
+}
+impl Display for LeftBracket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RightBracket;
+impl RightBracket {
+    pub const fn as_str() -> &'static str {
+        "]"
+    }
+}
+impl Display for RightBracket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Self::as_str())
     }
 }
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Backslash;
+
+impl Backslash {
+    pub const fn op_name() -> &'static str {
+        "solve"
+    }
+    pub const fn as_str() -> &'static str {
+        "\\"
+    }
+}
+impl Display for Backslash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::op_name())
+    }
+}
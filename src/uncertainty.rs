@@ -0,0 +1,269 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    evaluator::{EvalError, EvalResult},
+    parser::ASTNode,
+};
+
+/// Identifies an independent source of uncertainty.
+static NEXT_SOURCE: AtomicUsize = AtomicUsize::new(0);
+
+/// A value with a standard uncertainty.
+///
+/// The uncertainty is kept as one component per independent source, the
+/// first-order sensitivity to that source times its standard uncertainty, so
+/// values that share a source stay correlated: `x - x` is exactly `0` and
+/// `x * x` has the uncertainty of `x^2` rather than of two independent
+/// measurements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uncertain {
+    value: f64,
+    components: BTreeMap<usize, f64>,
+}
+
+impl Uncertain {
+    /// A measurement with its own independent source of uncertainty.
+    pub fn new(value: f64, uncertainty: f64) -> Self {
+        let mut components = BTreeMap::new();
+        if uncertainty != 0.0 {
+            let source = NEXT_SOURCE.fetch_add(1, Ordering::Relaxed);
+            components.insert(source, uncertainty);
+        }
+        Uncertain { value, components }
+    }
+
+    pub fn exact(value: f64) -> Self {
+        Uncertain {
+            value,
+            components: BTreeMap::new(),
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The combined standard uncertainty, the root sum of squares of the
+    /// components.
+    pub fn uncertainty(&self) -> f64 {
+        self.components.values().map(|c| c * c).sum::<f64>().sqrt()
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// `f(self, other) = value` with partial derivatives `d_self` and
+    /// `d_other`.
+    fn propagate(&self, other: &Uncertain, value: f64, d_self: f64, d_other: f64) -> Uncertain {
+        let mut components = BTreeMap::new();
+        for (source, c) in &self.components {
+            *components.entry(*source).or_insert(0.0) += d_self * c;
+        }
+        for (source, c) in &other.components {
+            *components.entry(*source).or_insert(0.0) += d_other * c;
+        }
+        Uncertain { value, components }
+    }
+
+    fn scale(&self, value: f64, derivative: f64) -> Uncertain {
+        self.propagate(&Uncertain::exact(0.0), value, derivative, 0.0)
+    }
+}
+
+impl Display for Uncertain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ± {}", self.value, self.uncertainty())
+    }
+}
+
+/// Evaluates an [`ASTNode`] over [`Uncertain`] values using first-order
+/// (linear) error propagation.
+///
+/// Each `±` in the expression and each variable set with
+/// [`Uncertain::new`] is an independent source; reusing a variable reuses its
+/// source. Only arithmetic is supported. The propagation is exact for sums
+/// and differences and a linear approximation otherwise, so it underestimates
+/// the spread when an uncertainty is large relative to its value.
+#[derive(Debug, Default)]
+pub struct UncertaintyEvaluator {
+    variables: HashMap<String, Uncertain>,
+}
+
+impl UncertaintyEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: Uncertain) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn evaluate(&self, node: &ASTNode) -> EvalResult<Uncertain> {
+        match node {
+            ASTNode::Located(_, node) => self.evaluate(node),
+            ASTNode::Number(n) => Ok(Uncertain::exact(n.value())),
+            ASTNode::Variable(name) => self.variable(name),
+            ASTNode::PlusMinus(l, r) => {
+                let value = self.evaluate(l)?;
+                let uncertainty = self.evaluate(r)?;
+                if !uncertainty.is_exact() {
+                    return Err(EvalError::new(format!(
+                        "Uncertainty must be exact: {}",
+                        uncertainty
+                    )));
+                }
+                if uncertainty.value < 0.0 {
+                    return Err(EvalError::new(format!(
+                        "Uncertainty must not be negative: {}",
+                        uncertainty.value
+                    )));
+                }
+                let measurement = Uncertain::new(0.0, uncertainty.value);
+                Ok(value.propagate(&measurement, value.value, 1.0, 1.0))
+            }
+            ASTNode::Add(l, r) => {
+                let (a, b) = (self.evaluate(l)?, self.evaluate(r)?);
+                Ok(a.propagate(&b, a.value + b.value, 1.0, 1.0))
+            }
+            ASTNode::Subtract(l, r) => {
+                let (a, b) = (self.evaluate(l)?, self.evaluate(r)?);
+                Ok(a.propagate(&b, a.value - b.value, 1.0, -1.0))
+            }
+            ASTNode::Multiply(l, r) => {
+                let (a, b) = (self.evaluate(l)?, self.evaluate(r)?);
+                Ok(a.propagate(&b, a.value * b.value, b.value, a.value))
+            }
+            ASTNode::Divide(l, r) => {
+                let (a, b) = (self.evaluate(l)?, self.evaluate(r)?);
+                if b.value == 0.0 {
+                    return Err(EvalError::new("Division by zero"));
+                }
+                Ok(a.propagate(
+                    &b,
+                    a.value / b.value,
+                    1.0 / b.value,
+                    -a.value / (b.value * b.value),
+                ))
+            }
+            ASTNode::Power(l, r) => {
+                let (a, b) = (self.evaluate(l)?, self.evaluate(r)?);
+                let value = a.value.powf(b.value);
+                let d_base = if b.value == 0.0 {
+                    0.0
+                } else {
+                    b.value * a.value.powf(b.value - 1.0)
+                };
+                // Only needed when the exponent is uncertain; for a negative
+                // base the logarithm is NaN.
+                let d_exponent = if b.is_exact() {
+                    0.0
+                } else {
+                    value * a.value.ln()
+                };
+                Ok(a.propagate(&b, value, d_base, d_exponent))
+            }
+            ASTNode::Negate(n) => {
+                let a = self.evaluate(n)?;
+                Ok(a.scale(-a.value, -1.0))
+            }
+            ASTNode::Percent(n) => {
+                let a = self.evaluate(n)?;
+                Ok(a.scale(a.value / 100.0, 0.01))
+            }
+            node => Err(EvalError::new(format!(
+                "Not supported in uncertainty mode: {}",
+                node
+            ))),
+        }
+    }
+
+    fn variable(&self, name: &str) -> EvalResult<Uncertain> {
+        if let Some(value) = self.variables.get(name) {
+            return Ok(value.clone());
+        }
+        match name {
+            "pi" => Ok(Uncertain::exact(std::f64::consts::PI)),
+            "e" => Ok(Uncertain::exact(std::f64::consts::E)),
+            _ => Err(EvalError::new(format!("Unknown variable: {}", name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn eval_with(evaluator: &UncertaintyEvaluator, input: &str) -> AppResult<Uncertain> {
+        let (tokens, _) = Lexer::new(input).tokenize()?;
+        let ast = Parser::new(tokens).parse()?;
+        Ok(evaluator.evaluate(&ast)?)
+    }
+
+    fn eval(input: &str) -> AppResult<Uncertain> {
+        eval_with(&UncertaintyEvaluator::new(), input)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn parses_both_spellings() {
+        let a = eval("9.81 ± 0.02").unwrap();
+        let b = eval("9.81 +/- 0.02").unwrap();
+        assert_eq!(a.value(), 9.81);
+        assert_eq!(a.uncertainty(), b.uncertainty());
+        assert_eq!(a.to_string(), "9.81 ± 0.02");
+    }
+
+    #[test]
+    fn independent_sources_add_in_quadrature() {
+        assert_close(eval("(1 ± 3) + (2 ± 4)").unwrap().uncertainty(), 5.0);
+        assert_close(eval("(1 ± 3) - (2 ± 4)").unwrap().uncertainty(), 5.0);
+        assert_close(eval("2 * 10 ± 1").unwrap().uncertainty(), 1.0);
+        assert_close(eval("2 * (10 ± 1)").unwrap().uncertainty(), 2.0);
+
+        // Relative uncertainties of a product or quotient add in quadrature.
+        let product = eval("(10 ± 0.3) * (20 ± 0.8)").unwrap();
+        assert_close(product.uncertainty(), 200.0 * 0.05);
+        let quotient = eval("(10 ± 0.3) / (20 ± 0.8)").unwrap();
+        assert_close(quotient.uncertainty(), 0.5 * 0.05);
+        assert_close(eval("(3 ± 0.1)^2").unwrap().uncertainty(), 0.6);
+    }
+
+    #[test]
+    fn reused_variables_are_correlated() {
+        let mut evaluator = UncertaintyEvaluator::new();
+        evaluator.set_variable("x", Uncertain::new(3.0, 0.1));
+        evaluator.set_variable("y", Uncertain::new(3.0, 0.1));
+
+        let difference = eval_with(&evaluator, "x - x").unwrap();
+        assert_eq!(difference.value(), 0.0);
+        assert_eq!(difference.uncertainty(), 0.0);
+        assert_close(eval_with(&evaluator, "x * x").unwrap().uncertainty(), 0.6);
+        assert_close(eval_with(&evaluator, "x / x").unwrap().uncertainty(), 0.0);
+        assert_close(
+            eval_with(&evaluator, "x - y").unwrap().uncertainty(),
+            0.1 * 2f64.sqrt(),
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_uncertainties() {
+        assert!(eval("1 ± -1").is_err());
+        assert!(eval("1 ± (2 ± 1)").is_err());
+        assert!(eval("(1 ± 1) / 0").is_err());
+        assert!(eval("1 < 2").is_err());
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    ops::{Div, Mul},
+};
+
+use crate::{
+    evaluator::{arity_mismatch, unknown_function, EvalError, EvalResult},
+    location::Location,
+    numeric::unary_function,
+    parser::{ASTNode, UnitExpr},
+};
+
+/// Symbols of the SI base units, in the order [`Dimension`] stores their
+/// exponents and prints them.
+const BASE_UNITS: [&str; 7] = ["kg", "m", "s", "A", "K", "mol", "cd"];
+
+/// Exponents of the seven SI base dimensions: mass, length, time, electric
+/// current, temperature, amount of substance and luminous intensity.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dimension([i32; 7]);
+
+impl Dimension {
+    pub const fn new(exponents: [i32; 7]) -> Self {
+        Dimension(exponents)
+    }
+
+    pub fn exponents(&self) -> [i32; 7] {
+        self.0
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.0 == [0; 7]
+    }
+
+    pub fn pow(self, exponent: i32) -> Dimension {
+        Dimension(self.0.map(|e| e * exponent))
+    }
+
+    /// Returns `None` unless every exponent is a multiple of `n`.
+    pub fn root(self, n: i32) -> Option<Dimension> {
+        self.0
+            .iter()
+            .all(|e| e % n == 0)
+            .then(|| Dimension(self.0.map(|e| e / n)))
+    }
+}
+
+impl Mul for Dimension {
+    type Output = Dimension;
+
+    fn mul(self, other: Dimension) -> Dimension {
+        Dimension(std::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+}
+
+impl Div for Dimension {
+    type Output = Dimension;
+
+    fn div(self, other: Dimension) -> Dimension {
+        Dimension(std::array::from_fn(|i| self.0[i] - other.0[i]))
+    }
+}
+
+/// Prints the dimension in SI base units, such as `kg*m/s^2`, or `1` when
+/// dimensionless.
+impl Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_dimensionless() {
+            return write!(f, "1");
+        }
+        let numerator = BASE_UNITS.iter().zip(self.0).filter(|(_, e)| *e > 0);
+        let denominator = BASE_UNITS.iter().zip(self.0).filter(|(_, e)| *e < 0);
+        let factors = UnitExpr {
+            factors: numerator
+                .chain(denominator)
+                .map(|(name, e)| (name.to_string(), e))
+                .collect(),
+        };
+        write!(f, "{}", factors)
+    }
+}
+
+/// A unit as a multiple of the coherent SI unit of its dimension, so a
+/// kilometre is `1000` metres and an hour `3600` seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub scale: f64,
+    pub dimension: Dimension,
+}
+
+const MASS: [i32; 7] = [1, 0, 0, 0, 0, 0, 0];
+const LENGTH: [i32; 7] = [0, 1, 0, 0, 0, 0, 0];
+const TIME: [i32; 7] = [0, 0, 1, 0, 0, 0, 0];
+
+/// Name, scale, dimension and whether SI prefixes apply.
+const UNITS: &[(&str, f64, [i32; 7], bool)] = &[
+    ("m", 1.0, LENGTH, true),
+    ("g", 1e-3, MASS, true),
+    ("s", 1.0, TIME, true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [1, -1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [1, 2, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [1, 2, -3, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [1, 2, -3, -1, 0, 0, 0], true),
+    ("ohm", 1.0, [1, 2, -3, -2, 0, 0, 0], true),
+    ("L", 1e-3, [0, 3, 0, 0, 0, 0, 0], true),
+    ("min", 60.0, TIME, false),
+    ("h", 3600.0, TIME, false),
+    ("day", 86400.0, TIME, false),
+    ("in", 0.0254, LENGTH, false),
+    ("ft", 0.3048, LENGTH, false),
+    ("mi", 1609.344, LENGTH, false),
+    ("lb", 0.45359237, MASS, false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("da", 1e1),
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+    ("y", 1e-24),
+];
+
+impl Unit {
+    /// Looks up a unit symbol, with an optional SI prefix: `m`, `km`, `ms`,
+    /// `kg`. Whole symbols win over prefixed readings, so `h` is an hour and
+    /// `cd` a candela.
+    pub fn lookup(name: &str) -> Option<Unit> {
+        let unit = |&(_, scale, dimension, _): &(&str, f64, [i32; 7], bool)| Unit {
+            scale,
+            dimension: Dimension(dimension),
+        };
+        if let Some(entry) = UNITS.iter().find(|entry| entry.0 == name) {
+            return Some(unit(entry));
+        }
+
+        PREFIXES.iter().find_map(|(prefix, factor)| {
+            let rest = name.strip_prefix(prefix)?;
+            let entry = UNITS.iter().find(|entry| entry.0 == rest && entry.3)?;
+            let base = unit(entry);
+            Some(Unit {
+                scale: base.scale * factor,
+                dimension: base.dimension,
+            })
+        })
+    }
+
+    /// Resolves every name in `expr` and combines them.
+    pub fn resolve(expr: &UnitExpr) -> EvalResult<Unit> {
+        expr.factors.iter().try_fold(
+            Unit {
+                scale: 1.0,
+                dimension: Dimension::default(),
+            },
+            |acc, (name, exponent)| {
+                let unit = Unit::lookup(name)
+                    .ok_or_else(|| EvalError::new(format!("Unknown unit: {}", name)))?;
+                Ok(Unit {
+                    scale: acc.scale * unit.scale.powi(*exponent),
+                    dimension: acc.dimension * unit.dimension.pow(*exponent),
+                })
+            },
+        )
+    }
+}
+
+/// A value with a dimension, stored in coherent SI units.
+///
+/// The result of a `to` conversion also remembers the unit it was converted
+/// to and prints in it; any further arithmetic goes back to SI units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    value: f64,
+    dimension: Dimension,
+    unit: Option<(UnitExpr, f64)>,
+}
+
+impl Quantity {
+    pub fn new(value: f64, dimension: Dimension) -> Self {
+        Quantity {
+            value,
+            dimension,
+            unit: None,
+        }
+    }
+
+    pub fn dimensionless(value: f64) -> Self {
+        Self::new(value, Dimension::default())
+    }
+
+    /// The value in coherent SI units.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    /// The value in the unit it prints in.
+    pub fn magnitude(&self) -> f64 {
+        match &self.unit {
+            Some((_, scale)) => self.value / scale,
+            None => self.value,
+        }
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.unit {
+            Some((unit, _)) => write!(f, "{} {}", self.magnitude(), unit),
+            None if self.dimension.is_dimensionless() => write!(f, "{}", self.value),
+            None => write!(f, "{} {}", self.value, self.dimension),
+        }
+    }
+}
+
+/// Evaluates an [`ASTNode`] parsed with [`Parser::units`] over
+/// [`Quantity`]s.
+///
+/// `+` and `-` need operands of the same dimension, `*` and `/` combine
+/// dimensions, and a quantity with units may only be raised to an integer
+/// power. `sqrt` halves dimensions, `abs` keeps them and every other function
+/// needs a dimensionless argument. Only multiplicative units are known, so
+/// there are no degrees Celsius. When the tree carries
+/// [`ASTNode::Located`] nodes, dimension errors point at the operator.
+///
+/// [`Parser::units`]: crate::parser::Parser::units
+#[derive(Debug, Default)]
+pub struct UnitEvaluator {
+    variables: HashMap<String, Quantity>,
+}
+
+impl UnitEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: Quantity) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn evaluate(&self, node: &ASTNode) -> EvalResult<Quantity> {
+        self.evaluate_at(node, None)
+    }
+
+    /// `location` is where the operator of `node` appears, if known.
+    fn evaluate_at(&self, node: &ASTNode, location: Option<Location>) -> EvalResult<Quantity> {
+        let locate = |error: EvalError| match location {
+            Some(location) => error.at(location),
+            None => error,
+        };
+        match node {
+            ASTNode::Located(location, node) => self.evaluate_at(node, Some(*location)),
+            ASTNode::Number(n) => Ok(Quantity::dimensionless(n.value())),
+            ASTNode::Quantity(n, unit) => {
+                let unit = Unit::resolve(unit).map_err(locate)?;
+                Ok(Quantity::new(n.value() * unit.scale, unit.dimension))
+            }
+            ASTNode::Variable(name) => self.variable(name).map_err(locate),
+            ASTNode::Add(l, r) => {
+                let (left, right) = self.same_dimension(l, r, "+").map_err(locate)?;
+                Ok(Quantity::new(left.value + right.value, left.dimension))
+            }
+            ASTNode::Subtract(l, r) => {
+                let (left, right) = self.same_dimension(l, r, "-").map_err(locate)?;
+                Ok(Quantity::new(left.value - right.value, left.dimension))
+            }
+            ASTNode::Multiply(l, r) => {
+                let left = self.evaluate(l)?;
+                let right = self.evaluate(r)?;
+                Ok(Quantity::new(
+                    left.value * right.value,
+                    left.dimension * right.dimension,
+                ))
+            }
+            ASTNode::Divide(l, r) => {
+                let left = self.evaluate(l)?;
+                let right = self.evaluate(r)?;
+                if right.value == 0.0 {
+                    return Err(locate(EvalError::new("Division by zero")));
+                }
+                Ok(Quantity::new(
+                    left.value / right.value,
+                    left.dimension / right.dimension,
+                ))
+            }
+            ASTNode::Power(l, r) => {
+                let base = self.evaluate(l)?;
+                let exponent = self.evaluate(r)?;
+                self.power(base, exponent).map_err(locate)
+            }
+            ASTNode::Negate(n) => {
+                let value = self.evaluate(n)?;
+                Ok(Quantity::new(-value.value, value.dimension))
+            }
+            ASTNode::Percent(n) => {
+                let value = self.evaluate(n)?;
+                Ok(Quantity::new(value.value / 100.0, value.dimension))
+            }
+            ASTNode::Call(name, args) => self.call(name, args).map_err(locate),
+            ASTNode::Convert(n, unit) => {
+                let value = self.evaluate(n)?;
+                let target = Unit::resolve(unit).map_err(locate)?;
+                if value.dimension != target.dimension {
+                    return Err(locate(EvalError::new(format!(
+                        "Cannot convert {} to {}",
+                        value.dimension, unit
+                    ))));
+                }
+                Ok(Quantity {
+                    unit: Some((unit.clone(), target.scale)),
+                    ..value
+                })
+            }
+            node => Err(EvalError::new(format!(
+                "Not supported in units mode: {}",
+                node
+            ))),
+        }
+    }
+
+    fn variable(&self, name: &str) -> EvalResult<Quantity> {
+        if let Some(value) = self.variables.get(name) {
+            return Ok(value.clone());
+        }
+        match name {
+            "pi" => Ok(Quantity::dimensionless(std::f64::consts::PI)),
+            "e" => Ok(Quantity::dimensionless(std::f64::consts::E)),
+            _ => Err(EvalError::new(format!("Unknown variable: {}", name))),
+        }
+    }
+
+    fn same_dimension(
+        &self,
+        left: &ASTNode,
+        right: &ASTNode,
+        operator: &str,
+    ) -> EvalResult<(Quantity, Quantity)> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        if left.dimension != right.dimension {
+            return Err(EvalError::new(format!(
+                "Dimension mismatch: {} {} {}",
+                left.dimension, operator, right.dimension
+            )));
+        }
+        Ok((left, right))
+    }
+
+    fn power(&self, base: Quantity, exponent: Quantity) -> EvalResult<Quantity> {
+        if !exponent.dimension.is_dimensionless() {
+            return Err(EvalError::new(format!(
+                "Exponent must be dimensionless, found {}",
+                exponent.dimension
+            )));
+        }
+        if base.dimension.is_dimensionless() {
+            return Ok(Quantity::dimensionless(base.value.powf(exponent.value)));
+        }
+        if exponent.value.fract() != 0.0 || exponent.value.abs() > i32::MAX as f64 {
+            return Err(EvalError::new(format!(
+                "Exponent of a quantity in {} must be an integer: {}",
+                base.dimension, exponent.value
+            )));
+        }
+        let exponent = exponent.value as i32;
+        Ok(Quantity::new(
+            base.value.powi(exponent),
+            base.dimension.pow(exponent),
+        ))
+    }
+
+    fn call(&self, name: &str, args: &[ASTNode]) -> EvalResult<Quantity> {
+        let function = unary_function(name).ok_or_else(|| unknown_function(name))?;
+        let [arg] = args else {
+            return Err(arity_mismatch(name, 1, args.len()));
+        };
+        let value = self.evaluate(arg)?;
+        let dimension = match name {
+            "sqrt" => value.dimension.root(2).ok_or_else(|| {
+                EvalError::new(format!(
+                    "Cannot take the square root of {}",
+                    value.dimension
+                ))
+            })?,
+            "abs" => value.dimension,
+            _ if value.dimension.is_dimensionless() => value.dimension,
+            _ => {
+                return Err(EvalError::new(format!(
+                    "Function {} needs a dimensionless argument, found {}",
+                    name, value.dimension
+                )))
+            }
+        };
+        Ok(Quantity::new(function(value.value), dimension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, result::AppResult};
+
+    fn parse(input: &str) -> AppResult<ASTNode> {
+        let (tokens, locations) = Lexer::new(input).tokenize()?;
+        Ok(Parser::new(tokens)
+            .units(true)
+            .locations(locations)
+            .parse()?)
+    }
+
+    fn eval(input: &str) -> AppResult<Quantity> {
+        Ok(UnitEvaluator::new().evaluate(&parse(input)?)?)
+    }
+
+    #[test]
+    fn parses_units_on_literals() {
+        assert_eq!(
+            parse("9.81 m/s^2 * 2 kg").unwrap().to_string(),
+            "((9.81 m/s^2) * (2 kg))"
+        );
+        assert_eq!(
+            parse("5 km/h to m/s").unwrap().to_string(),
+            "((5 km/h) to m/s)"
+        );
+        assert_eq!(parse("2 N m").unwrap().to_string(), "(2 N*m)");
+        assert_eq!(parse("3 m * (x)").unwrap().to_string(), "((3 m) * x)");
+        assert_eq!(parse("1 s^-1").unwrap().to_string(), "(1 s^-1)");
+        assert!(parse("1 m to 2").is_err());
+    }
+
+    #[test]
+    fn arithmetic_with_units() {
+        assert_eq!(eval("3 m + 20 cm").unwrap().to_string(), "3.2 m");
+        assert_eq!(
+            eval("9.81 m/s^2 * 2 kg").unwrap().to_string(),
+            "19.62 kg*m/s^2"
+        );
+        assert_eq!(eval("(3 m)^2 / 1 m").unwrap().to_string(), "9 m");
+        assert_eq!(eval("sqrt(16 m^2)").unwrap().to_string(), "4 m");
+        assert_eq!(eval("2 km / 4 m").unwrap().to_string(), "500");
+        assert!(eval("2 m ^ 0.5").is_err());
+        assert!(eval("3 furlong").is_err());
+    }
+
+    #[test]
+    fn conversion() {
+        let speed = eval("5 km/h to m/s").unwrap();
+        assert!((speed.magnitude() - 25.0 / 18.0).abs() < 1e-12);
+        assert_eq!(eval("90 min to h").unwrap().to_string(), "1.5 h");
+        assert_eq!(
+            eval("1 kN to kg*m/s^2").unwrap().to_string(),
+            "1000 kg*m/s^2"
+        );
+        assert_eq!(
+            eval("1 m to s").unwrap_err().to_string(),
+            "Error(Eval): Cannot convert m to s in line 1 at column 5"
+        );
+    }
+
+    #[test]
+    fn dimension_errors_point_at_the_operator() {
+        assert_eq!(
+            eval("3 m + 2 s").unwrap_err().to_string(),
+            "Error(Eval): Dimension mismatch: m + s in line 1 at column 5"
+        );
+        assert_eq!(
+            eval("1 m * 2 m - 3 m").unwrap_err().to_string(),
+            "Error(Eval): Dimension mismatch: m^2 - m in line 1 at column 11"
+        );
+        assert!(eval("sin(1 m)").is_err());
+    }
+}
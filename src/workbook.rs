@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    evaluator::{EvalError, EvalResult, Evaluator, Value},
+    graph,
+    lexer::Lexer,
+    parser::{ASTNode, Parser},
+    result::AppResult,
+    tokens::{Cell, Range},
+};
+
+/// A cell's formula, the single cells and the ranges it refers to, and its
+/// last computed value.
+#[derive(Debug)]
+struct Entry {
+    formula: ASTNode,
+    cells: BTreeSet<Cell>,
+    ranges: BTreeSet<Range>,
+    value: EvalResult<Value>,
+}
+
+/// A grid of cells holding formulas such as `A1 * 2`, `$B$1 + 1` or
+/// `sum(A1:A9)`.
+///
+/// The cell references in each formula make a dependency graph. A change
+/// recalculates the changed cell and the cells that depend on it, directly
+/// or through other cells, in topological order, so a cell is only computed
+/// after the cells it refers to. A formula that would make a cell
+/// depend on itself, directly or through other cells, is rejected with the
+/// cells of the cycle and leaves the workbook unchanged.
+///
+/// Empty cells count as `0` and are left out of ranges, so a range costs as
+/// much as the filled cells in its rows, however large it is. A formula that fails
+/// keeps the error as its value, and so do the formulas that refer to it.
+#[derive(Debug, Default)]
+pub struct Workbook {
+    cells: BTreeMap<Cell, Entry>,
+    evaluator: Evaluator,
+}
+
+impl Workbook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts `formula` in `cell`, replacing what was there, and recalculates.
+    pub fn set(&mut self, cell: &str, formula: &str) -> AppResult<()> {
+        let cell = parse_cell(cell)?;
+        let formula = parse_formula(formula)?;
+        let mut entry = Entry {
+            formula,
+            cells: BTreeSet::new(),
+            ranges: BTreeSet::new(),
+            value: Ok(Value::Number(0.0)),
+        };
+        references(&entry.formula, &mut entry.cells, &mut entry.ranges);
+
+        let previous = self.cells.insert(cell, entry);
+        let references = self.references(&self.cells[&cell]);
+        // The formula closes a cycle if it refers to a cell that depends on
+        // it; only then is the cycle itself worth looking for.
+        let affected = self.affected(cell);
+        let cycle = references
+            .iter()
+            .any(|reference| affected.contains(reference))
+            .then(|| {
+                graph::cycle_through(&cell, |cell| {
+                    self.cells
+                        .get(cell)
+                        .map(|entry| self.references(entry))
+                        .unwrap_or_default()
+                })
+            })
+            .flatten();
+        if let Some(cycle) = cycle {
+            match previous {
+                Some(previous) => self.cells.insert(cell, previous),
+                None => self.cells.remove(&cell),
+            };
+            let cycle: Vec<_> = cycle.iter().map(Cell::to_string).collect();
+            return Err(
+                EvalError::new(format!("Circular reference: {}", cycle.join(" -> "))).into(),
+            );
+        }
+
+        self.recalculate(&affected);
+        Ok(())
+    }
+
+    /// Empties `cell` and recalculates.
+    pub fn clear(&mut self, cell: &str) -> EvalResult<()> {
+        let cell = parse_cell(cell)?;
+        if self.cells.remove(&cell).is_some() {
+            self.evaluator.remove_variable(&cell.to_string());
+            let affected = self.affected(cell);
+            self.recalculate(&affected);
+        }
+        Ok(())
+    }
+
+    /// The value of `cell`, or `None` if it is empty.
+    pub fn value(&self, cell: &str) -> Option<&EvalResult<Value>> {
+        let cell = parse_cell(cell).ok()?;
+        self.cells.get(&cell).map(|entry| &entry.value)
+    }
+
+    pub fn formula(&self, cell: &str) -> Option<&ASTNode> {
+        let cell = parse_cell(cell).ok()?;
+        self.cells.get(&cell).map(|entry| &entry.formula)
+    }
+
+    /// The cells the formula in `cell` refers to, including the filled
+    /// cells of its ranges, row by row.
+    pub fn dependencies(&self, cell: &str) -> Vec<Cell> {
+        parse_cell(cell)
+            .ok()
+            .and_then(|cell| self.cells.get(&cell))
+            .map(|entry| self.references(entry))
+            .unwrap_or_default()
+    }
+
+    /// The cells whose formulas refer to `cell`, directly or through a
+    /// range.
+    pub fn dependents(&self, cell: &str) -> Vec<Cell> {
+        match parse_cell(cell) {
+            Ok(cell) => self.dependents_of(cell),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The filled cells, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.cells.keys().copied()
+    }
+
+    /// The single cells `entry` refers to, filled or not, and the filled
+    /// cells of its ranges, row by row.
+    fn references(&self, entry: &Entry) -> Vec<Cell> {
+        let mut cells = entry.cells.clone();
+        for range in &entry.ranges {
+            let (first, last) = range.corners();
+            let filled = self.cells.range(first..=last).map(|(cell, _)| *cell);
+            cells.extend(filled.filter(|cell| range.contains(*cell)));
+        }
+        cells.into_iter().collect()
+    }
+
+    fn dependents_of(&self, cell: Cell) -> Vec<Cell> {
+        self.cells
+            .iter()
+            .filter(|(_, entry)| {
+                entry.cells.contains(&cell) || entry.ranges.iter().any(|range| range.contains(cell))
+            })
+            .map(|(dependent, _)| *dependent)
+            .collect()
+    }
+
+    /// `changed`, unless it is empty, and the cells that depend on it,
+    /// directly or through other cells.
+    fn affected(&self, changed: Cell) -> BTreeSet<Cell> {
+        let mut affected = BTreeSet::new();
+        let mut pending = vec![changed];
+        while let Some(cell) = pending.pop() {
+            for dependent in self.dependents_of(cell) {
+                if affected.insert(dependent) {
+                    pending.push(dependent);
+                }
+            }
+        }
+        if self.cells.contains_key(&changed) {
+            affected.insert(changed);
+        }
+        affected
+    }
+
+    /// Recalculates the `affected` cells, each after the ones it refers to.
+    fn recalculate(&mut self, affected: &BTreeSet<Cell>) {
+        let order = graph::topological_order(affected.iter().copied(), |cell| {
+            let entry = self.cells.get(cell).filter(|_| affected.contains(cell))?;
+            Some(self.references(entry))
+        });
+        for cell in order {
+            let Some(entry) = self.cells.get(&cell) else {
+                continue;
+            };
+            let failed = self.references(entry).into_iter().find(|dependency| {
+                matches!(
+                    self.cells.get(dependency),
+                    Some(Entry { value: Err(_), .. })
+                )
+            });
+            let value = match failed {
+                Some(dependency) => Err(EvalError::new(format!(
+                    "Cell {} refers to {}, which has an error",
+                    cell, dependency
+                ))),
+                None => self.evaluator.evaluate(&entry.formula),
+            };
+
+            match &value {
+                Ok(value) => self.evaluator.set_variable(cell.to_string(), value.clone()),
+                Err(_) => {
+                    self.evaluator.remove_variable(&cell.to_string());
+                }
+            }
+            if let Some(entry) = self.cells.get_mut(&cell) {
+                entry.value = value;
+            }
+        }
+    }
+}
+
+fn parse_cell(name: &str) -> EvalResult<Cell> {
+    Cell::parse(name)
+        .map(Cell::position)
+        .ok_or_else(|| EvalError::new(format!("Invalid cell reference: {}", name)))
+}
+
+fn parse_formula(formula: &str) -> AppResult<ASTNode> {
+    if formula.trim().is_empty() {
+        return Err(EvalError::new("Empty formula").into());
+    }
+    let (tokens, locations) = Lexer::new(formula).cells(true).tokenize()?;
+    Ok(Parser::new(tokens).locations(locations).parse()?)
+}
+
+/// Adds the single cells and the ranges `node` refers to.
+fn references(node: &ASTNode, cells: &mut BTreeSet<Cell>, ranges: &mut BTreeSet<Range>) {
+    match node {
+        ASTNode::Cell(cell) => {
+            cells.insert(cell.position());
+        }
+        ASTNode::Range(Range(a, b)) => {
+            ranges.insert(Range(a.position(), b.position()));
+        }
+        node => {
+            for child in node.children() {
+                references(child, cells, ranges);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(workbook: &Workbook, cell: &str) -> f64 {
+        match workbook.value(cell) {
+            Some(Ok(Value::Number(n))) => *n,
+            value => panic!("{} is {:?}", cell, value),
+        }
+    }
+
+    #[test]
+    fn cell_references_and_ranges_are_lexed() {
+        let mut workbook = Workbook::new();
+        workbook.set("B2", "1").unwrap();
+        workbook
+            .set("C1", "$A$1 + A$2 * sum(B3:A1) + AA10 + x1")
+            .unwrap();
+        assert_eq!(
+            workbook.formula("C1").unwrap().to_string(),
+            "((($A$1 + (A$2 * sum(B3:A1))) + AA10) + x1)"
+        );
+        assert_eq!(
+            workbook
+                .dependencies("C1")
+                .iter()
+                .map(Cell::to_string)
+                .collect::<Vec<_>>(),
+            ["A1", "A2", "B2", "AA10"]
+        );
+        assert_eq!(workbook.dependents("A3"), [Cell::parse("C1").unwrap()]);
+        assert_eq!(Cell::parse("ZZ1").unwrap().column, 701);
+        assert!(Cell::parse("A0").is_none());
+        assert!(workbook.set("A1", "$x").is_err());
+        assert!(workbook.set("a1", "1").is_err());
+    }
+
+    #[test]
+    fn changes_recalculate_in_dependency_order() {
+        let mut workbook = Workbook::new();
+        workbook.set("A1", "B1 * 2").unwrap();
+        assert_eq!(number(&workbook, "A1"), 0.0);
+        workbook.set("B1", "3").unwrap();
+        workbook.set("C1", "sum(A1:B1) + $A$1").unwrap();
+        assert_eq!(number(&workbook, "A1"), 6.0);
+        assert_eq!(number(&workbook, "C1"), 15.0);
+
+        workbook.set("B1", "10").unwrap();
+        assert_eq!(number(&workbook, "C1"), 50.0);
+        assert_eq!(workbook.dependents("A1"), [Cell::parse("C1").unwrap()]);
+
+        workbook.clear("B1").unwrap();
+        assert_eq!(number(&workbook, "C1"), 0.0);
+        assert!(workbook.value("B1").is_none());
+    }
+
+    #[test]
+    fn ranges_leave_out_empty_cells() {
+        let mut workbook = Workbook::new();
+        workbook.set("A1", "2").unwrap();
+        workbook.set("A3", "4").unwrap();
+        workbook.set("B1", "avg(A1:A3)").unwrap();
+        workbook.set("B2", "count(A3:A1)").unwrap();
+        assert_eq!(number(&workbook, "B1"), 3.0);
+        assert_eq!(number(&workbook, "B2"), 2.0);
+    }
+
+    #[test]
+    fn large_ranges_only_visit_filled_cells() {
+        let mut workbook = Workbook::new();
+        workbook.set("B7", "2").unwrap();
+        workbook.set("ZZ200000", "3").unwrap();
+        workbook
+            .set("A1", "sum(B1:ZZ200000) + count($ZZ$2:B1048576)")
+            .unwrap();
+        assert_eq!(number(&workbook, "A1"), 7.0);
+        assert_eq!(
+            workbook
+                .set("C9", "sum(A1:ZZ200000)")
+                .unwrap_err()
+                .to_string(),
+            "Error(Eval): Circular reference: C9 -> A1 -> C9"
+        );
+    }
+
+    #[test]
+    fn empty_formulas_are_rejected() {
+        let mut workbook = Workbook::new();
+        for formula in ["", "  ", "\n"] {
+            assert_eq!(
+                workbook.set("A1", formula).unwrap_err().to_string(),
+                "Error(Eval): Empty formula"
+            );
+        }
+        assert!(workbook.value("A1").is_none());
+    }
+
+    #[test]
+    fn cycles_are_rejected_with_their_cells() {
+        let mut workbook = Workbook::new();
+        workbook.set("A1", "B1 + 1").unwrap();
+        workbook.set("B1", "C1 + 1").unwrap();
+        assert_eq!(
+            workbook.set("C1", "A1").unwrap_err().to_string(),
+            "Error(Eval): Circular reference: C1 -> A1 -> B1 -> C1"
+        );
+        assert!(workbook.value("C1").is_none());
+
+        workbook.set("C1", "5").unwrap();
+        assert_eq!(
+            workbook.set("C1", "sum(A1:A2)").unwrap_err().to_string(),
+            "Error(Eval): Circular reference: C1 -> A1 -> B1 -> C1"
+        );
+        assert_eq!(number(&workbook, "C1"), 5.0);
+        assert_eq!(number(&workbook, "A1"), 7.0);
+        assert_eq!(
+            workbook.set("D4", "D4 + 1").unwrap_err().to_string(),
+            "Error(Eval): Circular reference: D4 -> D4"
+        );
+        assert_eq!(
+            workbook.set("E1", "sum(E1:E3)").unwrap_err().to_string(),
+            "Error(Eval): Circular reference: E1 -> E1"
+        );
+        assert!(workbook.value("E1").is_none());
+    }
+
+    #[test]
+    fn errors_reach_dependent_cells() {
+        let mut workbook = Workbook::new();
+        workbook.set("A1", "1 / B1").unwrap();
+        workbook.set("A2", "A1 + 1").unwrap();
+        assert_eq!(
+            workbook
+                .value("A1")
+                .unwrap()
+                .as_ref()
+                .unwrap_err()
+                .to_string(),
+            "Division by zero in line 1 at column 3"
+        );
+        assert_eq!(
+            workbook
+                .value("A2")
+                .unwrap()
+                .as_ref()
+                .unwrap_err()
+                .to_string(),
+            "Cell A2 refers to A1, which has an error"
+        );
+
+        workbook.set("B1", "4").unwrap();
+        assert_eq!(number(&workbook, "A2"), 1.25);
+    }
+
+    #[test]
+    fn long_chains_recalculate_only_dependents() {
+        let mut workbook = Workbook::new();
+        workbook.set("A1", "1").unwrap();
+        for row in 2..=2000 {
+            workbook
+                .set(&format!("A{}", row), &format!("A{} + 1", row - 1))
+                .unwrap();
+        }
+        assert_eq!(number(&workbook, "A2000"), 2000.0);
+
+        workbook.set("A1000", "0").unwrap();
+        assert_eq!(number(&workbook, "A999"), 999.0);
+        assert_eq!(number(&workbook, "A2000"), 1000.0);
+
+        workbook.clear("A1000").unwrap();
+        assert_eq!(number(&workbook, "A2000"), 1000.0);
+        workbook.set("A1", "-998").unwrap();
+        assert_eq!(number(&workbook, "A999"), 0.0);
+        assert_eq!(number(&workbook, "A2000"), 1000.0);
+    }
+}